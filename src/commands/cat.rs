@@ -1,5 +1,5 @@
+use crate::commands::{ArgSpec, Args, Command, Output, SpecError, Status};
 use crate::context::Context;
-use std::io::{self, Write};
 
 /// `cat s1`
 ///
@@ -12,73 +12,85 @@ use std::io::{self, Write};
 /// - If the path is missing, or not exactly one argument, prints `FILE NOT FOUND`.
 /// - Resolves the path (symlinks are already dereferenced by `resolve_path`).
 /// - If target inode is not a regular file (`file_type != 0`), prints `FILE NOT FOUND`.
-/// - Reads the entire file content and prints it as UTF-8 (lossy for non-UTF8).
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Require exactly one argument
-    if argv.len() != 1 {
-        eprintln!("FILE NOT FOUND");
-        return;
-    }
-    let path = argv[0];
-
-    // Get filesystem
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+/// - Writes the content unchanged to the command output, adding a final newline only when the
+///   file doesn't end with one.
+pub struct Cat;
 
-    // Resolve path
-    let inode_id = match fs.resolve_path(path) {
-        Ok(id) => id,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+impl Command for Cat {
+    fn name(&self) -> &'static str {
+        "cat"
+    }
 
-    // Read inode
-    let inode = match fs.read_inode(inode_id) {
-        Ok(ino) => ino,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn synopsis(&self) -> &'static str {
+        "cat <file>"
+    }
 
-    // Must be regular file (file_type == 0)
-    if inode.file_type != 0 {
-        eprintln!("FILE NOT FOUND");
-        return;
+    fn help(&self) -> &'static str {
+        "Prints the content of a file."
     }
 
-    // Empty file -> print nothing (still success)
-    let size = inode.file_size as usize;
-    if size == 0 {
-        // Print empty line or nothing? Spec only says "OBSAH" => interpret as direct content.
-        // We'll just print nothing (like standard `cat` on empty file).
-        return;
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(1, SpecError::FileNotFound)
     }
 
-    // Stream content as lossy UTF-8 in chunks (avoid raw binary that UI může zahodit)
-    let mut remaining = size;
-    let mut offset: usize = 0;
-    const CHUNK: usize = 64 * 1024;
-    while remaining > 0 {
-        let to_read = CHUNK.min(remaining);
-        let mut chunk = vec![0u8; to_read];
-        if let Err(_) = fs.read_file_range(&inode, offset as u64, &mut chunk) {
-            eprintln!("FILE NOT FOUND");
-            return;
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let path = args[0];
+
+        // Get filesystem
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        // Resolve path
+        let inode_id = match fs.resolve_path(path) {
+            Ok(id) => id,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        // Read inode
+        let mut inode = match fs.read_inode(inode_id) {
+            Ok(ino) => ino,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        // Must be regular file (file_type == 0)
+        if inode.file_type != 0 {
+            return Err(SpecError::FileNotFound.into());
+        }
+
+        // Empty file -> print nothing (still success)
+        let size = inode.file_size as usize;
+        if size == 0 {
+            // Print empty line or nothing? Spec only says "OBSAH" => interpret as direct content.
+            // We'll just print nothing (like standard `cat` on empty file).
+            return Ok(());
+        }
+
+        // Stream the content unchanged in chunks, so `cat a > b` copies it byte for byte
+        let mut remaining = size;
+        let mut offset: usize = 0;
+        const CHUNK: usize = 64 * 1024;
+        let mut last = b'\n';
+        while remaining > 0 {
+            let to_read = CHUNK.min(remaining);
+            let mut chunk = vec![0u8; to_read];
+            if fs
+                .read_file_range(&mut inode, offset as u64, &mut chunk)
+                .is_err()
+            {
+                return Err(SpecError::FileNotFound.into());
+            }
+            output.out.write_all(&chunk)?;
+            last = chunk[to_read - 1];
+            remaining -= to_read;
+            offset += to_read;
+        }
+        // Konec souboru – přidej newline, pokud chybí (jinak by se prompt přilepil za obsah)
+        if last != b'\n' {
+            writeln!(output.out)?;
         }
-        let s = String::from_utf8_lossy(&chunk);
-        eprint!("{}", s);
-        remaining -= to_read;
-        offset += to_read;
+        output.out.flush()?;
+        Ok(())
     }
-    // Konec souboru – přidej newline
-    eprintln!();
-    let _ = std::io::stderr().flush();
 }
@@ -7,61 +7,64 @@
 //! - Expects exactly one argument (the target path).
 //! - If no argument is provided, attempts to change to root ("/").
 //! - Uses the FileSystem's `cd` method (which resolves symlinks, supports . and ..).
-//! - Prints:
-//!     OK              on success
-//!     PATH NOT FOUND  when filesystem not open, or target is invalid / not a directory
+//! - Prints "OK" on success, "PATH NOT FOUND" when filesystem not open, or target is
+//!   invalid / not a directory
 //!
 //! NOTE: To activate this command you must:
 //!   1. Add `pub mod cd;` to `commands/mod.rs`
-//!   2. Insert mapping: `map.insert("cd", crate::commands::cd::handle_argv as Handler);`
+//!   2. Register it in `Registry::new`: `registry.register(Box::new(crate::commands::cd::Cd));`
 
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
 use crate::context::Context;
 
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Acquire mutable FileSystem from context.
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
+pub struct Cd;
 
-    // Determine target path.
-    let target = match argv.len() {
-        0 => "/", // No args => go to root (optional design choice)
-        1 => argv[0],
-        _ => {
-            // Too many arguments: treat as invalid path.
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
+impl Command for Cd {
+    fn name(&self) -> &'static str {
+        "cd"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "cd [path]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Changes the current directory.\n\
+         Without a path goes to /."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::range(0, 1, SpecError::PathNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, _output: &mut Output) -> Status {
+        // Acquire mutable FileSystem from context.
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // Determine target path.
+        // No args => go to root (optional design choice)
+        let target = args.get(0).unwrap_or("/");
 
-    // Attempt to change directory.
-    match fs.cd(target) {
-        Ok(()) => (),
-        Err(_) => eprintln!("PATH NOT FOUND"),
+        // Attempt to change directory.
+        fs.cd(target)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::PathNotFound))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs::device::MemDevice;
     use crate::fs::filesystem::FileSystem;
     use crate::fs::io::write_superblock;
     use crate::fs::layout::Superblock;
-    use std::fs::{File, OpenOptions};
-    use std::io::{Seek, SeekFrom, Write};
 
-    // Helper to create a minimal mock FS file + superblock for testing cd root success.
-    fn mock_fs_file() -> File {
-        let mut f = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open("test.img")
-            .unwrap();
+    // Helper to create a minimal in-memory image + superblock for testing cd root success.
+    fn mock_fs_device() -> MemDevice {
+        let mut dev = MemDevice::new(8 * 1024);
         // Minimal superblock (root inode id = 0, counts = 0 so cd "/" succeeds trivially if resolver permits)
         let sb = Superblock {
             fs_size: 0,
@@ -73,29 +76,37 @@ mod tests {
             block_count: 0,
             inode_start: 0,
             inode_count: 1,
+            inode_bitmap_start: 0,
+            inode_bitmap_count: 0,
+            inode_version: 0,
+            features: 0,
+            journal_start: 0,
+            journal_count: 0,
         };
         // Write superblock block (requires BLOCK_SIZE zero padding)
-        write_superblock(&mut f, &sb).unwrap();
-        f.seek(SeekFrom::Start(0)).unwrap();
-        f
+        write_superblock(&mut dev, &sb).unwrap();
+        dev
     }
 
     #[test]
     fn cd_root_ok() {
-        let file = mock_fs_file();
-        let mut fs = FileSystem::open(file).unwrap();
+        let fs = FileSystem::open(mock_fs_device()).unwrap();
         let mut ctx = Context {
             fs: Some(fs),
             fs_path: None,
+            user: crate::fs::perm::Credentials::ROOT,
         };
-        handle_argv(&[], &mut ctx);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let _ = crate::commands::run(&Cd, &[], &mut ctx, &mut Output::new(&mut out, &mut err));
         // Expect OK printed (cannot assert stdout easily here without capturing; test checks no panic)
     }
 
     #[test]
     fn cd_arg_missing_fs() {
         let mut ctx = Context::new();
-        handle_argv(&["/"], &mut ctx);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut output = Output::new(&mut out, &mut err);
+        assert!(crate::commands::run(&Cd, &["/"], &mut ctx, &mut output).is_err());
         // Should print PATH NOT FOUND (filesystem not open)
     }
 }
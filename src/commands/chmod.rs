@@ -0,0 +1,56 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+
+/// chmod <mode> <path>
+/// Changes permission bits of a file, directory or symlink target.
+///
+/// MODE is octal, e.g. `644`, `0755`, `500`.
+///
+/// Outputs:
+///   OK
+///   INVALID MODE       (MODE is not an octal number in 0..=777)
+///   FILE NOT FOUND     (path does not exist / FS not opened / invalid arguments)
+///   PERMISSION DENIED  (current user is neither the owner nor root)
+///   NOT SUPPORTED      (image was formatted without permission support)
+pub struct Chmod;
+
+impl Command for Chmod {
+    fn name(&self) -> &'static str {
+        "chmod"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "chmod <mode> <path>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Changes the permission bits of a file.\n\
+         MODE is octal, e.g. 644 or 0755."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::FileNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let mode = match u16::from_str_radix(args[0], 8) {
+            Ok(m) if m <= 0o777 => m,
+            _ => return Err(SpecError::InvalidMode.into()),
+        };
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        let inode_id = match fs.resolve_path(args[1]) {
+            Ok(id) => id,
+            Err(e) => return Err(CommandError::from_fs(&e, SpecError::FileNotFound)),
+        };
+
+        fs.chmod(inode_id, mode)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::FileNotFound))?;
+        writeln!(output.err, "OK")?;
+        Ok(())
+    }
+}
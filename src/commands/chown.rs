@@ -0,0 +1,76 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+
+/// chown <uid>[:<gid>] <path>
+/// chown :<gid> <path>
+/// Changes the owner and/or group of a file, directory or symlink target. Root only.
+///
+/// Outputs:
+///   OK
+///   INVALID OWNER      (owner spec is not `uid`, `uid:gid` or `:gid` with numeric ids)
+///   FILE NOT FOUND     (path does not exist / FS not opened / invalid arguments)
+///   PERMISSION DENIED  (current user is not root)
+///   NOT SUPPORTED      (image was formatted without permission support)
+pub struct Chown;
+
+impl Command for Chown {
+    fn name(&self) -> &'static str {
+        "chown"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "chown <uid>[:<gid>] <path>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Changes the owner and/or group. Root only.\n\
+         With `:gid` only the group changes."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::FileNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let (uid, gid) = match parse_owner(args[0]) {
+            Some(v) => v,
+            None => return Err(SpecError::InvalidOwner.into()),
+        };
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        let inode_id = match fs.resolve_path(args[1]) {
+            Ok(id) => id,
+            Err(e) => return Err(CommandError::from_fs(&e, SpecError::FileNotFound)),
+        };
+
+        fs.chown(inode_id, uid, gid)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::FileNotFound))?;
+        writeln!(output.err, "OK")?;
+        Ok(())
+    }
+}
+
+/// Parse `uid`, `uid:gid` or `:gid` into optional numeric ids.
+fn parse_owner(spec: &str) -> Option<(Option<u32>, Option<u32>)> {
+    let (uid_str, gid_str) = match spec.split_once(':') {
+        Some((u, g)) => (u, Some(g)),
+        None => (spec, None),
+    };
+    let uid = if uid_str.is_empty() {
+        None
+    } else {
+        Some(uid_str.parse().ok()?)
+    };
+    let gid = match gid_str {
+        Some(g) => Some(g.parse().ok()?),
+        None => None,
+    };
+    if uid.is_none() && gid.is_none() {
+        return None;
+    }
+    Some((uid, gid))
+}
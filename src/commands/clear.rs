@@ -25,16 +25,38 @@
 //! - Reprint greeting header after clearing.
 //! - Add an option `clear -g` to force greeting reprint.
 //!
+use crate::commands::{ArgSpec, Args, Command, Output, Status};
 use crate::context::Context;
 
 /// Handler for the `clear` command.
-pub fn handle_argv(_argv: &[&str], _context: &mut Context) {
-    // ANSI escape sequence to clear screen & move cursor to 0,0
-    // \x1B == ESC
-    print!("\x1B[2J\x1B[H");
+pub struct Clear;
 
-    // Flush stdout to ensure the escape codes take effect immediately.
-    let _ = std::io::Write::flush(&mut std::io::stdout());
+impl Command for Clear {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "clear"
+    }
+
+    fn help(&self) -> &'static str {
+        "Clears the terminal screen."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::any()
+    }
+
+    fn run(&self, _args: &Args, _context: &mut Context, output: &mut Output) -> Status {
+        // ANSI escape sequence to clear screen & move cursor to 0,0
+        // \x1B == ESC
+        write!(output.out, "\x1B[2J\x1B[H")?;
+
+        // Flush the output to ensure the escape codes take effect immediately.
+        output.out.flush()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -45,13 +67,18 @@ mod tests {
     #[test]
     fn clear_runs_without_fs() {
         let mut ctx = Context::new();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut output = Output::new(&mut out, &mut err);
         // Should not panic.
-        handle_argv(&[], &mut ctx);
+        assert!(crate::commands::run(&Clear, &[], &mut ctx, &mut output).is_ok());
+        assert_eq!(out, b"\x1B[2J\x1B[H");
     }
 
     #[test]
     fn clear_ignores_extra_args() {
         let mut ctx = Context::new();
-        handle_argv(&["unexpected"], &mut ctx);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut output = Output::new(&mut out, &mut err);
+        assert!(crate::commands::run(&Clear, &["unexpected"], &mut ctx, &mut output).is_ok());
     }
 }
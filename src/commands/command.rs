@@ -0,0 +1,167 @@
+// Command interface: every shell command describes itself (name, synopsis, help, accepted
+// arguments) so the registry can validate input before running it and generate `help`.
+
+use super::{Output, SpecError, Status};
+use crate::context::Context;
+
+pub trait Command {
+    fn name(&self) -> &'static str;
+    // One-line usage, e.g. "ls [-l] [path]"
+    fn synopsis(&self) -> &'static str;
+    // Longer description; the first line doubles as the summary in the `help` listing
+    fn help(&self) -> &'static str;
+    fn args(&self) -> ArgSpec;
+    // Runs with arguments that already passed args(); writes the output and "OK" to `output`,
+    // a failure is returned for dispatch to report
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status;
+}
+
+// Single-letter switch, e.g. `-l`. Several can be combined (`-rf`).
+pub struct Flag {
+    pub name: char,
+    pub help: &'static str,
+}
+
+// What a command accepts. Arguments that don't fit fail with `error`, what the command's spec
+// reports for invalid input.
+pub struct ArgSpec {
+    pub flags: &'static [Flag],
+    pub min: usize,
+    // None = any number
+    pub max: Option<usize>,
+    pub error: SpecError,
+}
+
+impl ArgSpec {
+    pub const fn exactly(n: usize, error: SpecError) -> Self {
+        Self::range(n, n, error)
+    }
+
+    pub const fn range(min: usize, max: usize, error: SpecError) -> Self {
+        Self {
+            flags: &[],
+            min,
+            max: Some(max),
+            error,
+        }
+    }
+
+    // `min` or more arguments, all positional: for commands that parse options of their own
+    pub const fn at_least(min: usize, error: SpecError) -> Self {
+        Self {
+            flags: &[],
+            min,
+            max: None,
+            error,
+        }
+    }
+
+    // Arguments are accepted and ignored
+    pub const fn any() -> Self {
+        Self {
+            flags: &[],
+            min: 0,
+            max: None,
+            error: SpecError::Other,
+        }
+    }
+
+    pub const fn with_flags(self, flags: &'static [Flag]) -> Self {
+        Self { flags, ..self }
+    }
+
+    // Splits `argv` into flags and positional arguments. Only commands that declare flags
+    // treat "-x" as one; "--" ends the flags. None when something doesn't match the spec.
+    pub fn parse<'a>(&self, argv: &[&'a str]) -> Option<Args<'a>> {
+        let mut args = Args::default();
+        let mut only_positional = self.flags.is_empty();
+        for &arg in argv {
+            if only_positional || arg == "-" || !arg.starts_with('-') {
+                args.positional.push(arg);
+            } else if arg == "--" {
+                only_positional = true;
+            } else {
+                for c in arg[1..].chars() {
+                    if !self.flags.iter().any(|f| f.name == c) {
+                        return None;
+                    }
+                    if !args.flags.contains(&c) {
+                        args.flags.push(c);
+                    }
+                }
+            }
+        }
+        let n = args.positional.len();
+        if n < self.min || self.max.is_some_and(|max| n > max) {
+            return None;
+        }
+        Some(args)
+    }
+}
+
+// Parsed arguments; indexing gives the positional ones
+#[derive(Debug, Default)]
+pub struct Args<'a> {
+    flags: Vec<char>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    pub fn flag(&self, name: char) -> bool {
+        self.flags.contains(&name)
+    }
+
+    pub fn positional(&self) -> &[&'a str] {
+        &self.positional
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        self.positional.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positional.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positional.is_empty()
+    }
+}
+
+impl<'a> std::ops::Index<usize> for Args<'a> {
+    type Output = &'a str;
+
+    fn index(&self, index: usize) -> &&'a str {
+        &self.positional[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAGS: &[Flag] = &[
+        Flag {
+            name: 'r',
+            help: "",
+        },
+        Flag {
+            name: 'f',
+            help: "",
+        },
+    ];
+
+    #[test]
+    fn parses_flags_and_positionals() {
+        let spec = ArgSpec::exactly(2, SpecError::PathNotFound).with_flags(FLAGS);
+        let args = spec.parse(&["-rf", "a", "--", "-b"]).unwrap();
+        assert!(args.flag('r') && args.flag('f'));
+        assert_eq!(args.positional(), ["a", "-b"]);
+
+        assert!(spec.parse(&["-x", "a", "b"]).is_none());
+        assert!(spec.parse(&["-r", "a"]).is_none());
+        // Without declared flags a leading dash is just part of a name
+        let plain = ArgSpec::exactly(1, SpecError::PathNotFound);
+        assert_eq!(plain.parse(&["-r"]).unwrap()[0], "-r");
+    }
+}
@@ -1,4 +1,7 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Flag, Output, SpecError, Status};
 use crate::context::Context;
+use crate::fs::error::ElfsError;
+use crate::fs::filesystem::FileKind;
 
 /// Safe copy command: `cp <src> <dst>`
 ///
@@ -7,288 +10,163 @@ use crate::context::Context;
 ///   FILE NOT FOUND
 ///   PATH NOT FOUND
 ///   NAME TOO LONG
+///   PERMISSION DENIED  (existující cíl nelze přepsat)
+///
+/// Existující cílový soubor se přepisuje na místě (stejný inode, zkrácení přes truncate).
 ///
 /// Úprava: cílový path nesmí být adresář (ani ".", "..", ani cokoliv co rezolvuje na dir).
 /// Kopírování do adresáře se odmítá dle požadavku.
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Otevři FS
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
-
-    if argv.len() != 2 {
-        eprintln!("PATH NOT FOUND");
-        return;
-    }
-
-    let src_path = argv[0];
-    let dst_path = argv[1];
-
-    // Resolve source
-    let src_inode_id = match fs.resolve_path(src_path) {
-        Ok(id) => id,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
-    let src_inode = match fs.read_inode(src_inode_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
-    if src_inode.file_type != 0 {
-        eprintln!("FILE NOT FOUND");
-        return;
+///
+/// `cp -r` kopíruje adresář i s obsahem do cíle, který ještě neexistuje (jinak EXIST).
+/// Symlinky se kopírují jako symlinky; co se nepovede, se vypíše po řádcích.
+pub struct Cp;
+
+const FLAGS: &[Flag] = &[Flag {
+    name: 'r',
+    help: "copy a directory with its content to a new destination; symlinks stay symlinks",
+}];
+
+impl Command for Cp {
+    fn name(&self) -> &'static str {
+        "cp"
     }
-    debug_assert_eq!(src_inode.id, src_inode_id);
-    debug_assert_eq!(src_inode.file_type, 0);
 
-    // Načti obsah zdroje
-    let src_size = src_inode.file_size as usize;
-    let mut data = vec![0u8; src_size];
-    if src_size > 0 {
-        if let Err(_) = fs.read_file_range(&src_inode, 0, &mut data) {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
+    fn synopsis(&self) -> &'static str {
+        "cp [-r] <src> <dst>"
     }
 
-    // Odmítnout cíle, které jsou adresáře nebo speciální komponenty
-    if dst_path == "." || dst_path == ".." || dst_path.ends_with('/') {
-        eprintln!("PATH NOT FOUND");
-        return;
+    fn help(&self) -> &'static str {
+        "Copies a file.\n\
+         An existing destination file is overwritten in place; directories are not\n\
+         accepted as the destination. With -r a directory or symlink is copied whole to a\n\
+         destination that doesn't exist yet."
     }
 
-    // Pokud path už existuje a je adresář, odmítnout
-    if fs
-        .resolve_path(dst_path)
-        .map(|id| {
-            fs.read_inode(id)
-                .map(|ino| ino.file_type == 1)
-                .unwrap_or(false)
-        })
-        .unwrap_or(false)
-    {
-        eprintln!("PATH NOT FOUND");
-        return;
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound).with_flags(FLAGS)
     }
 
-    // Získat parent + jméno
-    let (dst_parent_id, dst_name) = match fs.resolve_parent_and_name(dst_path) {
-        Ok((pid, name)) => (pid, name),
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
-
-    // Validace jména
-    if dst_name.is_empty() || dst_name == "." || dst_name == ".." || dst_name.contains('/') {
-        eprintln!("PATH NOT FOUND");
-        return;
-    }
-    if dst_name.len() > crate::fs::consts::DIR_NAME_LEN {
-        eprintln!("NAME TOO LONG");
-        return;
-    }
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        // Otevři FS
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
 
-    // Načti parent inode
-    let mut dst_parent_inode = match fs.read_inode(dst_parent_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
+        let src_path = args[0];
+        let dst_path = args[1];
+
+        // -r: adresáře a symlinky celé přes copy_tree, běžný soubor jde cestou níže
+        if args.flag('r')
+            && fs
+                .lstat(src_path)
+                .is_ok_and(|meta| meta.kind != FileKind::File)
+        {
+            let report = fs
+                .copy_tree(src_path, dst_path)
+                .map_err(|e| copy_error(&e))?;
+            if !report.is_complete() {
+                return Err(CommandError::partial(&report, SpecError::PathNotFound));
+            }
+            writeln!(output.err, "OK")?;
+            return Ok(());
         }
-    };
-    if dst_parent_inode.file_type != 1 {
-        eprintln!("PATH NOT FOUND");
-        return;
-    }
-    debug_assert_eq!(dst_parent_inode.id, dst_parent_id);
-    debug_assert_eq!(dst_parent_inode.file_type, 1);
 
-    // Kolize? – existuje položka se jménem v parentu
-    let existing = match fs.dir_find(&dst_parent_inode, &dst_name) {
-        Ok(v) => v,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
+        // Zdroj musí být běžný soubor (symlinky se následují)
+        match fs.stat(src_path) {
+            Ok(meta) if meta.kind == FileKind::File => {}
+            _ => return Err(SpecError::FileNotFound.into()),
         }
-    };
 
-    // No-op: cp file file -> pokud jde o stejný inode
-    if let Some((_slot, entry)) = &existing {
-        if entry.inode_id == src_inode_id {
-            eprintln!("OK");
-            return;
+        // Odmítnout cíle, které jsou adresáře nebo speciální komponenty
+        if dst_path == "." || dst_path == ".." || dst_path.ends_with('/') {
+            return Err(SpecError::PathNotFound.into());
         }
-    }
 
-    // Overwrite?
-    if let Some((_slot, entry)) = existing {
-        let old_inode = match fs.read_inode(entry.inode_id) {
-            Ok(i) => i,
-            Err(_) => {
-                eprintln!("PATH NOT FOUND");
-                return;
-            }
-        };
-        if old_inode.file_type != 0 {
-            eprintln!("PATH NOT FOUND");
-            return;
+        // Pokud path už existuje a je adresář (nebo symlink), odmítnout
+        if fs
+            .lstat(dst_path)
+            .is_ok_and(|meta| meta.kind != FileKind::File)
+        {
+            return Err(SpecError::PathNotFound.into());
         }
 
-        // --- nový inode ---
-        let new_id = match fs.alloc_inode() {
-            Ok(Some(id)) => id,
-            Ok(None) => {
-                eprintln!("FILE NOT FOUND");
-                return;
-            }
-            Err(_) => {
-                eprintln!("PATH NOT FOUND");
-                return;
-            }
-        };
-        let mut new_inode = match fs.read_inode(new_id) {
-            Ok(i) => i,
-            Err(_) => {
-                let _ = fs.free_inode(new_id);
-                eprintln!("PATH NOT FOUND");
-                return;
-            }
+        // Získat parent + jméno
+        let dst_name = match fs.resolve_parent_and_name(dst_path) {
+            Ok((_, name)) => name,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
         };
-        new_inode.id = new_id;
-        new_inode.file_type = 0;
-        new_inode.link_count = 1;
-        new_inode.file_size = 0;
-        new_inode.single_directs = [0; 5];
-        new_inode.single_indirect = 0;
-        new_inode.double_indirect = 0;
 
-        if let Err(_) = fs.write_inode(new_id, &new_inode) {
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
+        // Validace jména
+        if dst_name.is_empty() || dst_name == "." || dst_name == ".." || dst_name.contains('/') {
+            return Err(SpecError::PathNotFound.into());
         }
-
-        // Zápis dat
-        if !data.is_empty() {
-            debug_assert_eq!(new_inode.file_type, 0);
-            if let Err(_) = fs.write_file_range(&mut new_inode, 0, &data) {
-                let _ = fs.free_inode(new_id);
-                eprintln!("FILE NOT FOUND");
-                return;
-            }
-            if let Err(_) = fs.write_inode(new_id, &new_inode) {
-                let _ = fs.free_inode(new_id);
-                eprintln!("PATH NOT FOUND");
-                return;
-            }
+        if dst_name.len() > fs.max_name_len() {
+            return Err(SpecError::NameTooLong.into());
         }
 
-        // Nahraď dirent
-        if let Err(_) = fs.dir_remove_entry(&mut dst_parent_inode, &dst_name) {
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-        if let Err(_) = fs.write_inode(dst_parent_id, &dst_parent_inode) {
-            let _ = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, old_inode.id);
-            let _ = fs.write_inode(dst_parent_id, &dst_parent_inode);
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
+        // Existující cíl se přepisuje na místě (stejný inode, takže
+        // hard linky na cíl vidí nový obsah). Díry zdroje zůstávají dírami i v kopii.
+        fs.copy_file(src_path, dst_path)
+            .map_err(|e| copy_error(&e))?;
 
-        if let Err(_) = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, new_id) {
-            let _ = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, old_inode.id);
-            let _ = fs.write_inode(dst_parent_id, &dst_parent_inode);
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-        if let Err(_) = fs.write_inode(dst_parent_id, &dst_parent_inode) {
-            let _ = fs.dir_remove_entry(&mut dst_parent_inode, &dst_name);
-            let _ = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, old_inode.id);
-            let _ = fs.write_inode(dst_parent_id, &dst_parent_inode);
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
+        writeln!(output.err, "OK")?;
 
-        let _ = fs.free_inode(old_inode.id);
-        eprintln!("OK");
-        return;
-    }
-
-    // --- cílový soubor neexistuje ---
-    let new_id = match fs.alloc_inode() {
-        Ok(Some(id)) => id,
-        Ok(None) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
-    let mut new_inode = match fs.read_inode(new_id) {
-        Ok(i) => i,
-        Err(_) => {
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
-    new_inode.id = new_id;
-    new_inode.file_type = 0;
-    new_inode.link_count = 1;
-    new_inode.file_size = 0;
-    new_inode.single_directs = [0; 5];
-    new_inode.single_indirect = 0;
-    new_inode.double_indirect = 0;
-
-    if let Err(_) = fs.write_inode(new_id, &new_inode) {
-        let _ = fs.free_inode(new_id);
-        eprintln!("PATH NOT FOUND");
-        return;
+        Ok(())
     }
+}
 
-    if !data.is_empty() {
-        debug_assert_eq!(new_inode.file_type, 0);
-        if let Err(_) = fs.write_file_range(&mut new_inode, 0, &data) {
-            let _ = fs.free_inode(new_id);
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-        if let Err(_) = fs.write_inode(new_id, &new_inode) {
-            let _ = fs.free_inode(new_id);
-            eprintln!("PATH NOT FOUND");
-            return;
+// Plné médium hlásí CANNOT CREATE FILE (jako slink), ostatní chyby cesty PATH NOT FOUND
+fn copy_error(e: &ElfsError) -> CommandError {
+    match e {
+        ElfsError::NoSpace | ElfsError::NoInodes | ElfsError::TransactionTooLarge => {
+            CommandError::caused_by(SpecError::CannotCreateFile, e)
         }
+        _ => CommandError::from_fs(e, SpecError::PathNotFound),
     }
+}
 
-    if let Err(_) = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, new_id) {
-        let _ = fs.free_inode(new_id);
-        eprintln!("PATH NOT FOUND");
-        return;
+#[cfg(test)]
+mod tests {
+    use crate::testing::Shell;
+
+    #[test]
+    fn copies_keep_holes_and_report_a_full_image() {
+        let mut shell = Shell::new();
+        shell.fs().write_file("/f", b"head").unwrap();
+        shell
+            .fs()
+            .write_file("/full", &vec![7; 3 * 1024 * 1024])
+            .unwrap();
+        for line in [
+            // Far larger than the image, nearly all of it a hole
+            "truncate /f 100MB",
+            "cp /f /g",
+            "cp -r /f /h",
+            "ln /g /link",
+            "cp /full /x",
+            "cp -r /full /y",
+        ] {
+            let _ = shell.run(line);
+        }
+        assert_eq!(
+            shell.err(),
+            "OK\n".repeat(4) + &"CANNOT CREATE FILE\n".repeat(2)
+        );
+
+        let fs = shell.fs();
+        let f = fs.stat("/f").unwrap();
+        for copy in ["/g", "/h"] {
+            let meta = fs.stat(copy).unwrap();
+            assert_eq!((meta.size, meta.blocks), (f.size, f.blocks));
+        }
+        assert!(fs.stat("/x").is_err() && fs.stat("/y").is_err());
+
+        // Overwriting goes through the same inode, hard links see the new content
+        fs.write_file("/small", b"small").unwrap();
+        shell.run("cp /small /g").unwrap();
+        let fs = shell.fs();
+        assert_eq!(fs.read_file("/link").unwrap(), b"small");
+        assert!(fs.check(false).unwrap().is_clean());
     }
-    if let Err(_) = fs.write_inode(dst_parent_id, &dst_parent_inode) {
-        let _ = fs.dir_remove_entry(&mut dst_parent_inode, &dst_name);
-        let _ = fs.write_inode(dst_parent_id, &dst_parent_inode);
-        let _ = fs.free_inode(new_id);
-        eprintln!("PATH NOT FOUND");
-        return;
-    }
-
-    eprintln!("OK");
 }
@@ -1,6 +1,28 @@
-use super::Context;
+use super::{ArgSpec, Args, Command, Context, Output, Status};
 use crate::utils::exit_codes::ExitCode;
 
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    std::process::exit(ExitCode::Success.into())
+pub struct Exit;
+
+impl Command for Exit {
+    fn name(&self) -> &'static str {
+        "exit"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "exit"
+    }
+
+    fn help(&self) -> &'static str {
+        "Saves the image and quits."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::any()
+    }
+
+    fn run(&self, _args: &Args, context: &mut Context, _output: &mut Output) -> Status {
+        // Write bitmaps back before the process ends
+        context.close_fs();
+        std::process::exit(ExitCode::Success.into())
+    }
 }
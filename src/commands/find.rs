@@ -0,0 +1,294 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::error::ElfsError;
+use crate::fs::filesystem::{FileSystem, TreeReport};
+use crate::fs::layout::Inode;
+use std::cmp::Ordering;
+
+/// find [-L] s1 [-name GLOB] [-type f|d|l] [-size [+|-]N[c|k|M|G]] [-maxdepth N]
+/// Prints the full path of every entry under s1 (s1 included) that matches all predicates.
+///
+/// - Walks the directory entries from s1 down; symlinks are reported as symlinks and not
+///   followed unless -L is given (then a link leading back up the tree is reported as a loop).
+/// - -name matches the entry name with `*`, `?` and `[...]`; -size compares the file size in
+///   bytes (k/M/G are multiples of 1024), +N means more than N, -N less than N.
+/// - Outputs: the matching paths | PATH NOT FOUND (s1 doesn't exist); directories that can't
+///   be read are listed after the rest.
+pub struct Find;
+
+impl Command for Find {
+    fn name(&self) -> &'static str {
+        "find"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "find [-L] <path> [-name GLOB] [-type f|d|l] [-size [+|-]N] [-maxdepth N]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Prints the paths below a directory that match.\n\
+         -name GLOB    entry name matches the pattern (*, ?, [abc], [a-z], [!x])\n\
+         -type f|d|l   regular file, directory or symlink\n\
+         -size [+|-]N  size more than / less than / exactly N bytes (suffix k, M or G)\n\
+         -maxdepth N   go at most N levels below the path\n\
+         -L            follow symlinks (by default they are listed, not entered)"
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::at_least(1, SpecError::PathNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let (start, query) = Query::parse(args.positional())?;
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // The start itself is followed only with -L, like any other symlink
+        let start_id = if query.follow {
+            fs.resolve_path(start)
+        } else {
+            fs.lstat(start).map(|meta| meta.inode)
+        };
+        let inode = match start_id.and_then(|id| fs.read_inode(id)) {
+            Ok(inode) => inode,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        let mut walk = Walk {
+            fs,
+            query: &query,
+            output,
+            ancestors: Vec::new(),
+            report: TreeReport::default(),
+        };
+        walk.visit(start, base_name(start), inode, 0)?;
+        if !walk.report.is_complete() {
+            return Err(CommandError::partial(&walk.report, SpecError::PathNotFound));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Query {
+    name: Option<String>,
+    file_type: Option<u8>,
+    size: Option<(Ordering, u64)>,
+    max_depth: Option<usize>,
+    follow: bool,
+}
+
+impl Query {
+    // Start path and predicates; anything unknown or without a valid value is an error
+    fn parse<'a>(args: &[&'a str]) -> Result<(&'a str, Query), CommandError> {
+        let mut query = Query::default();
+        let mut rest = args;
+        if rest.first() == Some(&"-L") {
+            query.follow = true;
+            rest = &rest[1..];
+        }
+        let Some((&start, mut rest)) = rest.split_first() else {
+            return Err(SpecError::PathNotFound.into());
+        };
+
+        while let Some((&option, tail)) = rest.split_first() {
+            let invalid = || CommandError::other(format!("Invalid find argument: {option}"));
+            let Some((&value, tail)) = tail.split_first() else {
+                return Err(invalid());
+            };
+            match option {
+                "-name" => query.name = Some(value.to_string()),
+                "-type" => {
+                    query.file_type = Some(match value {
+                        "f" => 0,
+                        "d" => 1,
+                        "l" => 2,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "-size" => query.size = Some(parse_size(value).ok_or_else(invalid)?),
+                "-maxdepth" => query.max_depth = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            }
+            rest = tail;
+        }
+        Ok((start, query))
+    }
+
+    fn matches(&self, name: &str, inode: &Inode) -> bool {
+        self.name.as_ref().is_none_or(|glob| glob_match(glob, name))
+            && self.file_type.is_none_or(|t| inode.file_type == t)
+            && self
+                .size
+                .is_none_or(|(order, size)| inode.file_size.cmp(&size) == order)
+    }
+}
+
+// Depth-first walk printing the matches to `output`
+struct Walk<'a, 'o> {
+    fs: &'a mut FileSystem,
+    query: &'a Query,
+    output: &'a mut Output<'o>,
+    // Directories on the way down from the start, to spot symlink loops with -L
+    ancestors: Vec<u32>,
+    report: TreeReport,
+}
+
+impl Walk<'_, '_> {
+    fn visit(&mut self, path: &str, name: &str, mut inode: Inode, depth: usize) -> Status {
+        if self.query.matches(name, &inode) {
+            writeln!(self.output.out, "{path}")?;
+        }
+        self.report.done += 1;
+        if inode.file_type != 1 || self.query.max_depth.is_some_and(|max| depth >= max) {
+            return Ok(());
+        }
+
+        let entries = match self.fs.read_dir(&mut inode) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.report.failed.push((path.to_string(), e));
+                return Ok(());
+            }
+        };
+        self.ancestors.push(inode.id);
+        for entry in entries {
+            let child_path = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+            match self.child(&child_path, entry.inode_id) {
+                Ok(child) => self.visit(&child_path, &entry.name, child, depth + 1)?,
+                Err(e) => self.report.failed.push((child_path, e)),
+            }
+        }
+        self.ancestors.pop();
+        Ok(())
+    }
+
+    // Inode an entry stands for: the entry's own, or with -L what a symlink leads to (a
+    // dangling symlink stays itself)
+    fn child(&mut self, path: &str, inode_id: u32) -> Result<Inode, ElfsError> {
+        let inode = self.fs.read_inode(inode_id)?;
+        if !self.query.follow || inode.file_type != 2 {
+            return Ok(inode);
+        }
+        let target = match self.fs.resolve_path(path) {
+            Ok(id) => self.fs.read_inode(id)?,
+            Err(ElfsError::NotFound) => return Ok(inode),
+            Err(e) => return Err(e),
+        };
+        if self.ancestors.contains(&target.id) {
+            return Err(ElfsError::SymlinkLoop);
+        }
+        Ok(target)
+    }
+}
+
+// "[+|-]N[c|k|M|G]": more than, less than or exactly N bytes
+fn parse_size(value: &str) -> Option<(Ordering, u64)> {
+    let (order, number) = match value.as_bytes().first()? {
+        b'+' => (Ordering::Greater, &value[1..]),
+        b'-' => (Ordering::Less, &value[1..]),
+        _ => (Ordering::Equal, value),
+    };
+    let (digits, unit) = match number.char_indices().last()? {
+        (i, 'c') => (&number[..i], 1),
+        (i, 'k') => (&number[..i], 1 << 10),
+        (i, 'M') => (&number[..i], 1 << 20),
+        (i, 'G') => (&number[..i], 1 << 30),
+        _ => (number, 1),
+    };
+    let n: u64 = digits.parse().ok()?;
+    Some((order, n.checked_mul(unit)?))
+}
+
+// Last path component as -name sees it ("/" for the root)
+fn base_name(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed.rsplit('/').next().unwrap_or(trimmed),
+    }
+}
+
+// Shell-style pattern: `*` any run, `?` one character, `[...]` a set with ranges and `!`/`^`
+// negation, `\` takes the next character literally
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // Where the last `*` was and how much of the name it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        let step = match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ni));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_class(&p[pi..], n[ni]),
+            Some('\\') if pi + 1 < p.len() => (p[pi + 1] == n[ni]).then_some(2),
+            Some(&c) => (c == n[ni]).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(len), _) => {
+                pi += len;
+                ni += 1;
+            }
+            // Mismatch: let the last `*` take one more character
+            (None, Some((star_pi, star_ni))) => {
+                star = Some((star_pi, star_ni + 1));
+                pi = star_pi + 1;
+                ni = star_ni + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// Length of the `[...]` class at the start of `p` when it matches `c`; an unclosed `[` is
+// matched literally
+fn match_class(p: &[char], c: char) -> Option<usize> {
+    let negate = matches!(p.get(1), Some('!' | '^'));
+    let mut i = if negate { 2 } else { 1 };
+    let mut found = false;
+    let mut first = true;
+    while i < p.len() && (p[i] != ']' || first) {
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            found |= p[i] <= c && c <= p[i + 2];
+            i += 3;
+        } else {
+            found |= p[i] == c;
+            i += 1;
+        }
+        first = false;
+    }
+    if i >= p.len() {
+        return (c == '[').then_some(1);
+    }
+    (found != negate).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(!glob_match("*.txt", "notes.txt.bak"));
+        assert!(glob_match("a?c*", "abc"));
+        assert!(glob_match("[a-c]x[!0-9]", "bxy"));
+        assert!(!glob_match("[a-c]x[!0-9]", "bx1"));
+        assert!(glob_match("*a*b", "xaxxab"));
+        assert!(glob_match("\\*", "*"));
+        assert!(!glob_match("\\*", "x"));
+        assert!(glob_match("[", "["));
+        assert_eq!(parse_size("+2k"), Some((Ordering::Greater, 2048)));
+        assert_eq!(parse_size("-10"), Some((Ordering::Less, 10)));
+        assert_eq!(parse_size("k"), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Flag, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::filesystem::{CheckReport, Problem};
+
+/// fsck [-n|-y]
+/// Checks the consistency of the opened image: walks the directory tree from the root and
+/// compares it with link counts, both bitmaps, file sizes and "."/".." entries.
+///
+///   -n  report only, the image is not modified (default)
+///   -y  repair everything that can be repaired; orphaned i-nodes are linked into /lost+found
+///
+/// Outputs:
+///   one line per problem found, then
+///   OK                    (image is consistent)
+///   ERRORS FOUND: <n>     (-n, problems were only reported)
+///   ERRORS FIXED: <n>     (-y, all problems were repaired)
+///   ERRORS LEFT: <n>      (-y, some problems could not be repaired)
+///   PATH NOT FOUND        (FS not opened / invalid arguments)
+pub struct Fsck;
+
+const FLAGS: &[Flag] = &[
+    Flag {
+        name: 'n',
+        help: "report only, the image is not modified (default)",
+    },
+    Flag {
+        name: 'y',
+        help: "repair everything that can be repaired; orphans go to /lost+found",
+    },
+];
+
+impl Command for Fsck {
+    fn name(&self) -> &'static str {
+        "fsck"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "fsck [-n|-y]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Checks the consistency of the image.\n\
+         Walks the directory tree from the root and compares it with link counts, both\n\
+         bitmaps, file sizes and \".\"/\"..\" entries."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(0, SpecError::PathNotFound).with_flags(FLAGS)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        // -n is the default; asking for both makes no sense
+        if args.flag('n') && args.flag('y') {
+            return Err(SpecError::PathNotFound.into());
+        }
+        let repair = args.flag('y');
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        let report: CheckReport = match fs.check(repair) {
+            Ok(r) => r,
+            Err(e) => {
+                writeln!(output.err, "fsck: {}", e)?;
+                return Err(SpecError::PathNotFound.into());
+            }
+        };
+
+        for problem in &report.problems {
+            writeln!(output.out, "{}", problem)?;
+        }
+
+        if report.is_clean() {
+            writeln!(output.err, "OK")?;
+        } else if !report.repaired {
+            return Err(CommandError::other(format!(
+                "ERRORS FOUND: {}",
+                report.problems.len()
+            )));
+        } else {
+            let left = unrepairable(&report.problems);
+            if left > 0 {
+                return Err(CommandError::other(format!("ERRORS LEFT: {}", left)));
+            }
+            writeln!(output.err, "ERRORS FIXED: {}", report.problems.len())?;
+        }
+        Ok(())
+    }
+}
+
+fn unrepairable(problems: &[Problem]) -> usize {
+    problems.iter().filter(|p| !p.is_repairable()).count()
+}
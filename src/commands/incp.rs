@@ -1,182 +1,346 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Flag, Output, SpecError, Status};
 use crate::context::Context;
-use crate::fs::consts::DIR_NAME_LEN;
-use crate::fs::layout::Inode;
+use crate::fs::error::ElfsError;
+use crate::fs::filesystem::{FileSystem, OpenOptions as FsOpenOptions, TreeReport};
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+use std::path::Path;
 
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Validate arguments: incp <host_src> <fs_dest>
-    if argv.len() != 2 {
-        eprintln!("PATH NOT FOUND");
-        return;
+/// incp s1 s2
+/// Copies a host file into the filesystem: OK | FILE NOT FOUND | PATH NOT FOUND | NAME TOO LONG
+///
+/// incp -r imports a whole host directory as s2, which must not exist yet (EXIST). Symlinks
+/// are imported as symlinks with their target text unchanged. Entries the image can't take
+/// (names longer than its limit, non-UTF-8 names, special files) are skipped and listed
+/// after the rest was imported.
+pub struct Incp;
+
+const FLAGS: &[Flag] = &[Flag {
+    name: 'r',
+    help: "import a host directory tree; entries that don't fit the image are reported",
+}];
+
+impl Command for Incp {
+    fn name(&self) -> &'static str {
+        "incp"
     }
-    let host_src = argv[0];
-    let fs_dest = argv[1];
-
-    // Open host source file
-    let mut src_file = match File::open(host_src) {
-        Ok(f) => f,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
-
-    // Get FS
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
-
-    // Resolve destination: support directory targets (".", existing dir, or path ending with '/')
-    // If destination is a directory, use the source file's basename as the new entry name.
-    let src_base = std::path::Path::new(host_src)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(host_src);
-
-    let (parent_id, name) = {
-        let treat_as_dir = fs_dest == "."
-            || fs_dest.ends_with('/')
-            || fs
-                .resolve_path(fs_dest)
-                .map(|id| {
-                    fs.read_inode(id)
-                        .map(|inode| inode.file_type == 1)
-                        .unwrap_or(false)
-                })
-                .unwrap_or(false);
-
-        if treat_as_dir {
-            // Determine the directory path (strip trailing '/' if present)
-            let dir_path = if fs_dest == "." {
-                "."
-            } else if fs_dest.ends_with('/') {
-                fs_dest.trim_end_matches('/')
-            } else {
-                fs_dest
-            };
-            let dir_id = match fs.resolve_path(dir_path) {
-                Ok(id) => id,
-                Err(_) => {
-                    eprintln!("PATH NOT FOUND");
-                    return;
-                }
+
+    fn synopsis(&self) -> &'static str {
+        "incp [-r] <host-file> <path>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Copies a host file into the filesystem.\n\
+         With -r a directory is copied with everything below it."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound).with_flags(FLAGS)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let host_src = args[0];
+        let fs_dest = args[1];
+
+        // -r: a host directory (or symlink) is mirrored whole, a regular file goes the way below
+        if args.flag('r') && std::fs::symlink_metadata(host_src).is_ok_and(|meta| !meta.is_file()) {
+            let fs = match context.fs_mut() {
+                Ok(fs) => fs,
+                Err(_) => return Err(SpecError::PathNotFound.into()),
             };
-            (dir_id, src_base.to_string())
-        } else {
-            match fs.resolve_parent_and_name(fs_dest) {
-                Ok(v) => v,
-                Err(_) => {
-                    eprintln!("PATH NOT FOUND");
-                    return;
-                }
+            let mut report = TreeReport::default();
+            import_tree(fs, Path::new(host_src), fs_dest, &mut report)
+                .map_err(|e| CommandError::from_fs(&e, SpecError::PathNotFound))?;
+            if !report.is_complete() {
+                return Err(CommandError::partial(&report, SpecError::PathNotFound));
             }
+            writeln!(output.err, "OK")?;
+            return Ok(());
         }
-    };
 
-    // Validate name: empty -> PATH NOT FOUND, too long -> NAME TOO LONG
+        // Open host source file
+        let mut src_file = match File::open(host_src) {
+            Ok(f) => f,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-    if name.is_empty() || name == "." || name == ".." {
-        eprintln!("PATH NOT FOUND");
+        // Get FS
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
 
-        return;
-    }
+        // Resolve destination: support directory targets (".", existing dir, or path ending with '/')
+        // If destination is a directory, use the source file's basename as the new entry name.
+        let src_base = std::path::Path::new(host_src)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(host_src);
 
-    if name.len() > DIR_NAME_LEN {
-        eprintln!("NAME TOO LONG");
+        let (parent_id, name) = {
+            let treat_as_dir = fs_dest == "."
+                || fs_dest.ends_with('/')
+                || fs
+                    .resolve_path(fs_dest)
+                    .map(|id| {
+                        fs.read_inode(id)
+                            .map(|inode| inode.file_type == 1)
+                            .unwrap_or(false)
+                    })
+                    .unwrap_or(false);
 
-        return;
-    }
+            if treat_as_dir {
+                // Determine the directory path (strip trailing '/' if present)
+                let dir_path = if fs_dest == "." {
+                    "."
+                } else if fs_dest.ends_with('/') {
+                    fs_dest.trim_end_matches('/')
+                } else {
+                    fs_dest
+                };
+                let dir_id = match fs.resolve_path(dir_path) {
+                    Ok(id) => id,
+                    Err(_) => return Err(SpecError::PathNotFound.into()),
+                };
+                (dir_id, src_base.to_string())
+            } else {
+                match fs.resolve_parent_and_name(fs_dest) {
+                    Ok(v) => v,
+                    Err(_) => return Err(SpecError::PathNotFound.into()),
+                }
+            }
+        };
 
-    // Load parent inode and check collision
-    let mut parent_inode = match fs.read_inode(parent_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
-    match fs.dir_find(&parent_inode, &name) {
-        Ok(Some(_)) => {
-            // Destination already exists -> per assignment for incp we don't overwrite
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-        Ok(None) => {}
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
+        // Validate name: empty -> PATH NOT FOUND, too long -> NAME TOO LONG
+
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(SpecError::PathNotFound.into());
         }
-    }
 
-    // Allocate a new inode for the destination file
-    let inode_id = match fs.alloc_inode() {
-        Ok(Some(id)) => id,
-        Ok(None) => {
-            // No free inode available
-            eprintln!("PATH NOT FOUND");
-            return;
+        if name.len() > fs.max_name_len() {
+            return Err(SpecError::NameTooLong.into());
         }
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
+
+        // Load parent inode and check collision
+        let mut parent_inode = match fs.read_inode(parent_id) {
+            Ok(i) => i,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+        match fs.dir_find(&parent_inode, &name) {
+            Ok(Some(_)) => {
+                // Destination already exists -> per assignment for incp we don't overwrite
+                return Err(SpecError::PathNotFound.into());
+            }
+            Ok(None) => {}
+            Err(_) => return Err(SpecError::PathNotFound.into()),
         }
-    };
 
-    // Initialize inode as a regular file
-    let mut inode = Inode {
-        file_size: 0,
-        id: inode_id,
+        // Allocate and initialize a new inode for the destination file (0 = file)
+        let mut inode = match fs.new_inode(0) {
+            Ok(Some(inode)) => inode,
+            Ok(None) => {
+                // No free inode available
+                return Err(SpecError::PathNotFound.into());
+            }
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+        let inode_id = inode.id;
+
+        // Copy data from host file into FS
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut offset: u64 = 0;
+        loop {
+            let n = match src_file.read(&mut buf) {
+                Ok(0) => break, // EOF
+                Ok(n) => n,
+                Err(_) => {
+                    let _ = fs.free_inode(inode_id);
+                    return Err(SpecError::FileNotFound.into());
+                }
+            };
 
-        single_directs: [0; 5],
+            if fs.write_file_range(&mut inode, offset, &buf[..n]).is_err() {
+                let _ = fs.free_inode(inode_id);
+                return Err(SpecError::PathNotFound.into());
+            }
+            offset += n as u64;
+        }
 
-        single_indirect: 0,
-        double_indirect: 0,
+        // Add directory entry in parent
+        if fs
+            .dir_add_entry(&mut parent_inode, &name, inode_id)
+            .is_err()
+        {
+            let _ = fs.free_inode(inode_id);
+            return Err(SpecError::PathNotFound.into());
+        }
 
-        file_type: 0, // 0 = file
-        link_count: 1,
-        _reserved: [0; 6],
-    };
+        writeln!(output.err, "OK")?;
 
-    if let Err(_) = fs.write_inode(inode_id, &inode) {
-        // Failed to initialize inode; free it
-        let _ = fs.free_inode(inode_id);
-        eprintln!("PATH NOT FOUND");
-        return;
+        Ok(())
     }
+}
 
-    // Copy data from host file into FS
-    let mut buf = vec![0u8; 64 * 1024];
-    let mut offset: u64 = 0;
-    loop {
-        let n = match src_file.read(&mut buf) {
-            Ok(0) => break, // EOF
-            Ok(n) => n,
-            Err(_) => {
-                let _ = fs.free_inode(inode_id);
-                eprintln!("FILE NOT FOUND");
-                return;
+// Imports `host` as `dest`, going into directories. Failures below `dest` are reported under
+// their host path and skipped; the error returned is for `host` itself.
+fn import_tree(
+    fs: &mut FileSystem,
+    host: &Path,
+    dest: &str,
+    report: &mut TreeReport,
+) -> Result<(), ElfsError> {
+    let file_type = std::fs::symlink_metadata(host)?.file_type();
+    if file_type.is_symlink() {
+        let target = std::fs::read_link(host)?;
+        fs.symlink(target.to_str().ok_or(ElfsError::InvalidName)?, dest)?;
+    } else if file_type.is_dir() {
+        fs.create_dir(dest)?;
+    } else if file_type.is_file() {
+        import_file(fs, host, dest)?;
+    } else {
+        return Err(ElfsError::Unsupported("special files"));
+    }
+    report.done += 1;
+    if !file_type.is_dir() {
+        return Ok(());
+    }
+
+    let mut entries =
+        match std::fs::read_dir(host).and_then(|dir| dir.collect::<io::Result<Vec<_>>>()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.failed.push((host.display().to_string(), e.into()));
+                return Ok(());
             }
         };
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        fs.checkpoint()?;
+        let path = entry.path();
+        let imported = match entry.file_name().to_str() {
+            None => Err(ElfsError::InvalidName),
+            Some(name) if name.len() > fs.max_name_len() => Err(ElfsError::NameTooLong),
+            Some(name) => import_tree(
+                fs,
+                &path,
+                &format!("{}/{}", dest.trim_end_matches('/'), name),
+                report,
+            ),
+        };
+        if let Err(e) = imported {
+            report.failed.push((path.display().to_string(), e));
+        }
+    }
+    Ok(())
+}
 
-        if let Err(_) = fs.write_file_range(&mut inode, offset, &buf[..n]) {
-            let _ = fs.free_inode(inode_id);
-            eprintln!("PATH NOT FOUND");
-            return;
+// Streams a host file into a new file at `dest`; nothing is left behind when it fails
+fn import_file(fs: &mut FileSystem, host: &Path, dest: &str) -> Result<(), ElfsError> {
+    let mut src = File::open(host)?;
+    let copied = fs
+        .open_file(dest, FsOpenOptions::new().write(true).create_new(true))
+        .and_then(|mut dst| Ok(io::copy(&mut src, &mut dst)?));
+    match copied {
+        Ok(_) => Ok(()),
+        Err(ElfsError::AlreadyExists) => Err(ElfsError::AlreadyExists),
+        Err(e) => {
+            let _ = fs.remove_file(dest);
+            Err(e)
         }
-        offset += n as u64;
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::consts::{FEATURE_DOT_ENTRIES, FEATURE_LONG_NAMES};
+    use crate::fs::device::FileDevice;
+    use crate::fs::io::{read_superblock, write_superblock};
+    use crate::testing::Shell;
 
-    // Add directory entry in parent
-    if let Err(_) = fs.dir_add_entry(&mut parent_inode, &name, inode_id) {
-        let _ = fs.free_inode(inode_id);
-        eprintln!("PATH NOT FOUND");
-        return;
+    // Image file with the fixed 16 B directory records of older versions (12 byte names), so
+    // host names can be longer than it takes
+    fn short_names_image(path: &Path) -> FileSystem {
+        let size = 4 * 1024 * 1024;
+        let open = || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map(FileDevice::new)
+                .unwrap()
+        };
+        let mut fs = FileSystem::format(open(), size).unwrap();
+        let mut root = fs.read_inode(fs.superblock().root_inode_id).unwrap();
+        fs.dir_remove_entry(&mut root, "..").unwrap();
+        fs.dir_remove_entry(&mut root, ".").unwrap();
+        fs.flush().unwrap();
+        drop(fs);
+
+        let mut dev = open();
+        let mut sb = read_superblock(&mut dev).unwrap();
+        sb.features &= !(FEATURE_LONG_NAMES | FEATURE_DOT_ENTRIES);
+        write_superblock(&mut dev, &sb).unwrap();
+        FileSystem::open(dev).unwrap()
     }
 
-    eprintln!("OK");
+    #[cfg(unix)]
+    #[test]
+    fn recursive_copy_round_trip() {
+        let host = std::env::temp_dir().join(format!("elfs-incp-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&host);
+        let src = host.join("src");
+        std::fs::create_dir_all(src.join("nested")).unwrap();
+        std::fs::write(src.join("nested/deep.txt"), b"deep").unwrap();
+        std::fs::write(src.join("file.txt"), vec![b'x'; 100_000]).unwrap();
+        std::os::unix::fs::symlink("nested/deep.txt", src.join("link")).unwrap();
+        let long = src.join("much-too-long-name.txt");
+        std::fs::write(&long, b"skipped").unwrap();
+
+        let mut fs = short_names_image(&host.join("image.elfs"));
+        let mut report = TreeReport::default();
+        import_tree(&mut fs, &src, "/tree", &mut report).unwrap();
+        assert_eq!(report.done, 5);
+        assert!(matches!(
+            report.failed.as_slice(),
+            [(path, ElfsError::NameTooLong)] if *path == long.display().to_string()
+        ));
+        assert!(fs.check(false).unwrap().is_clean());
+
+        let mut shell = Shell::with(fs);
+        shell
+            .run(&format!("outcp -r /tree {}", host.join("out").display()))
+            .unwrap();
+
+        let out = host.join("out");
+        assert_eq!(std::fs::read(out.join("nested/deep.txt")).unwrap(), b"deep");
+        assert_eq!(
+            std::fs::read(out.join("file.txt")).unwrap(),
+            std::fs::read(src.join("file.txt")).unwrap()
+        );
+        assert_eq!(
+            std::fs::read_link(out.join("link")).unwrap(),
+            Path::new("nested/deep.txt")
+        );
+        let mut names: Vec<_> = std::fs::read_dir(&out)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["file.txt", "link", "nested"]);
+
+        // Through the command the skipped entry shows up in the error output
+        assert!(
+            shell
+                .run(&format!("incp -r {} /again", src.display()))
+                .is_err()
+        );
+        std::fs::remove_dir_all(&host).unwrap();
+        let err = shell.err();
+        assert!(err.starts_with("OK\n"));
+        assert!(err.contains(&format!("{}: NAME TOO LONG", long.display())));
+        assert!(err.ends_with("INCOMPLETE: 1 failed, 5 done\n"));
+    }
 }
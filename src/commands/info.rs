@@ -1,88 +1,128 @@
+use crate::commands::{ArgSpec, Args, Command, Output, SpecError, Status};
 use crate::context::Context;
+use crate::fs::consts::BLOCK_SIZE;
+use crate::fs::perm::format_mode;
+use crate::utils::time::format_timestamp;
 
 /// info <path>
-
-/// Prints: "NAME – SIZE B – i-node INODE_ID – soft links: COUNT"
+///
+/// Prints: "NAME – SIZE B – i-node INODE_ID – hard links: COUNT – soft links: COUNT"
+/// Followed by the number of allocated blocks (data + indirect/extent metadata), mode/owner and created/modified/changed/accessed timestamps on images that store them.
 /// On error: "FILE NOT FOUND"
-
+///
+/// Hard link count = link_count uložený v inode (počet položek adresářů, které na inode ukazují).
 ///
 /// Soft link count = počet symlink inode (file_type == 2, link_count > 0),
 /// jejichž cílová cesta (uložená jako obsah symlinku) se aktuálně resolvuje
 /// na tento inode. Dangling symlinky nebo symlinky ukazující jinam se nepočítají.
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Exactly one argument expected.
-    if argv.len() != 1 {
-        eprintln!("FILE NOT FOUND");
+pub struct Info;
 
-        return;
+impl Command for Info {
+    fn name(&self) -> &'static str {
+        "info"
     }
 
-    let path = argv[0];
+    fn synopsis(&self) -> &'static str {
+        "info <path>"
+    }
 
-    // Access filesystem.
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
+    fn help(&self) -> &'static str {
+        "Shows details of a file or directory.\n\
+         Name, size, i-node, hard and soft link counts and allocated blocks, plus mode,\n\
+         owner and timestamps on images that store them."
+    }
 
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(1, SpecError::FileNotFound)
+    }
 
-            return;
-        }
-    };
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let path = args[0];
 
-    // Resolve target inode id.
-    let inode_id = match fs.resolve_path(path) {
-        Ok(id) => id,
+        // Access filesystem.
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
 
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-            return;
-        }
-    };
+        // Resolve target inode id.
+        let inode_id = match fs.resolve_path(path) {
+            Ok(id) => id,
 
-    // Read inode.
-    let inode = match fs.read_inode(inode_id) {
-        Ok(ino) => ino,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
+        // Read inode.
+        let inode = match fs.read_inode(inode_id) {
+            Ok(ino) => ino,
 
-            return;
-        }
-    };
-
-    let name = last_component_or_root(path);
-
-    // Scan all inodes for symlinks pointing to this inode.
-    let mut soft_links: u32 = 0;
-    let total = fs.inode_count();
-    for sid in 0..total {
-        // Read symlink inode
-        let sy_inode = match fs.read_inode(sid) {
-            Ok(i) => i,
-            Err(_) => continue,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
         };
-        if sy_inode.file_type != 2 || sy_inode.link_count == 0 {
-            continue;
-        }
-        // Read its stored target path
-        let target = match fs.readlink_target(sid) {
-            Ok(t) => t,
-            Err(_) => continue,
-        };
-        // Try resolving target; if resolves to queried inode, count it
-        if let Ok(tid) = fs.resolve_path(&target) {
-            if tid == inode_id {
+
+        let name = last_component_or_root(path);
+
+        // Scan all inodes for symlinks pointing to this inode.
+        let mut soft_links: u32 = 0;
+        let total = fs.inode_count();
+        for sid in 0..total {
+            // Read symlink inode
+            let sy_inode = match fs.read_inode(sid) {
+                Ok(i) => i,
+                Err(_) => continue,
+            };
+            if sy_inode.file_type != 2 || sy_inode.link_count == 0 {
+                continue;
+            }
+            // Read its stored target path
+            let target = match fs.readlink_target(sid) {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            // Try resolving target; if resolves to queried inode, count it
+            if let Ok(tid) = fs.resolve_path(&target)
+                && tid == inode_id
+            {
                 soft_links += 1;
             }
         }
-    }
 
-    eprintln!(
-        "{} – {} B – i-node {} – soft links: {}",
-        name, inode.file_size, inode_id, soft_links
-    );
+        writeln!(
+            output.out,
+            "{} – {} B – i-node {} – hard links: {} – soft links: {}",
+            name, inode.file_size, inode_id, inode.link_count, soft_links
+        )?;
+
+        // Space actually used on disk (sparse files hold fewer blocks than their size suggests)
+        if let Ok(blocks) = fs.allocated_blocks(&inode) {
+            writeln!(
+                output.out,
+                "  blocks:   {} allocated ({} B)",
+                blocks,
+                blocks * BLOCK_SIZE as u64
+            )?;
+        }
+
+        // Mode and ownership (inode version 3+)
+        if fs.superblock().has_permissions() {
+            writeln!(
+                output.out,
+                "  mode:     {} uid={} gid={}",
+                format_mode(inode.file_type, inode.mode),
+                inode.uid,
+                inode.gid
+            )?;
+        }
+
+        // Timestamps (only images with v2 inodes store them)
+        if fs.superblock().has_timestamps() {
+            writeln!(output.out, "  created:  {}", format_timestamp(inode.crtime))?;
+            writeln!(output.out, "  modified: {}", format_timestamp(inode.mtime))?;
+            writeln!(output.out, "  changed:  {}", format_timestamp(inode.ctime))?;
+            writeln!(output.out, "  accessed: {}", format_timestamp(inode.atime))?;
+        }
+        Ok(())
+    }
 }
 
 fn last_component_or_root(path: &str) -> String {
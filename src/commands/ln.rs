@@ -0,0 +1,106 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::error::ElfsError;
+
+/// ln s1 s2
+/// Creates a hard link s2 pointing to the same inode as s1.
+///
+/// Outputs:
+///   OK
+///   FILE NOT FOUND  (s1 does not exist or is a directory)
+///   PATH NOT FOUND  (parent of s2 does not exist / FS not opened / invalid arguments)
+///   NAME TOO LONG
+///   EXIST           (s2 already exists)
+///
+/// Behavior:
+/// - The last component of s1 is not dereferenced, so linking a symlink links the symlink itself.
+/// - Directories cannot be hard linked.
+/// - The inode's link_count is incremented; `rm` releases the data only when the last link goes away.
+pub struct Ln;
+
+impl Command for Ln {
+    fn name(&self) -> &'static str {
+        "ln"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "ln <target> <link>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Creates a hard link to a file or symlink."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let target_path = args[0];
+        let link_path = args[1];
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // Locate the target entry without following a trailing symlink
+        let (target_parent_id, target_name) = match fs.resolve_parent_and_name(target_path) {
+            Ok(v) => v,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+        let target_parent = match fs.read_inode(target_parent_id) {
+            Ok(i) => i,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+        let target_id = match fs.dir_find(&target_parent, &target_name) {
+            Ok(Some((_slot, entry))) => entry.inode_id,
+            _ => return Err(SpecError::FileNotFound.into()),
+        };
+        match fs.read_inode(target_id) {
+            Ok(i) if i.file_type != 1 => {}
+            _ => return Err(SpecError::FileNotFound.into()),
+        }
+
+        // Destination parent + name
+        let (parent_id, name) = match fs.resolve_parent_and_name(link_path) {
+            Ok(v) => v,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(SpecError::PathNotFound.into());
+        }
+        if name.len() > fs.max_name_len() {
+            return Err(SpecError::NameTooLong.into());
+        }
+
+        let mut parent_inode = match fs.read_inode(parent_id) {
+            Ok(i) => i,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+        if parent_inode.file_type != 1 {
+            return Err(SpecError::PathNotFound.into());
+        }
+
+        match fs.dir_find(&parent_inode, &name) {
+            Ok(Some(_)) => return Err(SpecError::Exist.into()),
+            Ok(None) => {}
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        }
+
+        if let Err(e) = fs.link(&mut parent_inode, &name, target_id) {
+            // Problems with the target (directory, freed inode) are FILE NOT FOUND
+            let fallback = match e {
+                ElfsError::IsADirectory | ElfsError::InvalidArgument(_) | ElfsError::NotFound => {
+                    SpecError::FileNotFound
+                }
+                _ => SpecError::PathNotFound,
+            };
+            return Err(CommandError::from_fs(&e, fallback));
+        }
+
+        writeln!(output.err, "OK")?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::commands::Registry;
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, Registry, SpecError, Status};
 use crate::context::Context;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -6,8 +6,9 @@ use std::io::{BufRead, BufReader};
 /// load s1
 /// Assignment spec: Executes commands from a host file.
 /// Outputs:
-///   OK             - if file read and all commands processed
-///   FILE NOT FOUND - if the file cannot be opened
+///   OK                              - if file read and all commands succeeded
+///   FILE NOT FOUND                  - if the file cannot be opened
+///   STOPPED AT LINE <n>: <command>  - command on line n failed (after its own error message)
 ///
 /// Behavior:
 /// - Reads the given host file line by line.
@@ -15,69 +16,99 @@ use std::io::{BufRead, BufReader};
 /// - Lines starting with '#' are treated as comments and skipped.
 /// - Whitespace-only lines are skipped.
 /// - Commands found inside the load file produce their normal outputs.
-/// - After finishing the file, prints "OK" (unless the file could not be opened).
+/// - The first failing command stops the script; commands before it stay done.
+/// - After finishing the file, prints "OK".
 ///
 /// Safety considerations:
 /// - No recursion guard (nested `load` calls can happen).
 ///   You can add a recursion depth guard later if needed.
 ///
 /// Usage example inside interactive session:
-///   > load script.txt
-///   (commands from script execute...)
-///   OK
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Validate number of arguments (exactly one expected: path to host file)
-    if argv.len() != 1 {
-        eprintln!("FILE NOT FOUND");
-        return;
+/// ```text
+/// > load script.txt
+/// (commands from script execute...)
+/// OK
+/// ```
+pub struct Load;
+
+impl Command for Load {
+    fn name(&self) -> &'static str {
+        "load"
     }
-    let host_path = argv[0];
 
-    // Try opening the host file
-    let file = match File::open(host_path) {
-        Ok(f) => f,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn synopsis(&self) -> &'static str {
+        "load <host-file>"
+    }
 
-    // Prepare command registry (same handlers as interactive mode)
-    let registry = Registry::new();
-    let reader = BufReader::new(file);
+    fn help(&self) -> &'static str {
+        "Runs the commands in a host file.\n\
+         One command per line, `#` starts a comment. Stops at the first command that fails."
+    }
 
-    // Process each line
-    for line_res in reader.lines() {
-        let line = match line_res {
-            Ok(l) => l,
-            Err(_) => {
-                // If a read error occurs mid-way, stop further processing.
-                // You could decide to print a different error; spec only defines FILE NOT FOUND vs OK.
-                eprintln!("FILE NOT FOUND");
-                return;
-            }
-        };
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(1, SpecError::FileNotFound)
+    }
 
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-        if trimmed.starts_with('#') {
-            continue; // comment
-        }
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let host_path = args[0];
 
-        // Split into command + args
-        let mut it = trimmed.split_whitespace();
-        let cmd = match it.next() {
-            Some(c) => c,
-            None => continue,
+        // Try opening the host file
+        let file = match File::open(host_path) {
+            Ok(f) => f,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
         };
-        let args: Vec<&str> = it.collect();
 
-        // Dispatch
-        registry.dispatch(cmd, &args, context);
+        // Prepare command registry (same handlers as interactive mode)
+        let registry = Registry::new();
+        let reader = BufReader::new(file);
+
+        // Process each line
+        for (index, line_res) in reader.lines().enumerate() {
+            let line = match line_res {
+                Ok(l) => l,
+                Err(_) => {
+                    // If a read error occurs mid-way, stop further processing.
+                    // You could decide to print a different error; spec only defines FILE NOT FOUND vs OK.
+                    return Err(SpecError::FileNotFound.into());
+                }
+            };
+
+            // Empty lines and comments are skipped by execute. The failed command has printed
+            // its error already; the rest of the file is not run.
+            if let Err(e) = registry.execute(&line, context, output) {
+                return Err(CommandError {
+                    kind: e.kind,
+                    message: format!("STOPPED AT LINE {}: {}", index + 1, line.trim()),
+                });
+            }
+        }
+
+        // Every command succeeded
+        writeln!(output.err, "OK")?;
+        Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::SpecError;
+    use crate::testing::Shell;
 
-    // Finished processing file
-    eprintln!("OK");
+    #[test]
+    fn stops_at_the_failing_line() {
+        let script = std::env::temp_dir().join(format!("elfs-load-{}.txt", std::process::id()));
+        std::fs::write(&script, "mkdir /a\ncd /missing\nmkdir /b\n").unwrap();
+        let mut shell = Shell::new();
+        let status = shell.run(&format!("load {}", script.display()));
+        std::fs::remove_file(&script).unwrap();
+
+        assert_eq!(status.unwrap_err().kind, SpecError::PathNotFound);
+        assert_eq!(
+            shell.err(),
+            "OK\nPATH NOT FOUND\nSTOPPED AT LINE 2: cd /missing\n"
+        );
+        let fs = shell.fs();
+        assert!(fs.resolve_path("/a").is_ok());
+        assert!(fs.resolve_path("/b").is_err());
+    }
 }
@@ -1,118 +1,146 @@
+use crate::commands::{ArgSpec, Args, Command, Flag, Output, SpecError, Status};
 use crate::context::Context;
-use crate::fs::consts::DIR_ENTRY_SIZE;
-use crate::fs::layout::DirectoryEntry;
+use crate::fs::layout::Inode;
+use crate::fs::perm::format_mode;
+use crate::utils::time::format_timestamp;
 
 /// Standalone `ls` command.
 ///
 /// Usage:
 ///   ls            -> lists current directory
 ///   ls <path>     -> lists specified directory or prints info for a single file
+///   ls -l [path]  -> long mode: adds mode, owner, size and modification time to every line
 ///
 /// Output (per assignment):
-///   - For directory entries:
-///       "FILE: <name>"
-///       "DIR: <name>"
-///     Additionally (symlink extension):
-///       "SYMLINK: <name>"
-///   - On invalid path:
-///       "PATH NOT FOUND"
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Obtain FileSystem instance.
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
+/// - For directory entries: "FILE: <name>", "DIR: <name>";
+///   additionally (symlink extension) "SYMLINK: <name>"
+/// - On invalid path: "PATH NOT FOUND"
+pub struct Ls;
 
-    // Determine target path: no args => current dir (".")
-    let target = match argv.len() {
-        0 => ".",
-        1 => argv[0],
-        _ => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
+const FLAGS: &[Flag] = &[Flag {
+    name: 'l',
+    help: "long listing: mode, owner, size and modification time",
+}];
 
-    // Resolve the path to an inode id.
-    let inode_id = match fs.resolve_path(target) {
-        Ok(id) => id,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
+impl Command for Ls {
+    fn name(&self) -> &'static str {
+        "ls"
+    }
 
-    // Read the inode to decide if we list a directory or print a single entry.
-    let inode = match fs.read_inode(inode_id) {
-        Ok(ino) => ino,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
+    fn synopsis(&self) -> &'static str {
+        "ls [-l] [path]"
+    }
 
-    // Helper to derive last component (basename) for non-directory targets
-    fn basename(path: &str) -> &str {
-        let trimmed = path.trim_end_matches('/');
-        let b = trimmed.rsplit('/').next().unwrap_or(trimmed);
-        if b.is_empty() { "/" } else { b }
+    fn help(&self) -> &'static str {
+        "Lists a directory or shows a single file.\n\
+         Without a path the current directory is listed."
     }
 
-    match inode.file_type {
-        // Directory – list entries
-        1 => {
-            let slots = (inode.file_size as usize) / DIR_ENTRY_SIZE;
-            let mut slot_buf = vec![0u8; DIR_ENTRY_SIZE];
+    fn args(&self) -> ArgSpec {
+        ArgSpec::range(0, 1, SpecError::PathNotFound).with_flags(FLAGS)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        // Obtain FileSystem instance.
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // -l switches to long mode
+        let long = args.flag('l');
+        let with_times = fs.superblock().has_timestamps();
+        let with_perms = fs.superblock().has_permissions();
+
+        // Determine target path: no args => current dir (".")
+        let target = args.get(0).unwrap_or(".");
+
+        // Resolve the path to an inode id.
+        let inode_id = match fs.resolve_path(target) {
+            Ok(id) => id,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
 
-            for i in 0..slots {
-                if let Err(_) =
-                    fs.read_file_range(&inode, (i * DIR_ENTRY_SIZE) as u64, &mut slot_buf)
-                {
-                    // If we fail to read a slot, skip it.
-                    continue;
+        // Read the inode to decide if we list a directory or print a single entry.
+        let mut inode = match fs.read_inode(inode_id) {
+            Ok(ino) => ino,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // Helper to derive last component (basename) for non-directory targets
+        fn basename(path: &str) -> &str {
+            let trimmed = path.trim_end_matches('/');
+            let b = trimmed.rsplit('/').next().unwrap_or(trimmed);
+            if b.is_empty() { "/" } else { b }
+        }
+
+        // One output line: "<KIND>: <name>" plus mode/owner, size and mtime in long mode
+        // (fields the image does not store are left out)
+        let line = |kind: &str, name: &str, ino: &Inode| -> String {
+            let mut out = format!("{}: {}", kind, name);
+            if long {
+                if with_perms {
+                    out.push_str(&format!(
+                        " – {} {}:{}",
+                        format_mode(ino.file_type, ino.mode),
+                        ino.uid,
+                        ino.gid
+                    ));
                 }
-                let entry = DirectoryEntry::deserialize(&slot_buf);
-                if entry.is_unused() {
-                    continue;
+                out.push_str(&format!(" – {} B", ino.file_size));
+                if with_times {
+                    out.push_str(&format!(" – {}", format_timestamp(ino.mtime)));
                 }
-                let name = entry.name_str();
-                // Read child's inode to determine type
-                match fs.read_inode(entry.inode_id) {
-                    Ok(child) => match child.file_type {
-                        0 => eprintln!("FILE: {}", name),
-                        1 => eprintln!("DIR: {}", name),
-                        2 => eprintln!("SYMLINK: {}", name),
-                        _ => eprintln!("FILE: {}", name), // Fallback as regular file
-                    },
-                    Err(_) => {
-                        // If child's inode can't be read, treat as not found (skip)
-                        continue;
+            }
+            out
+        };
+
+        match inode.file_type {
+            // Directory – list entries
+            1 => {
+                let entries = match fs.read_dir(&mut inode) {
+                    Ok(e) => e,
+                    Err(_) => return Err(SpecError::PathNotFound.into()),
+                };
+
+                for entry in entries {
+                    let name = entry.name_str();
+                    // Read child's inode to determine type
+                    match fs.read_inode(entry.inode_id) {
+                        Ok(child) => match child.file_type {
+                            0 => writeln!(output.out, "{}", line("FILE", name, &child))?,
+                            1 => writeln!(output.out, "{}", line("DIR", name, &child))?,
+                            2 => writeln!(output.out, "{}", line("SYMLINK", name, &child))?,
+                            _ => writeln!(output.out, "{}", line("FILE", name, &child))?, // Fallback as regular file
+                        },
+                        Err(_) => {
+                            // If child's inode can't be read, treat as not found (skip)
+                            continue;
+                        }
                     }
                 }
             }
+            // Regular file – print single line
+            0 => {
+                let name = if args.is_empty() {
+                    "."
+                } else {
+                    basename(target)
+                };
+                writeln!(output.out, "{}", line("FILE", name, &inode))?;
+            }
+            // Symlink – print single line (visible in listing; if ls is called on a symlink path directly)
+            2 => {
+                let name = if args.is_empty() {
+                    "."
+                } else {
+                    basename(target)
+                };
+                writeln!(output.out, "{}", line("SYMLINK", name, &inode))?;
+            }
+            // Unknown type – treat as not found
+            _ => return Err(SpecError::PathNotFound.into()),
         }
-        // Regular file – print single line
-        0 => {
-            let name = if argv.is_empty() {
-                "."
-            } else {
-                basename(target)
-            };
-            eprintln!("FILE: {}", name);
-        }
-        // Symlink – print single line (visible in listing; if ls is called on a symlink path directly)
-        2 => {
-            let name = if argv.is_empty() {
-                "."
-            } else {
-                basename(target)
-            };
-            eprintln!("SYMLINK: {}", name);
-        }
-        // Unknown type – treat as not found
-        _ => eprintln!("PATH NOT FOUND"),
+        Ok(())
     }
 }
@@ -1,71 +1,302 @@
 use crate::context::Context;
-use std::collections::HashMap;
+use crate::fs::filesystem::OpenOptions;
+use std::collections::BTreeMap;
+use std::io::Write;
 
-type Handler = fn(&[&str], &mut Context);
+mod command;
+mod output;
+mod status;
+
+pub use command::{ArgSpec, Args, Command, Flag};
+pub use output::Output;
+pub use status::{CommandError, SpecError, Status, set_verbose};
 
 pub struct Registry {
-    pub map: HashMap<&'static str, Handler>,
+    // Sorted, so `help` lists commands alphabetically
+    pub map: BTreeMap<&'static str, Box<dyn Command>>,
 }
 
 impl Registry {
     pub fn new() -> Self {
-        let mut map = HashMap::new();
-        map.insert("exit", crate::commands::exit::handle_argv as Handler);
-        map.insert("cd", crate::commands::cd::handle_argv as Handler);
-        map.insert("pwd", crate::commands::pwd::handle_argv as Handler);
-        map.insert("mkdir", crate::commands::mkdir::handle_argv as Handler);
-        map.insert("rmdir", crate::commands::rmdir::handle_argv as Handler);
-        map.insert("ls", crate::commands::ls::handle_argv as Handler);
-        map.insert("cat", crate::commands::cat::handle_argv as Handler);
-        map.insert("rm", crate::commands::rm::handle_argv as Handler);
-        map.insert("cp", crate::commands::cp::handle_argv as Handler);
-        map.insert("mv", crate::commands::mv::handle_argv as Handler);
-        map.insert("info", crate::commands::info::handle_argv as Handler);
-        map.insert("incp", crate::commands::incp::handle_argv as Handler);
-        map.insert("outcp", crate::commands::outcp::handle_argv as Handler);
-        map.insert("load", crate::commands::load::handle_argv as Handler);
-        map.insert("format", crate::commands::format::handle_argv as Handler);
-        map.insert("statfs", crate::commands::statfs::handle_argv as Handler);
-        map.insert("slink", crate::commands::slink::handle_argv as Handler);
-        map.insert("rmslink", crate::commands::rmslink::handle_argv as Handler);
-        map.insert("clear", crate::commands::clear::handle_argv as Handler);
-
-        Self { map }
+        let mut registry = Self {
+            map: BTreeMap::new(),
+        };
+        registry.register(Box::new(crate::commands::exit::Exit));
+        registry.register(Box::new(crate::commands::cd::Cd));
+        registry.register(Box::new(crate::commands::pwd::Pwd));
+        registry.register(Box::new(crate::commands::mkdir::Mkdir));
+        registry.register(Box::new(crate::commands::rmdir::Rmdir));
+        registry.register(Box::new(crate::commands::ls::Ls));
+        registry.register(Box::new(crate::commands::cat::Cat));
+        registry.register(Box::new(crate::commands::rm::Rm));
+        registry.register(Box::new(crate::commands::cp::Cp));
+        registry.register(Box::new(crate::commands::mv::Mv));
+        registry.register(Box::new(crate::commands::info::Info));
+        registry.register(Box::new(crate::commands::incp::Incp));
+        registry.register(Box::new(crate::commands::outcp::Outcp));
+        registry.register(Box::new(crate::commands::load::Load));
+        registry.register(Box::new(crate::commands::format::Format));
+        registry.register(Box::new(crate::commands::statfs::StatFs));
+        registry.register(Box::new(crate::commands::slink::Slink));
+        registry.register(Box::new(crate::commands::rmslink::RmSlink));
+        registry.register(Box::new(crate::commands::ln::Ln));
+        registry.register(Box::new(crate::commands::chmod::Chmod));
+        registry.register(Box::new(crate::commands::chown::Chown));
+        registry.register(Box::new(crate::commands::su::Su));
+        registry.register(Box::new(crate::commands::truncate::Truncate));
+        registry.register(Box::new(crate::commands::tar_in::TarIn));
+        registry.register(Box::new(crate::commands::tar_out::TarOut));
+        registry.register(Box::new(crate::commands::find::Find));
+        registry.register(Box::new(crate::commands::tree::Tree));
+        registry.register(Box::new(crate::commands::du::Du));
+        registry.register(Box::new(crate::commands::fsck::Fsck));
+        registry.register(Box::new(crate::commands::clear::Clear));
+
+        registry
+    }
+
+    pub fn register(&mut self, command: Box<dyn Command>) {
+        self.map.insert(command.name(), command);
+    }
+
+    // Runs one input line: the command name followed by whitespace separated arguments,
+    // optionally ending with `> path` or `>> path` to write the command's output into a file
+    // in the image instead. Empty lines and `#` comments are skipped.
+    pub fn execute(&self, line: &str, context: &mut Context, output: &mut Output) -> Status {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(());
+        }
+        let mut words: Vec<&str> = trimmed.split_whitespace().collect();
+        let redirect = match split_redirect(&mut words) {
+            Ok(r) => r,
+            Err(e) => {
+                writeln!(output.err, "{e}")?;
+                return Err(e);
+            }
+        };
+        let (name, args) = (words[0], &words[1..]);
+
+        let Some((path, append)) = redirect else {
+            return self.dispatch(name, args, context, output);
+        };
+        let mut captured = Vec::new();
+        let status = self.dispatch(
+            name,
+            args,
+            context,
+            &mut Output::new(&mut captured, output.err),
+        );
+        // Like a shell, the file gets whatever the command wrote, even when it failed
+        if let Err(e) = write_redirect(context, path, append, &captured) {
+            writeln!(output.err, "{e}")?;
+            return status.and(Err(e));
+        }
+        status
+    }
+
+    // Runs a command and writes the error it fails with to `output.err`
+    pub fn dispatch(
+        &self,
+        name: &str,
+        argv: &[&str],
+        context: &mut Context,
+        output: &mut Output,
+    ) -> Status {
+        let status = self.dispatch_quiet(name, argv, context, output);
+        if let Err(e) = &status {
+            writeln!(output.err, "{e}")?;
+        }
+        status
     }
 
-    pub fn dispatch(&self, name: &str, argv: &[&str], context: &mut Context) {
+    fn dispatch_quiet(
+        &self,
+        name: &str,
+        argv: &[&str],
+        context: &mut Context,
+        output: &mut Output,
+    ) -> Status {
+        // Only prints, no need for the transaction and cwd handling below
+        if name == "help" {
+            return self.help(argv, output);
+        }
+
         // Snapshot current working directory (if filesystem is open)
         let saved_cwd = match context.fs_mut() {
             Ok(fs) => Some(fs.pwd().to_string()),
             Err(_) => None,
         };
 
-        if let Some(handler) = self.map.get(name) {
-            handler(argv, context);
-        } else {
-            eprintln!("Unknown command: {name}");
+        // Every command runs as one transaction: its metadata changes reach the image through
+        // the journal together or not at all. `load` is left out, the script's commands get
+        // their own transactions as they are dispatched.
+        let transactional = name != "load";
+        if transactional && let Ok(fs) = context.fs_mut() {
+            fs.begin_transaction();
+        }
+
+        let mut status = match self.map.get(name) {
+            Some(command) => run(command.as_ref(), argv, context, output),
+            None => Err(CommandError::other(format!("Unknown command: {name}"))),
+        };
+
+        // The handler may have closed or replaced the filesystem (format); a new one has no
+        // transaction open
+        if transactional
+            && let Ok(fs) = context.fs_mut()
+            && fs.in_transaction()
+            && let Err(e) = fs.commit_transaction()
+            && status.is_ok()
+        {
+            status = Err(CommandError::other(format!("Journal commit failed: {e}")));
         }
 
         // Restore original working directory (best-effort), except for "cd"
+        if name != "cd"
+            && let Some(path) = saved_cwd
+            && let Ok(fs) = context.fs_mut()
+        {
+            let _ = fs.cd(&path);
+        }
+
+        status
+    }
 
-        if name != "cd" {
-            if let Some(path) = saved_cwd {
-                if let Ok(fs) = context.fs_mut() {
-                    let _ = fs.cd(&path);
+    // `help` lists the commands, `help <command>` describes one
+    fn help(&self, argv: &[&str], output: &mut Output) -> Status {
+        match argv {
+            [] => {
+                // A synopsis wider than the column gets a line of its own
+                let width = self
+                    .map
+                    .values()
+                    .map(|c| c.synopsis().len())
+                    .filter(|&len| len <= SYNOPSIS_COLUMN)
+                    .max()
+                    .unwrap_or(0)
+                    .max(HELP_SYNOPSIS.len());
+                writeln!(output.out, "Commands:")?;
+                let summaries = self
+                    .map
+                    .values()
+                    .map(|c| (c.synopsis(), c.help().lines().next().unwrap_or("")))
+                    .chain([(HELP_SYNOPSIS, "Lists commands or describes one.")]);
+                for (synopsis, summary) in summaries {
+                    if synopsis.len() > width {
+                        writeln!(output.out, "  {synopsis}")?;
+                        writeln!(output.out, "  {:width$}  {summary}", "")?;
+                    } else {
+                        writeln!(output.out, "  {synopsis:width$}  {summary}")?;
+                    }
                 }
+                Ok(())
             }
+            [name] => match self.map.get(name) {
+                Some(command) => {
+                    writeln!(output.out, "Usage: {}", command.synopsis())?;
+                    writeln!(output.out)?;
+                    writeln!(output.out, "{}", command.help())?;
+                    let flags = command.args().flags;
+                    if !flags.is_empty() {
+                        writeln!(output.out)?;
+                        for flag in flags {
+                            writeln!(output.out, "  -{}  {}", flag.name, flag.help)?;
+                        }
+                    }
+                    Ok(())
+                }
+                None => Err(CommandError::other(format!("Unknown command: {name}"))),
+            },
+            _ => Err(CommandError::other(format!("Usage: {HELP_SYNOPSIS}"))),
         }
     }
 }
 
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const HELP_SYNOPSIS: &str = "help [command]";
+// Widest synopsis `help` lines the summaries up after; the first line of a command's help()
+// is its summary and has to fit the rest of an 80 column line
+const SYNOPSIS_COLUMN: usize = 30;
+
+// Checks `argv` against what the command accepts and runs it; invalid arguments fail with the
+// command's error instead
+pub fn run(
+    command: &dyn Command,
+    argv: &[&str],
+    context: &mut Context,
+    output: &mut Output,
+) -> Status {
+    let spec = command.args();
+    match spec.parse(argv) {
+        Some(args) => command.run(&args, context, output),
+        None => Err(spec.error.into()),
+    }
+}
+
+// Takes a trailing `> path` / `>> path` (also written `>path`) off `words`. Returns the path
+// and whether to append.
+fn split_redirect<'a>(words: &mut Vec<&'a str>) -> Result<Option<(&'a str, bool)>, CommandError> {
+    let Some(at) = words.iter().position(|w| w.starts_with('>')) else {
+        return Ok(None);
+    };
+    let word = words[at];
+    let (append, attached) = match word.strip_prefix(">>") {
+        Some(rest) => (true, rest),
+        None => (false, &word[1..]),
+    };
+    let path = match (attached, &words[at + 1..]) {
+        ("", [path]) => *path,
+        (path, []) if !path.is_empty() => path,
+        _ => return Err(SpecError::PathNotFound.into()),
+    };
+    // Nothing to run
+    if at == 0 {
+        return Err(SpecError::PathNotFound.into());
+    }
+    words.truncate(at);
+    Ok(Some((path, append)))
+}
+
+// Stores captured output in the file at `path` (created when missing, symlinks followed)
+fn write_redirect(context: &mut Context, path: &str, append: bool, data: &[u8]) -> Status {
+    let fs = context
+        .fs_mut()
+        .map_err(|_| CommandError::from(SpecError::PathNotFound))?;
+    if !append {
+        return fs
+            .write_file(path, data)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::PathNotFound));
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(fs, path)
+        .map_err(|e| CommandError::from_fs(&e, SpecError::PathNotFound))?;
+    file.write_all(data)
+        .and_then(|_| file.flush())
+        .map_err(|e| CommandError::from_fs(&e.into(), SpecError::PathNotFound))
+}
+
 pub mod cat;
 pub mod cd;
+pub mod chmod;
+pub mod chown;
 pub mod clear;
 pub mod cp;
+pub mod du;
 pub mod exit;
+pub mod find;
 pub mod format;
+pub mod fsck;
 pub mod incp;
 pub mod info;
+pub mod ln;
 pub mod load;
 pub mod ls;
 pub mod mkdir;
@@ -77,3 +308,38 @@ pub mod rmdir;
 pub mod rmslink;
 pub mod slink;
 pub mod statfs;
+pub mod su;
+pub mod tar_in;
+pub mod tar_out;
+pub mod tree;
+pub mod truncate;
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::Shell;
+
+    #[test]
+    fn output_is_captured_and_redirected() {
+        let mut shell = Shell::new();
+        for line in ["mkdir /a", "ls / > /list", "pwd >>/list", "cat /list"] {
+            shell.run(line).unwrap();
+        }
+        assert!(shell.run("ls > ").is_err());
+        assert_eq!(shell.out(), "DIR: a\n/\n");
+        assert_eq!(shell.err(), "OK\nPATH NOT FOUND\n");
+    }
+
+    #[test]
+    fn help_summaries_fit_the_terminal() {
+        let mut shell = Shell::new();
+        shell.run("help").unwrap();
+        for line in shell.out().lines() {
+            assert!(line.chars().count() <= 80, "too wide: {line}");
+        }
+        // Every summary is a sentence of its own, not the start of a wrapped one
+        for command in shell.registry.map.values() {
+            let summary = command.help().lines().next().unwrap();
+            assert!(summary.ends_with('.'), "{}: {summary}", command.name());
+        }
+    }
+}
@@ -1,316 +1,352 @@
+use crate::commands::{ArgSpec, Args, Command, Output, SpecError, Status};
 use crate::context::Context;
+use crate::fs::filesystem::FileSystem;
 
 fn basename(path: &str) -> &str {
     path.rsplit('/').find(|s| !s.is_empty()).unwrap_or(path)
 }
 
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Validate args: need exactly two arguments: mv s1 s2
+pub struct Mv;
 
-    if argv.len() != 2 {
-        eprintln!("PATH NOT FOUND");
-
-        return;
+impl Command for Mv {
+    fn name(&self) -> &'static str {
+        "mv"
     }
 
-    let src_path = argv[0];
-
-    let dst_path = argv[1];
-
-    // Get FS
+    fn synopsis(&self) -> &'static str {
+        "mv <src> <dst>"
+    }
 
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
+    fn help(&self) -> &'static str {
+        "Moves or renames a file, symlink or directory."
+    }
 
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound)
+    }
 
-            return;
-        }
-    };
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let src_path = args[0];
 
-    // Resolve source inode and its parent + name
+        let dst_path = args[1];
 
-    let src_inode_id = match fs.resolve_path(src_path) {
-        Ok(id) => id,
+        // Get FS
 
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
 
-            return;
-        }
-    };
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
 
-    let (src_parent_id, src_name) = match fs.resolve_parent_and_name(src_path) {
-        Ok(v) => v,
+        // Resolve source inode and its parent + name
 
-        Err(_) => {
-            // Parent of source path not found – treat as PATH NOT FOUND
+        let src_inode_id = match fs.resolve_path(src_path) {
+            Ok(id) => id,
 
-            eprintln!("PATH NOT FOUND");
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-            return;
-        }
-    };
+        let (src_parent_id, src_name) = match fs.resolve_parent_and_name(src_path) {
+            Ok(v) => v,
 
-    let src_parent_inode = match fs.read_inode(src_parent_id) {
-        Ok(i) => i,
+            Err(_) => {
+                // Parent of source path not found – treat as PATH NOT FOUND
 
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
+                return Err(SpecError::PathNotFound.into());
+            }
+        };
 
-            return;
+        if fs.read_inode(src_parent_id).is_err() {
+            return Err(SpecError::PathNotFound.into());
         }
-    };
 
-    // Decide destination parent and final name
+        // Decide destination parent and final name
 
-    let (dst_parent_id, dst_name) = match fs.resolve_path(dst_path) {
-        // Destination exists
-        Ok(dst_existing_id) => {
-            // If destination resolves to the same inode as source, no-op
+        let (dst_parent_id, dst_name) = match fs.resolve_path(dst_path) {
+            // Destination exists
+            Ok(dst_existing_id) => {
+                // If destination resolves to the same inode as source, no-op
 
-            if dst_existing_id == src_inode_id {
-                eprintln!("OK");
+                if dst_existing_id == src_inode_id {
+                    writeln!(output.err, "OK")?;
 
-                return;
-            }
-
-            // If destination is a directory, move into it with basename(src)
+                    return Ok(());
+                }
 
-            match fs.read_inode(dst_existing_id) {
-                Ok(inode) => {
-                    if inode.file_type == 1 {
-                        // Dir: move into dir, name = basename(src)
+                // If destination is a directory, move into it with basename(src)
 
-                        let name = basename(src_path).to_string();
+                match fs.read_inode(dst_existing_id) {
+                    Ok(inode) => {
+                        if inode.file_type == 1 {
+                            // Dir: move into dir, name = basename(src)
 
-                        (dst_existing_id, name)
-                    } else {
-                        // Exists and not a directory -> treat as replace of this entry
+                            let name = basename(src_path).to_string();
 
-                        // Need its parent and exact last component as new name
+                            (dst_existing_id, name)
+                        } else {
+                            // Exists and not a directory -> treat as replace of this entry
 
-                        match fs.resolve_parent_and_name(dst_path) {
-                            Ok((p, name)) => (p, name),
+                            // Need its parent and exact last component as new name
 
-                            Err(_) => {
-                                eprintln!("PATH NOT FOUND");
+                            match fs.resolve_parent_and_name(dst_path) {
+                                Ok((p, name)) => (p, name),
 
-                                return;
+                                Err(_) => return Err(SpecError::PathNotFound.into()),
                             }
                         }
                     }
-                }
 
-                Err(_) => {
-                    eprintln!("PATH NOT FOUND");
-
-                    return;
+                    Err(_) => return Err(SpecError::PathNotFound.into()),
                 }
             }
-        }
-
-        // Destination does not exist – use its parent and last component as new name
-        Err(_) => match fs.resolve_parent_and_name(dst_path) {
-            Ok(v) => v,
 
-            Err(_) => {
-                eprintln!("PATH NOT FOUND");
+            // Destination does not exist – use its parent and last component as new name
+            Err(_) => match fs.resolve_parent_and_name(dst_path) {
+                Ok(v) => v,
 
-                return;
-            }
-        },
-    };
+                Err(_) => return Err(SpecError::PathNotFound.into()),
+            },
+        };
 
-    // Disallow special names as destination
-    if dst_name == "." || dst_name == ".." {
-        eprintln!("PATH NOT FOUND");
-        return;
-    }
+        // Disallow special names as destination
+        if dst_name == "." || dst_name == ".." {
+            return Err(SpecError::PathNotFound.into());
+        }
 
-    // Load destination parent inode
+        // Load destination parent inode
 
-    let mut dst_parent_inode = match fs.read_inode(dst_parent_id) {
-        Ok(i) => i,
+        let mut dst_parent_inode = match fs.read_inode(dst_parent_id) {
+            Ok(i) => i,
 
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
 
-            return;
+        if dst_parent_inode.file_type != 1 {
+            return Err(SpecError::PathNotFound.into());
         }
-    };
 
-    if dst_parent_inode.file_type != 1 {
-        eprintln!("PATH NOT FOUND");
+        // A directory cannot be moved into itself or any of its subdirectories
 
-        return;
-    }
+        let src_is_dir = match fs.read_inode(src_inode_id) {
+            Ok(i) => i.file_type == 1,
 
-    // No-op: moving to the same parent and same name
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-    if dst_parent_id == src_parent_id && dst_name == src_name {
-        eprintln!("OK");
+        if src_is_dir {
+            match fs.is_ancestor(src_inode_id, dst_parent_id) {
+                Ok(false) => {}
 
-        return;
-    }
+                _ => return Err(SpecError::PathNotFound.into()),
+            }
+        }
 
-    // If destination entry exists, handle collision (safe / rollback-aware)
+        // No-op: moving to the same parent and same name
 
-    match fs.dir_find(&dst_parent_inode, &dst_name) {
-        Ok(Some((_slot, existing_entry))) => {
-            // Case 1: Destination entry already points to source inode (self-target)
+        if dst_parent_id == src_parent_id && dst_name == src_name {
+            writeln!(output.err, "OK")?;
 
-            if existing_entry.inode_id == src_inode_id {
-                // If parent differs, we are effectively creating a second hard link then removing the old one;
+            return Ok(());
+        }
 
-                // current FS semantics: we only allow single link (link_count not leveraged here),
+        // If destination entry exists, handle collision (safe / rollback-aware)
 
-                // so treat it as moving across dirs: just remove old source entry.
+        match fs.dir_find(&dst_parent_inode, &dst_name) {
+            Ok(Some((_slot, existing_entry))) => {
+                // Case 1: Destination entry already points to source inode (self-target)
 
-                if src_parent_id != dst_parent_id {
-                    let mut src_parent_inode_mut = match fs.read_inode(src_parent_id) {
-                        Ok(i) => i,
+                if existing_entry.inode_id == src_inode_id {
+                    // Destination is already a hard link to the source inode: dropping the source
+                    // entry is the whole move, and the inode loses one link.
 
-                        Err(_) => {
-                            eprintln!("PATH NOT FOUND");
+                    if src_parent_id != dst_parent_id {
+                        let mut src_parent_inode_mut = match fs.read_inode(src_parent_id) {
+                            Ok(i) => i,
 
-                            return;
-                        }
-                    };
+                            Err(_) => return Err(SpecError::PathNotFound.into()),
+                        };
 
-                    if let Err(_) = fs.dir_remove_entry(&mut src_parent_inode_mut, &src_name) {
-                        eprintln!("PATH NOT FOUND");
+                        if fs
+                            .dir_remove_entry(&mut src_parent_inode_mut, &src_name)
+                            .is_err()
+                        {
+                            return Err(SpecError::PathNotFound.into());
+                        }
 
-                        return;
+                        if fs.free_inode(src_inode_id).is_err() {
+                            return Err(SpecError::PathNotFound.into());
+                        }
                     }
-                }
-
-                eprintln!("OK");
-
-                return;
-            }
 
-            // Load existing target inode
+                    writeln!(output.err, "OK")?;
 
-            let existing_inode = match fs.read_inode(existing_entry.inode_id) {
-                Ok(i) => i,
+                    return Ok(());
+                }
 
-                Err(_) => {
-                    eprintln!("PATH NOT FOUND");
+                // Load existing target inode
 
-                    return;
-                }
-            };
+                let existing_inode = match fs.read_inode(existing_entry.inode_id) {
+                    Ok(i) => i,
 
-            // Directories can be replaced only if empty
+                    Err(_) => return Err(SpecError::PathNotFound.into()),
+                };
 
-            if existing_inode.file_type == 1 {
-                match fs.dir_is_empty(&existing_inode) {
-                    Ok(true) => { /* allowed */ }
+                // Directories can be replaced only if empty
 
-                    _ => {
-                        eprintln!("PATH NOT FOUND");
+                if existing_inode.file_type == 1 {
+                    match fs.dir_is_empty(&existing_inode) {
+                        Ok(true) => { /* allowed */ }
 
-                        return;
+                        _ => return Err(SpecError::PathNotFound.into()),
                     }
                 }
-            }
 
-            // Stage removal: remove target directory entry but DO NOT free inode yet (to allow rollback)
+                // Stage removal: remove target directory entry but DO NOT free inode yet (to allow rollback)
 
-            if let Err(_) = fs.dir_remove_entry(&mut dst_parent_inode, &dst_name) {
-                eprintln!("PATH NOT FOUND");
+                if fs
+                    .dir_remove_entry(&mut dst_parent_inode, &dst_name)
+                    .is_err()
+                {
+                    return Err(SpecError::PathNotFound.into());
+                }
 
-                return;
-            }
+                // Try to insert new destination entry pointing to the source inode
 
-            // Try to insert new destination entry pointing to the source inode
+                if fs
+                    .dir_add_entry(&mut dst_parent_inode, &dst_name, src_inode_id)
+                    .is_err()
+                {
+                    // Rollback attempt: re-add old entry with original inode id
 
-            if let Err(_) = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, src_inode_id) {
-                // Rollback attempt: re-add old entry with original inode id
+                    let _ =
+                        fs.dir_add_entry(&mut dst_parent_inode, &dst_name, existing_entry.inode_id);
 
-                let _ = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, existing_entry.inode_id);
+                    return Err(SpecError::PathNotFound.into());
+                }
 
-                eprintln!("PATH NOT FOUND");
+                // New entry added; now remove source entry
 
-                return;
-            }
+                let mut src_parent_inode_mut = match fs.read_inode(src_parent_id) {
+                    Ok(i) => i,
 
-            // New entry added; now remove source entry
+                    Err(_) => return Err(SpecError::PathNotFound.into()),
+                };
 
-            let mut src_parent_inode_mut = match fs.read_inode(src_parent_id) {
-                Ok(i) => i,
+                if fs
+                    .dir_remove_entry(&mut src_parent_inode_mut, &src_name)
+                    .is_err()
+                {
+                    // Rollback attempt: remove newly added entry and restore old one
 
-                Err(_) => {
-                    eprintln!("PATH NOT FOUND");
+                    let _ = fs.dir_remove_entry(&mut dst_parent_inode, &dst_name);
 
-                    return;
-                }
-            };
+                    let _ =
+                        fs.dir_add_entry(&mut dst_parent_inode, &dst_name, existing_entry.inode_id);
 
-            if let Err(_) = fs.dir_remove_entry(&mut src_parent_inode_mut, &src_name) {
-                // Rollback attempt: remove newly added entry and restore old one
+                    return Err(SpecError::PathNotFound.into());
+                }
 
-                let _ = fs.dir_remove_entry(&mut dst_parent_inode, &dst_name);
+                // Finally drop the replaced entry's link (frees the inode if it was the last one)
 
-                let _ = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, existing_entry.inode_id);
+                if fs.free_inode(existing_inode.id).is_err() {
+                    // Non-fatal: we already performed the move, but report generic error per spec style
 
-                eprintln!("PATH NOT FOUND");
+                    return Err(SpecError::PathNotFound.into());
+                }
 
-                return;
-            }
+                if reparent(fs, src_inode_id, src_parent_id, dst_parent_id).is_err() {
+                    return Err(SpecError::PathNotFound.into());
+                }
 
-            // Finally free the replaced inode (file or empty dir)
+                writeln!(output.err, "OK")?;
 
-            if let Err(_) = fs.free_inode(existing_inode.id) {
-                // Non-fatal: we already performed the move, but report generic error per spec style
+                return Ok(());
+            }
 
-                eprintln!("PATH NOT FOUND");
+            Ok(None) => {
 
-                return;
+                // No collision; proceed normally
             }
-        }
-
-        Ok(None) => {
 
-            // No collision; proceed normally
+            Err(_) => return Err(SpecError::PathNotFound.into()),
         }
 
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
+        // Add new entry in destination parent that points to the source inode
 
-            return;
+        if fs
+            .dir_add_entry(&mut dst_parent_inode, &dst_name, src_inode_id)
+            .is_err()
+        {
+            return Err(SpecError::PathNotFound.into());
         }
-    }
-
-    // Add new entry in destination parent that points to the source inode
 
-    if let Err(_) = fs.dir_add_entry(&mut dst_parent_inode, &dst_name, src_inode_id) {
-        eprintln!("PATH NOT FOUND");
+        // Remove the source entry from its parent
 
-        return;
-    }
+        let mut src_parent_inode_mut = match fs.read_inode(src_parent_id) {
+            Ok(i) => i,
 
-    // Remove the source entry from its parent
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
 
-    let mut src_parent_inode_mut = match fs.read_inode(src_parent_id) {
-        Ok(i) => i,
+        if fs
+            .dir_remove_entry(&mut src_parent_inode_mut, &src_name)
+            .is_err()
+        {
+            // Best-effort rollback is complex; report failure
 
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
+            return Err(SpecError::PathNotFound.into());
+        }
 
-            return;
+        if reparent(fs, src_inode_id, src_parent_id, dst_parent_id).is_err() {
+            return Err(SpecError::PathNotFound.into());
         }
-    };
 
-    if let Err(_) = fs.dir_remove_entry(&mut src_parent_inode_mut, &src_name) {
-        // Best-effort rollback is complex; report failure
+        writeln!(output.err, "OK")?;
 
-        eprintln!("PATH NOT FOUND");
+        Ok(())
+    }
+}
 
-        return;
+// A directory moved to another parent must have its ".." entry pointed at the new parent
+fn reparent(
+    fs: &mut FileSystem,
+    inode_id: u32,
+    old_parent_id: u32,
+    new_parent_id: u32,
+) -> crate::fs::error::Result<()> {
+    if old_parent_id == new_parent_id || !fs.superblock().has_dot_entries() {
+        return Ok(());
     }
+    let mut inode = fs.read_inode(inode_id)?;
+    if inode.file_type != 1 {
+        return Ok(());
+    }
+    fs.dir_set_entry(&mut inode, "..", new_parent_id)
+}
 
-    eprintln!("OK");
+#[cfg(test)]
+mod tests {
+    use crate::testing::Shell;
+
+    #[test]
+    fn moving_a_directory_updates_dot_dot() {
+        let mut shell = Shell::new();
+        for line in ["mkdir /a", "mkdir /a/sub", "mkdir /b", "mv /a /b"] {
+            shell.run(line).unwrap();
+        }
+        // Into itself or below itself is refused
+        assert!(shell.run("mv /b /b/a/sub").is_err());
+        assert!(shell.run("mv /b/a /b/a").is_ok());
+        assert_eq!(shell.err(), "OK\n".repeat(4) + "PATH NOT FOUND\nOK\n");
+
+        let fs = shell.fs();
+        let b = fs.resolve_path("/b").unwrap();
+        let moved = fs.resolve_path("/b/a").unwrap();
+        assert_eq!(fs.parent_of(moved).unwrap(), b);
+        assert_eq!(fs.resolve_path("/b/a/sub/../..").unwrap(), b);
+        let sub = fs.resolve_path("/b/a/sub").unwrap();
+        assert_eq!(fs.path_of(sub).unwrap(), "/b/a/sub");
+        assert!(fs.resolve_path("/a").is_err());
+        assert!(fs.check(false).unwrap().is_clean());
+    }
 }
@@ -1,4 +1,7 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Flag, Output, SpecError, Status};
 use crate::context::Context;
+use crate::fs::error::ElfsError;
+use crate::fs::filesystem::{FileKind, FileSystem, OpenOptions as FsOpenOptions, TreeReport};
 use std::fs::{OpenOptions, create_dir_all};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
@@ -18,98 +21,168 @@ use std::path::{Path, PathBuf};
 /// - If creating parent directory of host destination fails -> PATH NOT FOUND
 /// - If opening or writing host destination fails -> PATH NOT FOUND
 /// - Success -> OK
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Validate arguments
-    if argv.len() != 2 {
-        eprintln!("PATH NOT FOUND");
-        return;
+///
+/// outcp -r exports a directory with everything in it as s2, which must not exist on the host
+/// yet (EXIST). Symlinks are written as host symlinks with the same target text; entries that
+/// fail are listed after the rest was exported.
+pub struct Outcp;
+
+const FLAGS: &[Flag] = &[Flag {
+    name: 'r',
+    help: "export a directory tree; symlinks stay symlinks",
+}];
+
+impl Command for Outcp {
+    fn name(&self) -> &'static str {
+        "outcp"
     }
-    let fs_src = argv[0];
-    let host_dest = argv[1];
-
-    // Acquire filesystem
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
-        }
-    };
 
-    // Resolve source file inside FS
-    let inode_id = match fs.resolve_path(fs_src) {
-        Ok(id) => id,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
+    fn synopsis(&self) -> &'static str {
+        "outcp [-r] <path> <host-file>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Copies a file from the filesystem to the host.\n\
+         With -r a directory is copied with everything below it."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound).with_flags(FLAGS)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let fs_src = args[0];
+        let host_dest = args[1];
+
+        // Acquire filesystem
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // -r: a directory (or symlink) is exported whole, a regular file goes the way below
+        if args.flag('r')
+            && fs
+                .lstat(fs_src)
+                .is_ok_and(|meta| meta.kind != FileKind::File)
+        {
+            let host_path = Path::new(host_dest);
+            if host_path.symlink_metadata().is_ok() {
+                return Err(SpecError::Exist.into());
+            }
+            if let Some(parent) = host_path.parent()
+                && !parent.as_os_str().is_empty()
+                && create_dir_all(parent).is_err()
+            {
+                return Err(SpecError::PathNotFound.into());
+            }
+            let mut report = TreeReport::default();
+            export_tree(fs, fs_src, host_path, &mut report)
+                .map_err(|e| CommandError::from_fs(&e, SpecError::PathNotFound))?;
+            if !report.is_complete() {
+                return Err(CommandError::partial(&report, SpecError::PathNotFound));
+            }
+            writeln!(output.err, "OK")?;
+            return Ok(());
         }
-    };
 
-    // Read inode and ensure it's a regular file (file_type == 0)
-    let inode = match fs.read_inode(inode_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
+        // Open source file inside FS (missing, directory or symlink to a non-file -> FILE NOT FOUND)
+        let mut src = match fs.open_file(fs_src, FsOpenOptions::new().read(true)) {
+            Ok(f) => f,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        // Ensure parent directory for host destination exists (if any)
+        let host_path = PathBuf::from(host_dest);
+        if let Some(parent) = host_path.parent()
+            && !parent.as_os_str().is_empty()
+            && !parent.exists()
+            && create_dir_all(parent).is_err()
+        {
+            return Err(SpecError::PathNotFound.into());
         }
-    };
-    if inode.file_type != 0 {
-        // Not a regular file (directory or symlink target resolved to non-file)
-        eprintln!("FILE NOT FOUND");
-        return;
-    }
 
-    // Prepare buffer for entire file
-    let size = inode.file_size as usize;
-    let mut buf = vec![0u8; size];
-    if size > 0 {
-        if let Err(_) = fs.read_file_range(&inode, 0, &mut buf) {
-            eprintln!("FILE NOT FOUND");
-            return;
+        // Create/truncate destination file on host FS and stream the content into it
+        match OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&host_path)
+        {
+            Ok(mut f) => {
+                if io::copy(&mut src, &mut f).is_err() {
+                    return Err(SpecError::PathNotFound.into());
+                }
+            }
+            Err(_) => return Err(SpecError::PathNotFound.into()),
         }
+
+        writeln!(output.err, "OK")?;
+
+        Ok(())
     }
+}
 
-    // Ensure parent directory for host destination exists (if any)
-    let host_path = PathBuf::from(host_dest);
-    if let Some(parent) = host_path.parent() {
-        if !parent.as_os_str().is_empty() && !parent.exists() {
-            if let Err(_) = create_dir_all(parent) {
-                eprintln!("PATH NOT FOUND");
-                return;
-            }
+// Exports `src` as `host`, going into directories. Failures below `host` are reported under
+// their path in the image and skipped; the error returned is for `src` itself.
+fn export_tree(
+    fs: &mut FileSystem,
+    src: &str,
+    host: &Path,
+    report: &mut TreeReport,
+) -> Result<(), ElfsError> {
+    let kind = fs.lstat(src)?.kind;
+    match kind {
+        FileKind::Symlink => host_symlink(&fs.read_link(src)?, host)?,
+        FileKind::Dir => std::fs::create_dir(host)?,
+        FileKind::File => {
+            let mut file = fs.open_file(src, FsOpenOptions::new().read(true))?;
+            let mut dst = OpenOptions::new().write(true).create_new(true).open(host)?;
+            io::copy(&mut file, &mut dst)?;
         }
     }
+    report.done += 1;
+    if kind != FileKind::Dir {
+        return Ok(());
+    }
 
-    // Create/truncate destination file on host FS
-    match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&host_path)
-    {
-        Ok(mut f) => {
-            if let Err(_) = f.write_all(&buf) {
-                eprintln!("PATH NOT FOUND");
-                return;
-            }
+    let entries = match fs.list_dir(src) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.failed.push((src.to_string(), e));
+            return Ok(());
         }
-        Err(_) => {
-            eprintln!("PATH NOT FOUND");
-            return;
+    };
+    for entry in entries {
+        let path = format!("{}/{}", src.trim_end_matches('/'), entry.name);
+        if let Err(e) = export_tree(fs, &path, &host.join(&entry.name), report) {
+            report.failed.push((path, e));
         }
     }
+    Ok(())
+}
 
-    eprintln!("OK");
+#[cfg(unix)]
+fn host_symlink(target: &str, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn host_symlink(_target: &str, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are not supported on this host",
+    ))
 }
 
 /// Optional helper (not used directly by handler): attempt to write a slice to host path.
-/// Could be used if later you refactor outcp to stream instead of buffering whole file.
 #[allow(dead_code)]
 fn write_host_file(path: &Path, data: &[u8]) -> io::Result<()> {
-    if let Some(parent) = path.parent() {
-        if !parent.as_os_str().is_empty() && !parent.exists() {
-            create_dir_all(parent)?;
-        }
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        create_dir_all(parent)?;
     }
     let mut f = OpenOptions::new()
         .write(true)
@@ -0,0 +1,17 @@
+// Where commands write. `out` gets what a command produces (listings, file content, reports)
+// and is what `>` redirects; `err` gets status lines: "OK" and error messages.
+//
+// The shell passes stdout and stderr; tests and embedding callers can pass buffers instead.
+
+use std::io::Write;
+
+pub struct Output<'a> {
+    pub out: &'a mut dyn Write,
+    pub err: &'a mut dyn Write,
+}
+
+impl<'a> Output<'a> {
+    pub fn new(out: &'a mut dyn Write, err: &'a mut dyn Write) -> Self {
+        Self { out, err }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Flag, Output, SpecError, Status};
 use crate::context::Context;
 
 /// Standalone `rm` command handler.
@@ -6,80 +7,60 @@ use crate::context::Context;
 /// - rm s1 -> OK | FILE NOT FOUND
 ///
 /// Chování:
-/// - Cíl musí být běžný soubor (ne adresář).
+/// - Cíl musí být běžný soubor nebo symlink (ne adresář); symlink se maže sám, ne jeho cíl.
 /// - Při chybě (FS neotevřen, neexistuje, je to adresář, chyba při odstranění)
 ///   vytiskne "FILE NOT FOUND".
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Ověření argumentů
-    if argv.len() != 1 {
-        eprintln!("FILE NOT FOUND");
-        return;
+/// - S `-r` maže i adresář s celým obsahem; symlinky uvnitř se mažou jako odkazy, jejich cíl
+///   zůstává. Co nejde smazat, se vypíše po řádcích a adresáře nad tím zůstanou.
+pub struct Rm;
+
+const FLAGS: &[Flag] = &[Flag {
+    name: 'r',
+    help: "remove directories with everything in them (symlinks are not followed)",
+}];
+
+impl Command for Rm {
+    fn name(&self) -> &'static str {
+        "rm"
     }
-    let path = argv[0];
 
-    // Získání FS instance
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn synopsis(&self) -> &'static str {
+        "rm [-r] <path>"
+    }
 
-    // Najít cílový inode
-    let inode_id = match fs.resolve_path(path) {
-        Ok(id) => id,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn help(&self) -> &'static str {
+        "Removes a file or symlink (not its target).\n\
+         With -r a directory is removed with everything below it."
+    }
 
-    // Načíst inode a ověřit, že není adresář
-    let inode = match fs.read_inode(inode_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
-    if inode.file_type == 1 {
-        // Je to adresář -> rm neumí mazat adresáře
-        eprintln!("FILE NOT FOUND");
-        return;
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(1, SpecError::FileNotFound).with_flags(FLAGS)
     }
 
-    // Získat rodiče a jméno poslední komponenty
-    let (parent_id, name) = match fs.resolve_parent_and_name(path) {
-        Ok(v) => v,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let path = args[0];
 
-    // Načíst inode rodiče
-    let mut parent_inode = match fs.read_inode(parent_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+        // Získání FS instance
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-    // Odebrat záznam z adresáře rodiče
-    if let Err(_) = fs.dir_remove_entry(&mut parent_inode, &name) {
-        eprintln!("FILE NOT FOUND");
-        return;
-    }
+        if args.flag('r') {
+            let report = fs
+                .remove_tree(path)
+                .map_err(|e| CommandError::from_fs(&e, SpecError::FileNotFound))?;
+            if !report.is_complete() {
+                return Err(CommandError::partial(&report, SpecError::FileNotFound));
+            }
+            writeln!(output.err, "OK")?;
+            return Ok(());
+        }
 
-    // Uvolnit inode cílového souboru (uvolní bloky a označí inode jako volný)
-    if let Err(_) = fs.free_inode(inode_id) {
-        // V extrémním případě by bylo vhodné pokusit se zvrátit odstranění záznamu,
-        // ale v rámci zadání stačí signalizovat chybu.
-        eprintln!("FILE NOT FOUND");
-        return;
+        // Odebrat záznam a jeden link; bloky se uvolní až s posledním hard linkem
+        fs.remove_file(path)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::FileNotFound))?;
+        writeln!(output.err, "OK")?;
+        Ok(())
     }
-
-    eprintln!("OK");
 }
@@ -1,4 +1,6 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
 use crate::context::Context;
+use crate::fs::filesystem::FileKind;
 
 /// rmslink <path>
 /// Odstraní symbolický link (symlink) na dané cestě BEZ dereference cíle.
@@ -8,89 +10,49 @@ use crate::context::Context;
 ///   FILE NOT FOUND  (neexistuje, není symlink, rodič neexistuje / není dir, FS není otevřený)
 ///
 /// Chování:
-/// - Najde rodičovský adresář a jméno poslední komponenty (nepoužívá resolve_path, aby nedošlo k dereferenci).
-/// - V adresáři vyhledá položku se jménem.
-/// - Ověří, že inode má file_type == 2 (symlink).
-/// - Odebere položku z adresáře a uvolní inode.
+/// - Zjistí typ poslední komponenty přes lstat (bez dereference symlinku).
+/// - Ověří, že jde o symlink.
+/// - Odebere položku z adresáře a uvolní inode (remove_file).
 /// - Pokud jméno odkazuje na běžný soubor nebo adresář, nic nemaže (FILE NOT FOUND).
-pub fn handle_argv(argv: &[&str], context: &mut Context) {
-    // Ověření argumentů
-    if argv.len() != 1 {
-        eprintln!("FILE NOT FOUND");
-        return;
-    }
-    let path = argv[0];
+pub struct RmSlink;
 
-    // Získání FS instance
-    let fs = match context.fs_mut() {
-        Ok(fs) => fs,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+impl Command for RmSlink {
+    fn name(&self) -> &'static str {
+        "rmslink"
+    }
 
-    // Získat parent + name (bez dereference symlinku na konci)
-    let (parent_id, name) = match fs.resolve_parent_and_name(path) {
-        Ok(v) => v,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
-    if name.is_empty() {
-        eprintln!("FILE NOT FOUND");
-        return;
+    fn synopsis(&self) -> &'static str {
+        "rmslink <path>"
     }
 
-    // Načíst parent inode
-    let mut parent_inode = match fs.read_inode(parent_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
-    if parent_inode.file_type != 1 {
-        eprintln!("FILE NOT FOUND");
-        return;
+    fn help(&self) -> &'static str {
+        "Removes a symlink without touching its target."
     }
 
-    // Najít položku v adresáři
-    let entry = match fs.dir_find(&parent_inode, &name) {
-        Ok(Some((_slot, e))) => e,
-        _ => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(1, SpecError::FileNotFound)
+    }
 
-    // Načíst inode cíle
-    let target_inode = match fs.read_inode(entry.inode_id) {
-        Ok(i) => i,
-        Err(_) => {
-            eprintln!("FILE NOT FOUND");
-            return;
-        }
-    };
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let path = args[0];
 
-    // Musí být symlink
-    if target_inode.file_type != 2 {
-        eprintln!("FILE NOT FOUND");
-        return;
-    }
+        // Získání FS instance
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
 
-    // Odebrat položku z adresáře
-    if let Err(_) = fs.dir_remove_entry(&mut parent_inode, &name) {
-        eprintln!("FILE NOT FOUND");
-        return;
-    }
+        // Musí jít o symlink samotný (lstat nedereferencuje poslední komponentu)
+        match fs.lstat(path) {
+            Ok(meta) if meta.kind == FileKind::Symlink => {}
+            Ok(_) => return Err(SpecError::FileNotFound.into()),
+            Err(e) => return Err(CommandError::from_fs(&e, SpecError::FileNotFound)),
+        }
 
-    // Uvolnit inode symlinku
-    if let Err(_) = fs.free_inode(entry.inode_id) {
-        eprintln!("FILE NOT FOUND");
-        return;
+        // Odebrat položku z adresáře a uvolnit inode symlinku
+        fs.remove_file(path)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::FileNotFound))?;
+        writeln!(output.err, "OK")?;
+        Ok(())
     }
-
-    eprintln!("OK");
 }
@@ -0,0 +1,146 @@
+// What a command ends with. Handlers write their output and "OK" themselves; a failure is
+// returned instead and Registry::dispatch writes its message to Output::err.
+
+use crate::fs::error::ElfsError;
+use crate::fs::filesystem::TreeReport;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub type Status = Result<(), CommandError>;
+
+// Error outputs the assignment defines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecError {
+    FileNotFound,
+    PathNotFound,
+    Exist,
+    NameTooLong,
+    NotEmpty,
+    PermissionDenied,
+    NotSupported,
+    CannotCreateFile,
+    InvalidMode,
+    InvalidOwner,
+    InvalidSize,
+    InvalidUser,
+    // No spec message: unknown command, fsck findings, a failed journal commit, ...
+    Other,
+}
+
+impl SpecError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SpecError::FileNotFound => "FILE NOT FOUND",
+            SpecError::PathNotFound => "PATH NOT FOUND",
+            SpecError::Exist => "EXIST",
+            SpecError::NameTooLong => "NAME TOO LONG",
+            SpecError::NotEmpty => "NOT EMPTY",
+            SpecError::PermissionDenied => "PERMISSION DENIED",
+            SpecError::NotSupported => "NOT SUPPORTED",
+            SpecError::CannotCreateFile => "CANNOT CREATE FILE",
+            SpecError::InvalidMode => "INVALID MODE",
+            SpecError::InvalidOwner => "INVALID OWNER",
+            SpecError::InvalidSize => "INVALID SIZE",
+            SpecError::InvalidUser => "INVALID USER",
+            SpecError::Other => "ERROR",
+        }
+    }
+
+    // Spec error for a failed filesystem call. Errors the assignment has a message for get it,
+    // the rest fall back to what the command reports for a bad path (PATH NOT FOUND, FILE NOT
+    // FOUND).
+    pub fn of(err: &ElfsError, fallback: SpecError) -> SpecError {
+        match err {
+            ElfsError::AlreadyExists => SpecError::Exist,
+            ElfsError::NameTooLong => SpecError::NameTooLong,
+            ElfsError::DirectoryNotEmpty => SpecError::NotEmpty,
+            ElfsError::PermissionDenied => SpecError::PermissionDenied,
+            ElfsError::Unsupported(_) => SpecError::NotSupported,
+            _ => fallback,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandError {
+    pub kind: SpecError,
+    // Line printed for the failure
+    pub message: String,
+}
+
+impl CommandError {
+    // Failure without a spec message
+    pub fn other(message: impl Into<String>) -> Self {
+        Self {
+            kind: SpecError::Other,
+            message: message.into(),
+        }
+    }
+
+    // Failed filesystem call reported as `kind`; in verbose mode the real cause follows it
+    pub fn caused_by(kind: SpecError, err: &ElfsError) -> Self {
+        let message = if VERBOSE.load(Ordering::Relaxed) {
+            format!("{} ({err})", kind.as_str())
+        } else {
+            kind.as_str().to_string()
+        };
+        Self { kind, message }
+    }
+
+    // Failed filesystem call, mapped by SpecError::of
+    pub fn from_fs(err: &ElfsError, fallback: SpecError) -> Self {
+        Self::caused_by(SpecError::of(err, fallback), err)
+    }
+
+    // Recursive operation that got only partly through: a "<path>: <error>" line for every
+    // entry that failed, then the counts. The kind is that of the first failure.
+    pub fn partial(report: &TreeReport, fallback: SpecError) -> Self {
+        let mut lines: Vec<String> = report
+            .failed
+            .iter()
+            .map(|(path, e)| format!("{path}: {}", Self::from_fs(e, fallback)))
+            .collect();
+        lines.push(format!(
+            "INCOMPLETE: {} failed, {} done",
+            report.failed.len(),
+            report.done
+        ));
+        Self {
+            kind: report
+                .failed
+                .first()
+                .map_or(fallback, |(_, e)| SpecError::of(e, fallback)),
+            message: lines.join("\n"),
+        }
+    }
+}
+
+impl From<SpecError> for CommandError {
+    fn from(kind: SpecError) -> Self {
+        Self {
+            kind,
+            message: kind.as_str().to_string(),
+        }
+    }
+}
+
+// Writing the output failed (closed pipe, full disk behind a redirect, ...)
+impl From<io::Error> for CommandError {
+    fn from(e: io::Error) -> Self {
+        Self::other(format!("Cannot write output: {e}"))
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// Set by --verbose
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(on: bool) {
+    VERBOSE.store(on, Ordering::Relaxed);
+}
@@ -0,0 +1,62 @@
+use crate::commands::{ArgSpec, Args, Command, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::perm::Credentials;
+
+/// su [<uid>[:<gid>]]
+/// Switches the current user that filesystem operations run as.
+///
+/// - Without arguments switches back to root (uid 0, gid 0).
+/// - If gid is omitted, it defaults to the uid.
+/// - The user stays in effect across `format` and re-opening images.
+///
+/// Outputs:
+///   OK
+///   INVALID USER  (ids are not numeric / too many arguments)
+pub struct Su;
+
+impl Command for Su {
+    fn name(&self) -> &'static str {
+        "su"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "su [<uid>[:<gid>]]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Switches the user that operations run as.\n\
+         Without arguments switches back to root; the gid defaults to the uid."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::range(0, 1, SpecError::InvalidUser)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let user = match args.positional() {
+            [] => Credentials::ROOT,
+            [spec] => match parse_user(spec) {
+                Some(u) => u,
+                None => return Err(SpecError::InvalidUser.into()),
+            },
+            _ => return Err(SpecError::InvalidUser.into()),
+        };
+
+        context.set_user(user);
+        writeln!(output.err, "OK")?;
+        Ok(())
+    }
+}
+
+fn parse_user(spec: &str) -> Option<Credentials> {
+    match spec.split_once(':') {
+        Some((u, g)) => Some(Credentials {
+            uid: u.parse().ok()?,
+            gid: g.parse().ok()?,
+        }),
+        None => {
+            let uid = spec.parse().ok()?;
+            Some(Credentials { uid, gid: uid })
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::error::ElfsError;
+use std::fs::File;
+use std::io::BufReader;
+
+/// tar-in s1 s2
+/// Extracts the host tar archive s1 (ustar/pax, also GNU long names) into the directory s2.
+///
+/// Outputs:
+///   OK
+///   FILE NOT FOUND      (archive cannot be opened)
+///   PATH NOT FOUND      (s2 cannot be created / is not a directory)
+///   INVALID ARCHIVE     (damaged archive; members before the damage stay extracted)
+///
+/// s2 is created with its parents when missing. Members that can't be extracted (devices,
+/// FIFOs, paths with "..", names too long for the image) are skipped and listed.
+pub struct TarIn;
+
+impl Command for TarIn {
+    fn name(&self) -> &'static str {
+        "tar-in"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "tar-in <host.tar> <dir>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Extracts a host tar archive into a directory.\n\
+         Files, directories, symlinks and hard links are created directly in the image with\n\
+         the mode, owner (as root) and times the archive records. Existing files are\n\
+         replaced, existing directories merged into."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let host_src = args[0];
+        let fs_dest = args[1];
+
+        let archive = match File::open(host_src) {
+            Ok(f) => BufReader::new(f),
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        let report = match fs.import_tar(archive, fs_dest) {
+            Ok(report) => report,
+            Err(ElfsError::Corrupt(why)) => {
+                return Err(CommandError::other(format!("INVALID ARCHIVE ({why})")));
+            }
+            Err(e) => return Err(CommandError::from_fs(&e, SpecError::PathNotFound)),
+        };
+        if !report.is_complete() {
+            return Err(CommandError::partial(&report, SpecError::PathNotFound));
+        }
+        writeln!(output.err, "OK")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::filesystem::FileKind;
+use std::fs::File;
+use std::io::BufWriter;
+
+/// tar-out s1 s2
+/// Writes the directory s1 with everything below it as the host tar archive s2 (ustar, pax
+/// headers for long paths).
+///
+/// Outputs:
+///   OK
+///   PATH NOT FOUND      (s1 is not a directory / s2 cannot be created or written)
+///
+/// Member paths are relative to s1. Entries that can't be read are listed after the archive
+/// was written.
+pub struct TarOut;
+
+impl Command for TarOut {
+    fn name(&self) -> &'static str {
+        "tar-out"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "tar-out <dir> <host.tar>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Writes a directory tree to a host tar archive.\n\
+         Paths are relative to the directory; symlinks, hard links, mode, owner and\n\
+         modification times are kept."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::PathNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let fs_src = args[0];
+        let host_dest = args[1];
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        // Check the source before the host file gets created
+        if !fs.stat(fs_src).is_ok_and(|meta| meta.kind == FileKind::Dir) {
+            return Err(SpecError::PathNotFound.into());
+        }
+        let archive = match File::create(host_dest) {
+            Ok(f) => BufWriter::new(f),
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        let report = fs
+            .export_tar(fs_src, archive)
+            .map_err(|e| CommandError::from_fs(&e, SpecError::PathNotFound))?;
+        if !report.is_complete() {
+            return Err(CommandError::partial(&report, SpecError::PathNotFound));
+        }
+        writeln!(output.err, "OK")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::filesystem::{FileSystem, TreeReport};
+use crate::fs::layout::Inode;
+
+/// tree [path]
+/// Prints the hierarchy under the path (the current directory without one), one entry per
+/// line, indented by depth:
+///
+/// ```text
+/// /a
+/// ├── DIR: b
+/// │   └── FILE: x.txt
+/// └── SYMLINK: l -> /a/b/x.txt
+///
+/// 1 directory, 1 file, 1 symlink
+/// ```
+///
+/// - Kinds are named like in `ls`; symlinks show their target and are not followed.
+/// - A path that isn't a directory prints just itself.
+/// - Outputs: the tree | PATH NOT FOUND (path doesn't exist); directories that can't be read
+///   are listed after the rest.
+pub struct Tree;
+
+impl Command for Tree {
+    fn name(&self) -> &'static str {
+        "tree"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "tree [path]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Shows a directory tree with entry kinds.\n\
+         Symlinks show their target; without a path the current directory is shown."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::range(0, 1, SpecError::PathNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        let path = args.get(0).unwrap_or(".");
+        let mut inode = match fs.resolve_path(path).and_then(|id| fs.read_inode(id)) {
+            Ok(inode) => inode,
+            Err(_) => return Err(SpecError::PathNotFound.into()),
+        };
+
+        writeln!(output.out, "{path}")?;
+        // A file or symlink on its own has nothing to list below it
+        if inode.file_type != 1 {
+            return Ok(());
+        }
+
+        let mut printer = Printer {
+            fs,
+            output,
+            counts: [0; 3],
+            report: TreeReport::default(),
+        };
+        printer.children(path, &mut inode, "")?;
+        let [files, dirs, links] = printer.counts;
+        writeln!(
+            printer.output.out,
+            "\n{}, {}, {}",
+            counted(dirs, "directory", "directories"),
+            counted(files, "file", "files"),
+            counted(links, "symlink", "symlinks")
+        )?;
+        if !printer.report.is_complete() {
+            return Err(CommandError::partial(
+                &printer.report,
+                SpecError::PathNotFound,
+            ));
+        }
+        Ok(())
+    }
+}
+
+struct Printer<'a, 'o> {
+    fs: &'a mut FileSystem,
+    output: &'a mut Output<'o>,
+    // Entries printed, indexed by file_type (file, dir, symlink)
+    counts: [usize; 3],
+    report: TreeReport,
+}
+
+impl Printer<'_, '_> {
+    // Lines for the entries of `dir`; `indent` is what the levels above put before them
+    fn children(&mut self, path: &str, dir: &mut Inode, indent: &str) -> Status {
+        let entries = match self.fs.read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.report.failed.push((path.to_string(), e));
+                return Ok(());
+            }
+        };
+        let count = entries.len();
+        for (i, entry) in entries.into_iter().enumerate() {
+            let last = i + 1 == count;
+            let child_path = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+            let mut child = match self.fs.read_inode(entry.inode_id) {
+                Ok(child) => child,
+                Err(e) => {
+                    self.report.failed.push((child_path, e));
+                    continue;
+                }
+            };
+
+            let branch = if last { "└── " } else { "├── " };
+            let line = match child.file_type {
+                1 => format!("DIR: {}", entry.name),
+                // A symlink whose target can't be read is still listed, just without it
+                2 => match self.fs.readlink_target(child.id) {
+                    Ok(target) => format!("SYMLINK: {} -> {}", entry.name, target),
+                    Err(_) => format!("SYMLINK: {}", entry.name),
+                },
+                _ => format!("FILE: {}", entry.name),
+            };
+            writeln!(self.output.out, "{indent}{branch}{line}")?;
+            self.report.done += 1;
+            if let Some(count) = self.counts.get_mut(child.file_type as usize) {
+                *count += 1;
+            }
+
+            if child.file_type == 1 {
+                let nested = format!("{indent}{}", if last { "    " } else { "│   " });
+                self.children(&child_path, &mut child, &nested)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// "1 file", "2 files"
+fn counted(n: usize, one: &str, many: &str) -> String {
+    format!("{n} {}", if n == 1 { one } else { many })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::Shell;
+
+    #[test]
+    fn prints_kinds_targets_and_counts() {
+        let mut shell = Shell::new();
+        for line in ["mkdir /d", "mkdir /d/sub", "slink /d/sub/x.txt /d/l"] {
+            shell.run(line).unwrap();
+        }
+        shell.fs().write_file("/d/sub/x.txt", b"x").unwrap();
+        shell.run("tree /d").unwrap();
+        shell.run("tree /d/l").unwrap();
+        shell.run("tree /").unwrap();
+
+        assert_eq!(
+            shell.out(),
+            "/d\n\
+             ├── DIR: sub\n\
+             │   └── FILE: x.txt\n\
+             └── SYMLINK: l -> /d/sub/x.txt\n\
+             \n\
+             1 directory, 1 file, 1 symlink\n\
+             /d/l\n\
+             /\n\
+             └── DIR: d\n    \
+                 ├── DIR: sub\n    \
+                 │   └── FILE: x.txt\n    \
+                 └── SYMLINK: l -> /d/sub/x.txt\n\
+             \n\
+             2 directories, 1 file, 1 symlink\n"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use crate::commands::{ArgSpec, Args, Command, CommandError, Output, SpecError, Status};
+use crate::context::Context;
+use crate::fs::error::ElfsError;
+
+/// truncate <path> <size>
+/// Sets the size of a regular file. Extending leaves a hole: the new bytes read as zeros
+/// and no data blocks are allocated for them. Shrinking releases the blocks past the new end.
+///
+/// SIZE accepts the same units as `format` (B, KB, MB, GB), e.g. `4096`, `10MB`.
+///
+/// Outputs:
+///   OK
+///   INVALID SIZE       (SIZE cannot be parsed or exceeds the maximum file size)
+///   FILE NOT FOUND     (path does not exist, is not a regular file / FS not opened / invalid arguments)
+///   PERMISSION DENIED  (no write permission on the file)
+pub struct Truncate;
+
+impl Command for Truncate {
+    fn name(&self) -> &'static str {
+        "truncate"
+    }
+
+    fn synopsis(&self) -> &'static str {
+        "truncate <path> <size>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Sets the size of a regular file.\n\
+         Extending leaves a hole, shrinking releases the blocks past the new end.\n\
+         SIZE takes the same units as format."
+    }
+
+    fn args(&self) -> ArgSpec {
+        ArgSpec::exactly(2, SpecError::FileNotFound)
+    }
+
+    fn run(&self, args: &Args, context: &mut Context, output: &mut Output) -> Status {
+        let size = match crate::commands::format::parse_size(args[1]) {
+            Ok(s) => s,
+            Err(_) => return Err(SpecError::InvalidSize.into()),
+        };
+
+        let fs = match context.fs_mut() {
+            Ok(fs) => fs,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        let inode_id = match fs.resolve_path(args[0]) {
+            Ok(id) => id,
+            Err(e) => return Err(CommandError::from_fs(&e, SpecError::FileNotFound)),
+        };
+        let mut inode = match fs.read_inode(inode_id) {
+            Ok(i) => i,
+            Err(_) => return Err(SpecError::FileNotFound.into()),
+        };
+
+        match fs.truncate(&mut inode, size) {
+            Ok(()) => writeln!(output.err, "OK")?,
+            Err(e @ ElfsError::FileTooLarge) => {
+                return Err(CommandError::caused_by(SpecError::InvalidSize, &e));
+            }
+            Err(e) => return Err(CommandError::from_fs(&e, SpecError::FileNotFound)),
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
+use crate::fs::device::FileDevice;
 use crate::fs::filesystem::FileSystem;
+use crate::fs::perm::Credentials;
 use std::fs::OpenOptions;
 use std::io;
 use std::path::{Path, PathBuf};
@@ -6,6 +8,14 @@ use std::path::{Path, PathBuf};
 pub struct Context {
     pub(crate) fs: Option<FileSystem>,
     pub(crate) fs_path: Option<PathBuf>,
+    // Current user; applied to every filesystem opened through this context
+    pub(crate) user: Credentials,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Context {
@@ -13,6 +23,7 @@ impl Context {
         Self {
             fs: None,
             fs_path: None,
+            user: Credentials::ROOT,
         }
     }
 
@@ -22,16 +33,34 @@ impl Context {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&path)?;
 
         // Fills instance's attributes
         self.fs_path = Some(path.as_ref().to_path_buf());
 
-        let fs = FileSystem::open(file)?;
-        self.fs = Some(fs);
+        let fs = FileSystem::open(FileDevice::new(file))?;
+        self.install_fs(fs);
         Ok(())
     }
 
+    // Makes `fs` the active filesystem, operating as the current user
+    pub fn install_fs(&mut self, mut fs: FileSystem) {
+        fs.set_credentials(self.user);
+        self.fs = Some(fs);
+    }
+
+    pub fn user(&self) -> Credentials {
+        self.user
+    }
+
+    pub fn set_user(&mut self, user: Credentials) {
+        self.user = user;
+        if let Some(fs) = self.fs.as_mut() {
+            fs.set_credentials(user);
+        }
+    }
+
     pub fn close_fs(&mut self) {
         if let Some(fs) = self.fs.as_mut() {
             let _ = fs.flush();
@@ -45,7 +74,7 @@ impl Context {
     pub fn fs_mut(&mut self) -> io::Result<&mut FileSystem> {
         self.fs
             .as_mut()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Filesystem is not opened"))
+            .ok_or_else(|| io::Error::other("Filesystem is not opened"))
     }
 
     pub fn fs_path(&self) -> Option<&Path> {
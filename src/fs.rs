@@ -1,4 +1,9 @@
 pub mod consts;
+pub mod device;
+pub mod error;
+pub(crate) mod extent;
 pub mod filesystem;
-pub mod io;
-pub mod layout;
+pub(crate) mod io;
+pub(crate) mod journal;
+pub(crate) mod layout;
+pub mod perm;
@@ -1,11 +1,40 @@
 pub const FS_MAGIC: [u8; 4] = *b"ELFS";
-pub const INODE_SIZE: usize = 48; // 48 B
+pub const INODE_SIZE: usize = 128; // 128 B - current (v2) on-disk inode
+pub const INODE_SIZE_V1: usize = 48; // 48 B - legacy inode without timestamps
+pub const INODE_VERSION: u32 = 3; // 2 = timestamps, 3 = + mode/uid/gid
 pub const BLOCK_SIZE: u32 = 4 * 1024; // 4 KiB
 pub const DEFAULT_FS_BYTES: u64 = 600 * 1024 * 1024; // 600 MiB
 // based of ext default BPI - Bytes per Inode
 // lower BPI -> more inodes = good for small files
 // higher BPI -> less inodes = good for bigger files
 pub const DEFAULT_BPI: u32 = 16 * 1024; // 16 KiB
-pub const DIR_NAME_LEN: usize = 12;
+pub const DIR_NAME_LEN: usize = 12; // fixed 16 B entries (images without FEATURE_LONG_NAMES)
 pub const DIR_ENTRY_SIZE: usize = 16;
+pub const DIR_NAME_MAX_LEN: usize = 255; // variable-length entries
+pub const DIR_VAR_HEADER_SIZE: usize = 8;
 pub const DIR_INODE_UNUSED: u32 = u32::MAX;
+
+// Superblock feature flags
+pub const FEATURE_LONG_NAMES: u32 = 1 << 0; // variable-length directory entries
+pub const FEATURE_DOT_ENTRIES: u32 = 1 << 1; // directories store real "." and ".." entries
+pub const FEATURE_EXTENTS: u32 = 1 << 2; // new regular files are extent-mapped
+pub const FEATURE_JOURNAL: u32 = 1 << 3; // metadata updates go through the journal region
+// Features enabled by `format`
+pub const DEFAULT_FEATURES: u32 =
+    FEATURE_LONG_NAMES | FEATURE_DOT_ENTRIES | FEATURE_EXTENTS | FEATURE_JOURNAL;
+
+// Inode flags
+pub const INODE_FLAG_EXTENTS: u8 = 1 << 0; // block pointer words hold an extent tree root
+
+// Extent tree nodes: header (entries u16, depth u16) followed by 12 B entries
+pub const EXTENT_HEADER_SIZE: usize = 4;
+pub const EXTENT_ENTRY_SIZE: usize = 12;
+pub const EXTENT_INLINE_MAX: usize = 2; // entries that fit into the inode's pointer words
+
+// Journal: descriptor block (magic, sequence, count, target block numbers), the block images,
+// then a commit block (magic, sequence, count, checksum)
+pub const JOURNAL_DESC_MAGIC: [u8; 4] = *b"ELJD";
+pub const JOURNAL_COMMIT_MAGIC: [u8; 4] = *b"ELJC";
+pub const JOURNAL_HEADER_SIZE: usize = 12;
+pub const JOURNAL_MIN_BLOCKS: u32 = 32; // 128 KiB
+pub const JOURNAL_MAX_BLOCKS: u32 = 1024; // 4 MiB
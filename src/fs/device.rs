@@ -0,0 +1,200 @@
+// Storage under a FileSystem. Everything the filesystem reads or writes goes through a
+// BlockDevice, so images can live in a host file, in memory or behind any other backend.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Byte-addressed storage an image lives on.
+pub trait BlockDevice {
+    /// Fills `buf` from byte `offset`; reading past the end fails with UnexpectedEof.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+    /// Writes `buf` at byte `offset`, growing the device when it ends earlier.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()>;
+    /// Makes previous writes durable (the journal relies on this ordering).
+    fn flush(&mut self) -> io::Result<()>;
+    /// Current size in bytes.
+    fn size(&mut self) -> io::Result<u64>;
+    /// Truncates or extends (with zeros) to `len` bytes.
+    fn set_size(&mut self, len: u64) -> io::Result<()>;
+}
+
+/// Image stored in a host file.
+pub struct FileDevice {
+    file: File,
+}
+
+impl FileDevice {
+    pub fn new(file: File) -> Self {
+        Self { file }
+    }
+}
+
+impl BlockDevice for FileDevice {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn set_size(&mut self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+}
+
+/// Image kept in memory, e.g. for tests.
+#[derive(Debug, Default, Clone)]
+pub struct MemDevice {
+    data: Vec<u8>,
+}
+
+impl MemDevice {
+    /// Zero-filled device of `len` bytes.
+    pub fn new(len: u64) -> Self {
+        Self {
+            data: vec![0; len as usize],
+        }
+    }
+}
+
+impl BlockDevice for MemDevice {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let start = offset as usize;
+        let src = start
+            .checked_add(buf.len())
+            .and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "read past end"))?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let start = offset as usize;
+        let end = start + buf.len();
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+        }
+        self.data[start..end].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.data.len() as u64)
+    }
+
+    fn set_size(&mut self, len: u64) -> io::Result<()> {
+        self.data.resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+/// MemDevice whose writes start failing on request, for crash and error tests. Clones share
+/// the image, so a test keeps a handle to it while a FileSystem owns another.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FailingDevice {
+    image: std::rc::Rc<std::cell::RefCell<MemDevice>>,
+    writes_left: std::rc::Rc<std::cell::Cell<Option<usize>>>,
+    writes: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+#[cfg(test)]
+impl FailingDevice {
+    pub fn new(len: u64) -> Self {
+        Self {
+            image: std::rc::Rc::new(std::cell::RefCell::new(MemDevice::new(len))),
+            ..Self::default()
+        }
+    }
+
+    // Lets `n` more writes through and fails every one after them; None lifts the limit
+    pub fn fail_after(&self, n: Option<usize>) {
+        self.writes_left.set(n);
+    }
+
+    // Writes that went through so far
+    pub fn writes(&self) -> usize {
+        self.writes.get()
+    }
+
+    // Copy of the image as it is now, e.g. to look at it after a simulated crash
+    pub fn image(&self) -> MemDevice {
+        self.image.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl BlockDevice for FailingDevice {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.image.borrow_mut().read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        match self.writes_left.get() {
+            Some(0) => return Err(io::Error::other("injected write failure")),
+            Some(n) => self.writes_left.set(Some(n - 1)),
+            None => {}
+        }
+        self.writes.set(self.writes.get() + 1);
+        self.image.borrow_mut().write_at(offset, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        self.image.borrow_mut().size()
+    }
+
+    fn set_size(&mut self, len: u64) -> io::Result<()> {
+        self.image.borrow_mut().set_size(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image;
+
+    #[test]
+    fn filesystem_in_memory() {
+        let mut fs = image();
+        let root_id = fs.superblock().root_inode_id;
+        let mut root = fs.read_inode(root_id).unwrap();
+        let mut file = fs.new_inode(0).unwrap().unwrap();
+        fs.write_file_range(&mut file, 0, b"hello").unwrap();
+        fs.dir_add_entry(&mut root, "greeting", file.id).unwrap();
+
+        let id = fs.resolve_path("/greeting").unwrap();
+        let mut inode = fs.read_inode(id).unwrap();
+        let mut buf = vec![0u8; inode.file_size as usize];
+        fs.read_file_range(&mut inode, 0, &mut buf).unwrap();
+        assert_eq!(buf, b"hello");
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn mem_device_reads_past_end_fail() {
+        let mut dev = MemDevice::new(16);
+        let mut buf = [0u8; 8];
+        assert!(dev.read_at(12, &mut buf).is_err());
+        dev.write_at(12, &buf).unwrap();
+        assert_eq!(dev.size().unwrap(), 20);
+    }
+}
@@ -0,0 +1,139 @@
+// Errors reported by FileSystem.
+//
+// Lower layers (BlockDevice, fs::io, the journal) keep working with std::io::Error; it reaches
+// FileSystem callers wrapped in ElfsError::Io. Both directions convert with `?`, so code
+// returning io::Result (Read/Write impls, host file handling) can call FileSystem directly.
+
+use std::fmt;
+use std::io;
+
+/// Why a [`FileSystem`](crate::fs::filesystem::FileSystem) call failed.
+#[derive(Debug)]
+pub enum ElfsError {
+    /// A path component or directory entry does not exist
+    NotFound,
+    /// A directory was expected (path traversal, directory operation)
+    NotADirectory,
+    /// A regular file was expected
+    IsADirectory,
+    NotASymlink,
+    /// Name longer than the directory format of the image allows
+    NameTooLong,
+    /// Empty name, or a path that ends in "." or ".." where a name is needed
+    InvalidName,
+    /// No free data block
+    NoSpace,
+    /// No free inode
+    NoInodes,
+    AlreadyExists,
+    DirectoryNotEmpty,
+    /// Too many symlinks followed while resolving a path
+    SymlinkLoop,
+    /// link_count would overflow
+    TooManyLinks,
+    /// Past what the inode's block mapping can address
+    FileTooLarge,
+    PermissionDenied,
+    /// The image was formatted without the feature the operation needs
+    Unsupported(&'static str),
+    /// The call itself makes no sense (freeing the root, commit without a transaction, ...)
+    InvalidArgument(&'static str),
+    /// On-disk structures contradict each other; `fsck` may help
+    Corrupt(&'static str),
+    /// One transaction changed more metadata blocks than the journal can hold; nothing of it
+    /// was written
+    TransactionTooLarge,
+    /// A failed commit could not be rolled back; the image has to be reopened before it can
+    /// change again
+    ReadOnly,
+    /// The underlying device failed
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ElfsError>;
+
+impl ElfsError {
+    /// Closest std::io::ErrorKind, used when the error is turned into an io::Error.
+    pub fn kind(&self) -> io::ErrorKind {
+        use io::ErrorKind;
+        match self {
+            ElfsError::NotFound => ErrorKind::NotFound,
+            ElfsError::NotADirectory => ErrorKind::NotADirectory,
+            ElfsError::IsADirectory => ErrorKind::IsADirectory,
+            ElfsError::NotASymlink | ElfsError::InvalidName | ElfsError::InvalidArgument(_) => {
+                ErrorKind::InvalidInput
+            }
+            ElfsError::NameTooLong => ErrorKind::InvalidFilename,
+            ElfsError::NoSpace | ElfsError::NoInodes => ErrorKind::StorageFull,
+            ElfsError::AlreadyExists => ErrorKind::AlreadyExists,
+            ElfsError::DirectoryNotEmpty => ErrorKind::DirectoryNotEmpty,
+            ElfsError::SymlinkLoop | ElfsError::Corrupt(_) => ErrorKind::InvalidData,
+            ElfsError::TransactionTooLarge => ErrorKind::StorageFull,
+            ElfsError::ReadOnly => ErrorKind::ReadOnlyFilesystem,
+            ElfsError::TooManyLinks => ErrorKind::TooManyLinks,
+            ElfsError::FileTooLarge => ErrorKind::FileTooLarge,
+            ElfsError::PermissionDenied => ErrorKind::PermissionDenied,
+            ElfsError::Unsupported(_) => ErrorKind::Unsupported,
+            ElfsError::Io(e) => e.kind(),
+        }
+    }
+}
+
+impl fmt::Display for ElfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfsError::NotFound => f.write_str("no such file or directory"),
+            ElfsError::NotADirectory => f.write_str("not a directory"),
+            ElfsError::IsADirectory => f.write_str("is a directory"),
+            ElfsError::NotASymlink => f.write_str("not a symlink"),
+            ElfsError::NameTooLong => f.write_str("name too long"),
+            ElfsError::InvalidName => f.write_str("invalid name"),
+            ElfsError::NoSpace => f.write_str("no free data block"),
+            ElfsError::NoInodes => f.write_str("no free i-node"),
+            ElfsError::AlreadyExists => f.write_str("already exists"),
+            ElfsError::DirectoryNotEmpty => f.write_str("directory not empty"),
+            ElfsError::SymlinkLoop => f.write_str("too many levels of symlinks"),
+            ElfsError::TooManyLinks => f.write_str("too many links"),
+            ElfsError::FileTooLarge => f.write_str("file too large"),
+            ElfsError::PermissionDenied => f.write_str("permission denied"),
+            ElfsError::Unsupported(what) => write!(f, "not supported: {what}"),
+            ElfsError::InvalidArgument(what) => f.write_str(what),
+            ElfsError::Corrupt(what) => write!(f, "corrupt filesystem: {what}"),
+            ElfsError::TransactionTooLarge => f.write_str("transaction larger than the journal"),
+            ElfsError::ReadOnly => f.write_str("read-only after a failed commit, reopen the image"),
+            ElfsError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ElfsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ElfsError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ElfsError {
+    // An ElfsError that passed through io::Error (Read/Write impls, the journal) comes back as
+    // itself
+    fn from(e: io::Error) -> Self {
+        if !e.get_ref().is_some_and(|inner| inner.is::<ElfsError>()) {
+            return ElfsError::Io(e);
+        }
+        match e.into_inner().map(|inner| inner.downcast::<ElfsError>()) {
+            Some(Ok(inner)) => *inner,
+            _ => unreachable!("checked above"),
+        }
+    }
+}
+
+impl From<ElfsError> for io::Error {
+    fn from(e: ElfsError) -> Self {
+        match e {
+            ElfsError::Io(e) => e,
+            other => io::Error::new(other.kind(), other),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::fs::consts::{BLOCK_SIZE, EXTENT_ENTRY_SIZE, EXTENT_HEADER_SIZE, EXTENT_INLINE_MAX};
+use crate::fs::layout::Inode;
+
+// One run of a file: `len` blocks starting at logical block `logical` are stored in the
+// contiguous physical blocks starting at `physical`. In index nodes the same record points
+// to a child node: `physical` is the child block and `len` is unused (0).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extent {
+    pub logical: u32,
+    pub physical: u32,
+    pub len: u32,
+}
+
+impl Extent {
+    // First logical block after the run
+    pub fn logical_end(&self) -> u64 {
+        self.logical as u64 + self.len as u64
+    }
+
+    // Physical block holding `logical`, if the run covers it
+    pub fn map(&self, logical: u64) -> Option<u32> {
+        if logical >= self.logical as u64 && logical < self.logical_end() {
+            Some(self.physical + (logical - self.logical as u64) as u32)
+        } else {
+            None
+        }
+    }
+}
+
+// Entries that fit into one tree node block
+pub fn entries_per_block() -> usize {
+    (BLOCK_SIZE as usize - EXTENT_HEADER_SIZE) / EXTENT_ENTRY_SIZE
+}
+
+// A node of the extent tree. Depth 0 nodes hold extents, deeper nodes hold index records.
+// The root lives in the inode's seven pointer words (header word + 2 entries × 3 words),
+// all other nodes occupy a whole block.
+#[derive(Debug, Default)]
+pub struct ExtentNode {
+    pub depth: u16,
+    pub entries: Vec<Extent>,
+}
+
+impl ExtentNode {
+    pub fn from_inode(inode: &Inode) -> Self {
+        let w = inode_words(inode);
+        let count = (w[0] & 0xFFFF) as usize;
+        let depth = (w[0] >> 16) as u16;
+        let entries = (0..count.min(EXTENT_INLINE_MAX))
+            .map(|i| Extent {
+                logical: w[1 + i * 3],
+                physical: w[2 + i * 3],
+                len: w[3 + i * 3],
+            })
+            .collect();
+        Self { depth, entries }
+    }
+
+    pub fn write_to_inode(&self, inode: &mut Inode) {
+        debug_assert!(self.entries.len() <= EXTENT_INLINE_MAX);
+        let mut w = [0u32; 7];
+        w[0] = self.entries.len() as u32 | (self.depth as u32) << 16;
+        for (i, e) in self.entries.iter().take(EXTENT_INLINE_MAX).enumerate() {
+            w[1 + i * 3] = e.logical;
+            w[2 + i * 3] = e.physical;
+            w[3 + i * 3] = e.len;
+        }
+        inode.single_directs.copy_from_slice(&w[0..5]);
+        inode.single_indirect = w[5];
+        inode.double_indirect = w[6];
+    }
+
+    // None if the header claims more entries than a block can hold
+    pub fn from_block(buf: &[u8]) -> Option<Self> {
+        let count = u16::from_le_bytes([buf[0], buf[1]]) as usize;
+        let depth = u16::from_le_bytes([buf[2], buf[3]]);
+        if count > entries_per_block() {
+            return None;
+        }
+        let entries = buf[EXTENT_HEADER_SIZE..]
+            .chunks_exact(EXTENT_ENTRY_SIZE)
+            .take(count)
+            .map(|c| Extent {
+                logical: u32::from_le_bytes(c[0..4].try_into().unwrap()),
+                physical: u32::from_le_bytes(c[4..8].try_into().unwrap()),
+                len: u32::from_le_bytes(c[8..12].try_into().unwrap()),
+            })
+            .collect();
+        Some(Self { depth, entries })
+    }
+
+    pub fn to_block(&self) -> Vec<u8> {
+        debug_assert!(self.entries.len() <= entries_per_block());
+        let mut buf = vec![0u8; BLOCK_SIZE as usize];
+        buf[0..2].copy_from_slice(&(self.entries.len() as u16).to_le_bytes());
+        buf[2..4].copy_from_slice(&self.depth.to_le_bytes());
+        for (i, e) in self.entries.iter().enumerate() {
+            let off = EXTENT_HEADER_SIZE + i * EXTENT_ENTRY_SIZE;
+            buf[off..off + 4].copy_from_slice(&e.logical.to_le_bytes());
+            buf[off + 4..off + 8].copy_from_slice(&e.physical.to_le_bytes());
+            buf[off + 8..off + 12].copy_from_slice(&e.len.to_le_bytes());
+        }
+        buf
+    }
+}
+
+fn inode_words(inode: &Inode) -> [u32; 7] {
+    let mut w = [0u32; 7];
+    w[0..5].copy_from_slice(&inode.single_directs);
+    w[5] = inode.single_indirect;
+    w[6] = inode.double_indirect;
+    w
+}
+
+// Adds a freshly mapped run, keeping the list sorted and merging runs that are adjacent
+// both logically and physically
+pub fn insert_extent(extents: &mut Vec<Extent>, new: Extent) {
+    let pos = extents.partition_point(|e| e.logical < new.logical);
+    extents.insert(pos, new);
+    let mut merged: Vec<Extent> = Vec::with_capacity(extents.len());
+    for e in extents.drain(..) {
+        match merged.last_mut() {
+            Some(prev)
+                if prev.logical_end() == e.logical as u64
+                    && prev.physical as u64 + prev.len as u64 == e.physical as u64 =>
+            {
+                prev.len += e.len;
+            }
+            _ => merged.push(e),
+        }
+    }
+    *extents = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext(logical: u32, physical: u32, len: u32) -> Extent {
+        Extent {
+            logical,
+            physical,
+            len,
+        }
+    }
+
+    #[test]
+    fn insert_merges_adjacent_runs() {
+        let mut list = vec![ext(0, 100, 4)];
+        insert_extent(&mut list, ext(4, 104, 2));
+        assert_eq!(list, vec![ext(0, 100, 6)]);
+
+        // Logically adjacent but physically elsewhere stays separate
+        insert_extent(&mut list, ext(6, 200, 1));
+        // Out-of-order insert lands in logical order
+        insert_extent(&mut list, ext(10, 50, 1));
+        insert_extent(&mut list, ext(8, 48, 2));
+        assert_eq!(list, vec![ext(0, 100, 6), ext(6, 200, 1), ext(8, 48, 3)]);
+    }
+
+    #[test]
+    fn inline_and_block_nodes_roundtrip() {
+        let mut inode = Inode::new(1, 0);
+        let root = ExtentNode {
+            depth: 1,
+            entries: vec![ext(0, 7, 0), ext(341, 9, 0)],
+        };
+        root.write_to_inode(&mut inode);
+        let back = ExtentNode::from_inode(&inode);
+        assert_eq!(back.depth, 1);
+        assert_eq!(back.entries, root.entries);
+
+        let leaf = ExtentNode {
+            depth: 0,
+            entries: (0..entries_per_block() as u32)
+                .map(|i| ext(i * 2, 1000 + i * 3, 1))
+                .collect(),
+        };
+        let back = ExtentNode::from_block(&leaf.to_block()).unwrap();
+        assert_eq!(back.depth, 0);
+        assert_eq!(back.entries, leaf.entries);
+    }
+}
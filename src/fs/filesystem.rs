@@ -1,112 +1,269 @@
+mod api;
+mod check;
+mod file;
+mod tar;
+mod tree;
+mod txn;
+
+pub use api::{DirEntry, FileKind, Metadata};
+pub use check::{CheckReport, Problem};
+pub use file::{ElfsFile, OpenOptions};
+pub use tree::TreeReport;
+
+/// A mounted ELFS image. Opened with [`FileSystem::open`] or created with
+/// [`FileSystem::format`] on any [`BlockDevice`](crate::fs::device::BlockDevice).
 pub struct FileSystem {
-    file: std::fs::File,
+    dev: Box<dyn crate::fs::device::BlockDevice>,
 
     sb: crate::fs::layout::Superblock,
 
     data_bitmap: Vec<u8>,
+    inode_bitmap: Vec<u8>,
     cwd_inode: u32,
-    cwd_stack: Vec<u32>,
     cwd_path: String,
     bitmap_dirty: bool,
+    inode_bitmap_dirty: bool,
+    creds: crate::fs::perm::Credentials,
+    // Open transaction (see txn.rs) and the journal state found by open()
+    txn: Option<crate::fs::journal::Transaction>,
+    journal_seq: u32,
+    recovery: crate::fs::journal::Recovery,
+    // Set when a failed commit couldn't be rolled back; nothing is written until reopened
+    read_only: bool,
 }
 
 impl FileSystem {
-    pub fn open(mut file: std::fs::File) -> std::io::Result<Self> {
-        use crate::fs::io::{load_bitmap, read_superblock};
-        let sb = read_superblock(&mut file)?;
-        let data_bitmap = load_bitmap(&mut file, &sb)?;
+    /// Opens an existing image, replaying or discarding what a crash left in the journal
+    /// (see [`FileSystem::recovery`]).
+    pub fn open<D: crate::fs::device::BlockDevice + 'static>(
+        dev: D,
+    ) -> crate::fs::error::Result<Self> {
+        use crate::fs::io::read_superblock;
+        let mut dev: Box<dyn crate::fs::device::BlockDevice> = Box::new(dev);
+        let mut sb = read_superblock(dev.as_mut())?;
+        if dev.size()? < sb.fs_size {
+            return Err(crate::fs::error::ElfsError::Corrupt(
+                "image is smaller than its superblock says",
+            ));
+        }
+        // A crash may have left a transaction in the journal
+        let (recovery, journal_seq) = crate::fs::journal::recover(dev.as_mut(), &sb)?;
+        if recovery != crate::fs::journal::Recovery::Clean {
+            sb = read_superblock(dev.as_mut())?;
+        }
         let cwd_inode = sb.root_inode_id;
 
-        Ok(Self {
-            file,
+        let mut fs = Self {
+            dev,
 
             sb,
 
-            data_bitmap,
+            data_bitmap: Vec::new(),
+            inode_bitmap: Vec::new(),
 
             cwd_inode,
 
-            cwd_stack: Vec::new(),
-
             cwd_path: "/".to_string(),
             bitmap_dirty: false,
-        })
+            inode_bitmap_dirty: false,
+            creds: crate::fs::perm::Credentials::ROOT,
+            txn: None,
+            journal_seq,
+            recovery,
+            read_only: false,
+        };
+        (fs.data_bitmap, fs.inode_bitmap) = load_bitmaps(&mut fs)?;
+        Ok(fs)
     }
 
-    pub fn flush(&mut self) -> std::io::Result<()> {
-        if self.bitmap_dirty {
-            crate::fs::io::flush_bitmap(&mut self.file, &self.sb, &self.data_bitmap)?;
-            self.bitmap_dirty = false;
+    /// Creates a fresh image of `fs_bytes` bytes on `dev`: superblock, empty journal, bitmaps
+    /// and inode table, plus the root directory. Fails with `InvalidInput` when the size leaves
+    /// no room for a single inode.
+    pub fn format<D: crate::fs::device::BlockDevice + 'static>(
+        mut dev: D,
+        fs_bytes: u64,
+    ) -> crate::fs::error::Result<Self> {
+        use crate::fs::consts::{BLOCK_SIZE, DEFAULT_BPI};
+        use crate::fs::io::{
+            bitmap_set, compute_layout, flush_inode_bitmap, write_inode, write_span,
+            write_superblock,
+        };
+        let sb = compute_layout(fs_bytes, BLOCK_SIZE, DEFAULT_BPI);
+        if sb.inode_count == 0 {
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
+                "image too small",
+            ));
         }
-        Ok(())
+        dev.set_size(fs_bytes)?;
+        write_superblock(&mut dev, &sb)?;
+
+        // Journal (so nothing from a previous image gets replayed), data bitmap and inode
+        // table (bitmap regions included) start out zeroed
+        for (start, count) in [
+            (sb.journal_start, sb.journal_count),
+            (sb.bitmap_start, sb.bitmap_count),
+            (
+                sb.inode_bitmap_start,
+                sb.block_start - sb.inode_bitmap_start,
+            ),
+        ] {
+            let zeros = vec![0u8; count as usize * BLOCK_SIZE as usize];
+            write_span(&mut dev, start as u64, count as u64, BLOCK_SIZE, &zeros)?;
+        }
+
+        // Root directory inode, the only one in use
+        let root_id = sb.root_inode_id;
+        let mut root = crate::fs::layout::Inode::new(root_id, 1);
+        write_inode(&mut dev, &sb, root_id, &root)?;
+        let mut inode_bitmap = vec![0u8; sb.inode_bitmap_count as usize * BLOCK_SIZE as usize];
+        bitmap_set(&mut inode_bitmap, root_id);
+        flush_inode_bitmap(&mut dev, &sb, &inode_bitmap)?;
+
+        // Root gets "." and ".." entries, both pointing back to itself
+        let mut fs = Self::open(dev)?;
+        fs.init_dir(&mut root, root_id)?;
+        fs.flush()?;
+        Ok(fs)
+    }
+
+    // Identity used for permission checks and ownership of newly created inodes
+    pub fn set_credentials(&mut self, creds: crate::fs::perm::Credentials) {
+        self.creds = creds;
+    }
+    pub fn credentials(&self) -> crate::fs::perm::Credentials {
+        self.creds
+    }
+
+    // Fails with PermissionDenied unless the current user has all `want` bits on the inode.
+    // Images without stored permissions (inode version < 3) allow everything.
+    fn check_access(
+        &self,
+        inode: &crate::fs::layout::Inode,
+        want: u16,
+    ) -> crate::fs::error::Result<()> {
+        if !self.sb.has_permissions() || crate::fs::perm::permits(&self.creds, inode, want) {
+            Ok(())
+        } else {
+            Err(crate::fs::error::ElfsError::PermissionDenied)
+        }
+    }
+
+    /// Writes the bitmaps back, together with everything an open transaction collected so far.
+    pub fn flush(&mut self) -> crate::fs::error::Result<()> {
+        if self.txn.is_some() {
+            return self.commit_pending();
+        }
+        self.begin_transaction();
+        self.commit_transaction()
     }
 
     // Accessors for statfs and other read-only inspection
-    pub fn superblock(&self) -> &crate::fs::layout::Superblock {
+    pub(crate) fn superblock(&self) -> &crate::fs::layout::Superblock {
         &self.sb
     }
-    pub fn data_bitmap(&self) -> &[u8] {
+    pub(crate) fn data_bitmap(&self) -> &[u8] {
         &self.data_bitmap
     }
+    pub(crate) fn inode_bitmap(&self) -> &[u8] {
+        &self.inode_bitmap
+    }
     pub fn block_count(&self) -> u32 {
         self.sb.block_count
     }
     pub fn inode_count(&self) -> u32 {
         self.sb.inode_count
     }
-    pub fn is_bitmap_dirty(&self) -> bool {
-        self.bitmap_dirty
-    }
 
     // Inode helpers
-    pub fn read_inode(&mut self, id: u32) -> std::io::Result<crate::fs::layout::Inode> {
-        crate::fs::io::read_inode(&mut self.file, &self.sb, id)
+    pub(crate) fn read_inode(
+        &mut self,
+        id: u32,
+    ) -> crate::fs::error::Result<crate::fs::layout::Inode> {
+        let mut buf = vec![0u8; self.sb.inode_size()];
+        self.read_at(crate::fs::io::inode_offset(&self.sb, id)?, &mut buf)?;
+        Ok(crate::fs::layout::Inode::from_bytes(&buf))
     }
-    pub fn write_inode(
+    pub(crate) fn write_inode(
         &mut self,
         id: u32,
         inode: &crate::fs::layout::Inode,
-    ) -> std::io::Result<()> {
+    ) -> crate::fs::error::Result<()> {
         if id != inode.id {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
                 "inode id mismatch",
             ));
         }
-        crate::fs::io::write_inode(&mut self.file, &self.sb, id, inode)
+        let buf = inode.to_bytes(self.sb.inode_size());
+        self.write_meta_at(crate::fs::io::inode_offset(&self.sb, id)?, &buf)
     }
 
     // Block alloc/free (via bitmapu)
-    pub fn alloc_block(&mut self) -> Option<u32> {
+    pub(crate) fn alloc_block(&mut self) -> Option<u32> {
         let b = crate::fs::io::alloc_data_block(&mut self.data_bitmap, &self.sb)?;
         self.bitmap_dirty = true;
         Some(b)
     }
-    pub fn free_block(&mut self, abs_block: u32) -> std::io::Result<()> {
-        crate::fs::io::free_data_block(&mut self.data_bitmap, &self.sb, abs_block)?;
-        self.bitmap_dirty = true;
-        Ok(())
+
+    // Inode allocation (via inode bitmap); the id is reserved until free_inode releases it
+    pub(crate) fn alloc_inode(&mut self) -> crate::fs::error::Result<Option<u32>> {
+        let id = crate::fs::io::alloc_inode_id(&mut self.inode_bitmap, &self.sb);
+        if id.is_some() {
+            self.inode_bitmap_dirty = true;
+        }
+        Ok(id)
     }
 
-    // Inode allocation (scan link_count == 0)
-    pub fn alloc_inode(&mut self) -> std::io::Result<Option<u32>> {
-        for id in 1..self.sb.inode_count {
-            let ino = crate::fs::io::read_inode(&mut self.file, &self.sb, id)?;
-            if ino.link_count == 0 {
-                return Ok(Some(id));
-            }
+    // Allocates an inode and initializes it on disk as an empty file_type object (link_count 1).
+    pub(crate) fn new_inode(
+        &mut self,
+        file_type: u8,
+    ) -> crate::fs::error::Result<Option<crate::fs::layout::Inode>> {
+        let Some(id) = self.alloc_inode()? else {
+            return Ok(None);
+        };
+        let mut inode = crate::fs::layout::Inode::new(id, file_type);
+        inode.uid = self.creds.uid;
+        inode.gid = self.creds.gid;
+        if file_type == 0 && self.sb.has_extents() {
+            inode.flags |= crate::fs::consts::INODE_FLAG_EXTENTS;
+            crate::fs::extent::ExtentNode::default().write_to_inode(&mut inode);
         }
-        Ok(None)
+        if let Err(e) = self.write_inode(id, &inode) {
+            let _ = self.free_inode(id);
+            return Err(e);
+        }
+        Ok(Some(inode))
     }
 
-    pub fn free_inode(&mut self, inode_id: u32) -> std::io::Result<()> {
+    // Drops one link to the inode; blocks are released only when the last link goes away.
+    pub(crate) fn free_inode(&mut self, inode_id: u32) -> crate::fs::error::Result<()> {
         if inode_id == self.sb.root_inode_id {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
                 "cannot free root inode",
             ));
         }
-        let mut ino = crate::fs::io::read_inode(&mut self.file, &self.sb, inode_id)?;
+        let mut ino = self.read_inode(inode_id)?;
+
+        // Other hard links still reference the inode -> just drop this one
+        if ino.link_count > 1 {
+            ino.link_count -= 1;
+            ino.ctime = crate::fs::layout::now_timestamp();
+            return self.write_inode(inode_id, &ino);
+        }
+
+        if ino.is_extent_mapped() {
+            // Data runs first, then the tree's node blocks
+            let (extents, nodes) = self.load_extents(&ino)?;
+            for e in extents {
+                for b in e.physical..e.physical + e.len {
+                    let _ = self.free_block(b);
+                }
+            }
+            for b in nodes {
+                let _ = self.free_block(b);
+            }
+            crate::fs::extent::ExtentNode::default().write_to_inode(&mut ino);
+        }
 
         // Release direct blocks
 
@@ -118,88 +275,58 @@ impl FileSystem {
             }
         }
 
-        // Release single-, double-, and triple-indirect data blocks (without capturing self in closures)
+        // Release single-, double- and triple-indirect trees (pointer blocks included)
+        for level in 1..=3 {
+            let root = indirect_root(&ino, level);
+            if root != 0 {
+                self.free_tree(root, level);
+                set_indirect_root(&mut ino, level, 0);
+            }
+        }
 
-        // Single-indirect (data pointers)
-        if ino.single_indirect != 0 {
-            let mut raw = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+        ino.file_size = 0;
 
-            if crate::fs::io::read_block(
-                &mut self.file,
-                crate::fs::consts::BLOCK_SIZE,
-                ino.single_indirect as u64,
-                &mut raw,
-            )
-            .is_ok()
-            {
-                for chunk in raw.chunks_exact(4) {
-                    let p = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-                    if p != 0 {
-                        let _ = self.free_block(p);
-                    }
-                }
-            }
+        ino.file_type = 0;
 
-            let _ = self.free_block(ino.single_indirect);
+        ino.link_count = 0;
 
-            ino.single_indirect = 0;
-        }
+        self.write_inode(inode_id, &ino)?;
 
-        // Double-indirect (block of single-indirect blocks)
+        crate::fs::io::bitmap_clear(&mut self.inode_bitmap, inode_id);
+        self.inode_bitmap_dirty = true;
+        Ok(())
+    }
 
-        if ino.double_indirect != 0 {
-            let mut l2 = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-            if crate::fs::io::read_block(
-                &mut self.file,
-                crate::fs::consts::BLOCK_SIZE,
-                ino.double_indirect as u64,
-                &mut l2,
-            )
-            .is_ok()
-            {
-                for l2_chunk in l2.chunks_exact(4) {
-                    let l1_ptr =
-                        u32::from_le_bytes([l2_chunk[0], l2_chunk[1], l2_chunk[2], l2_chunk[3]]);
-                    if l1_ptr != 0 {
-                        let mut l1 = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                        if crate::fs::io::read_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            l1_ptr as u64,
-                            &mut l1,
-                        )
-                        .is_ok()
-                        {
-                            for d_chunk in l1.chunks_exact(4) {
-                                let d = u32::from_le_bytes([
-                                    d_chunk[0], d_chunk[1], d_chunk[2], d_chunk[3],
-                                ]);
-                                if d != 0 {
-                                    let _ = self.free_block(d);
-                                }
-                            }
-                        }
-                        let _ = self.free_block(l1_ptr);
+    // Frees a pointer block of the given depth and everything below it (depth 0 = data block).
+    // Best-effort: unreadable pointer blocks are released without descending.
+    fn free_tree(&mut self, block_id: u32, depth: usize) {
+        if depth > 0 {
+            let mut raw = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+            if self.read_block(block_id, &mut raw).is_ok() {
+                for chunk in raw.chunks_exact(4) {
+                    let p = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    if p != 0 {
+                        self.free_tree(p, depth - 1);
                     }
                 }
             }
-
-            let _ = self.free_block(ino.double_indirect);
-            ino.double_indirect = 0;
         }
+        let _ = self.free_block(block_id);
+    }
 
-        ino.file_size = 0;
-
-        ino.file_type = 0;
-
-        ino.link_count = 0;
-
-        crate::fs::io::write_inode(&mut self.file, &self.sb, inode_id, &ino)
+    // Number of logical blocks a file can address on this image. Legacy 48 B inodes have no
+    // room for the triple-indirect pointer and stop after the double-indirect range.
+    fn max_file_blocks(&self) -> u64 {
+        let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
+        let levels = if self.sb.has_timestamps() { 3 } else { 2 };
+        5 + (1..=levels).map(|l| ptrs_per_block.pow(l)).sum::<u64>()
     }
 
     fn get_block(&mut self, inode: &crate::fs::layout::Inode, logical: u64) -> Option<u32> {
-        let block_size = crate::fs::consts::BLOCK_SIZE as u64;
-        let ptrs_per_block = (block_size / 4) as u64;
+        if inode.is_extent_mapped() {
+            let (extents, _) = self.load_extents(inode).ok()?;
+            return extents.iter().find_map(|e| e.map(logical));
+        }
 
         // Direct region
         if logical < 5 {
@@ -207,29 +334,15 @@ impl FileSystem {
             return if b == 0 { None } else { Some(b) };
         }
 
-        // Single-indirect region
-        let single_start = 5;
-        let single_end = single_start + ptrs_per_block;
-        if logical < single_end {
-            let idx = logical - single_start;
-            return self.load_pointer(inode.single_indirect, idx);
+        // Indirect regions: walk from the level's root pointer down to the data block
+        let (level, rel) = indirect_position(logical)?;
+        let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
+        let mut ptr = indirect_root(inode, level);
+        for depth in (0..level as u32).rev() {
+            let idx = (rel / ptrs_per_block.pow(depth)) % ptrs_per_block;
+            ptr = self.load_pointer(ptr, idx)?;
         }
-
-        // Double-indirect region
-        let double_start = single_end;
-        let double_span = ptrs_per_block * ptrs_per_block;
-        let double_end = double_start + double_span;
-        if logical < double_end {
-            let rel = logical - double_start;
-            let l2 = rel / ptrs_per_block;
-            let l1 = rel % ptrs_per_block;
-            let first = self.load_pointer(inode.double_indirect, l2)?;
-            return self.load_pointer(first, l1);
-        }
-
-        // Triple-indirect region removed (after double-indirect we stop)
-
-        None
+        Some(ptr)
     }
 
     // Helper: load nth 32-bit pointer from a pointer block id.
@@ -243,14 +356,7 @@ impl FileSystem {
             return None;
         }
         let mut buf = vec![0u8; block_size];
-        if crate::fs::io::read_block(
-            &mut self.file,
-            crate::fs::consts::BLOCK_SIZE,
-            block_id as u64,
-            &mut buf,
-        )
-        .is_err()
-        {
+        if self.read_block(block_id, &mut buf).is_err() {
             return None;
         }
         let off = (index as usize) * 4;
@@ -258,13 +364,23 @@ impl FileSystem {
         if val == 0 { None } else { Some(val) }
     }
 
+    // Allocates a block and fills it with zeros (fresh pointer blocks must not hold garbage)
+    fn alloc_zeroed_block(&mut self) -> crate::fs::error::Result<Option<u32>> {
+        let Some(b) = self.alloc_block() else {
+            return Ok(None);
+        };
+        let zero = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+        self.write_block(b, &zero)?;
+        Ok(Some(b))
+    }
+
     fn get_or_alloc_block(
         &mut self,
 
         inode: &mut crate::fs::layout::Inode,
 
         logical: u64,
-    ) -> std::io::Result<Option<u32>> {
+    ) -> crate::fs::error::Result<Option<u32>> {
         if logical < 5 {
             let idx = logical as usize;
             if inode.single_directs[idx] == 0 {
@@ -282,193 +398,101 @@ impl FileSystem {
             }
         } else {
             // Indirect regions allocation (single / double / triple)
-            let block_size = crate::fs::consts::BLOCK_SIZE as u64;
-            let ptrs_per_block = (block_size / 4) as u64;
-
-            // Single-indirect
-            let single_start = 5;
-            let single_end = single_start + ptrs_per_block;
-            if logical < single_end {
-                let idx = logical - single_start;
-                // Inline single-indirect allocation (avoids helper borrowing issues)
-                if inode.single_indirect == 0 {
-                    if let Some(b) = self.alloc_block() {
-                        let zero = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                        crate::fs::io::write_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            b as u64,
-                            &zero,
-                        )?;
-                        inode.single_indirect = b;
-                        self.write_inode(inode.id, inode)?;
-                    } else {
-                        return Ok(None);
-                    }
-                }
-                // Load single-indirect block
-                let mut sibuf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                crate::fs::io::read_block(
-                    &mut self.file,
-                    crate::fs::consts::BLOCK_SIZE,
-                    inode.single_indirect as u64,
-                    &mut sibuf,
-                )?;
-                let off_si = (idx as usize) * 4;
-                let mut data_ptr = u32::from_le_bytes([
-                    sibuf[off_si],
-                    sibuf[off_si + 1],
-                    sibuf[off_si + 2],
-                    sibuf[off_si + 3],
-                ]);
-                if data_ptr == 0 {
-                    if let Some(new_b) = self.alloc_block() {
-                        data_ptr = new_b;
-                        let bytes = data_ptr.to_le_bytes();
-                        sibuf[off_si..off_si + 4].copy_from_slice(&bytes);
-                        crate::fs::io::write_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            inode.single_indirect as u64,
-                            &sibuf,
-                        )?;
-                    } else {
-                        return Ok(None);
-                    }
-                }
-                return Ok(Some(data_ptr));
-            }
-
-            // Double-indirect
-            let double_start = single_end;
-            let double_span = ptrs_per_block * ptrs_per_block;
-            let double_end = double_start + double_span;
-            if logical < double_end {
-                let rel = logical - double_start;
-                let l2 = rel / ptrs_per_block;
-                let l1 = rel % ptrs_per_block;
-
-                // Ensure top-level double_indirect pointer block exists
-                if inode.double_indirect == 0 {
-                    if let Some(b) = self.alloc_block() {
-                        let zero = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                        crate::fs::io::write_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            b as u64,
-                            &zero,
-                        )?;
-                        inode.double_indirect = b;
-                        self.write_inode(inode.id, inode)?;
-                    } else {
-                        return Ok(None);
-                    }
-                }
+            let Some((level, rel)) = indirect_position(logical) else {
+                return Ok(None); // beyond supported range
+            };
+            let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
 
-                // Load level-2 (double_indirect) block
-                let mut l2_buf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                crate::fs::io::read_block(
-                    &mut self.file,
-                    crate::fs::consts::BLOCK_SIZE,
-                    inode.double_indirect as u64,
-                    &mut l2_buf,
-                )?;
-                let off_l2 = (l2 as usize) * 4;
-                let mut second = u32::from_le_bytes([
-                    l2_buf[off_l2],
-                    l2_buf[off_l2 + 1],
-                    l2_buf[off_l2 + 2],
-                    l2_buf[off_l2 + 3],
-                ]);
-                if second == 0 {
-                    if let Some(new_b) = self.alloc_block() {
-                        // Zero new level-1 pointer block
-                        let zero = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                        crate::fs::io::write_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            new_b as u64,
-                            &zero,
-                        )?;
-                        second = new_b;
-                        let bytes = second.to_le_bytes();
-                        l2_buf[off_l2..off_l2 + 4].copy_from_slice(&bytes);
-                        crate::fs::io::write_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            inode.double_indirect as u64,
-                            &l2_buf,
-                        )?;
-                    } else {
-                        return Ok(None);
-                    }
-                }
+            // Ensure the level's top pointer block exists
+            if indirect_root(inode, level) == 0 {
+                let Some(b) = self.alloc_zeroed_block()? else {
+                    return Ok(None);
+                };
+                set_indirect_root(inode, level, b);
+                self.write_inode(inode.id, inode)?;
+            }
 
-                // Load level-1 block
-                let mut l1_buf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-                crate::fs::io::read_block(
-                    &mut self.file,
-                    crate::fs::consts::BLOCK_SIZE,
-                    second as u64,
-                    &mut l1_buf,
-                )?;
-                let off_l1 = (l1 as usize) * 4;
-                let mut data_ptr = u32::from_le_bytes([
-                    l1_buf[off_l1],
-                    l1_buf[off_l1 + 1],
-                    l1_buf[off_l1 + 2],
-                    l1_buf[off_l1 + 3],
-                ]);
-                if data_ptr == 0 {
-                    if let Some(new_b) = self.alloc_block() {
-                        data_ptr = new_b;
-                        let bytes = data_ptr.to_le_bytes();
-                        l1_buf[off_l1..off_l1 + 4].copy_from_slice(&bytes);
-                        crate::fs::io::write_block(
-                            &mut self.file,
-                            crate::fs::consts::BLOCK_SIZE,
-                            second as u64,
-                            &l1_buf,
-                        )?;
+            // Walk down, allocating missing pointer blocks (zeroed) and finally the data block
+            let mut ptr_block = indirect_root(inode, level);
+            for depth in (0..level as u32).rev() {
+                let idx = ((rel / ptrs_per_block.pow(depth)) % ptrs_per_block) as usize;
+                let mut buf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+                self.read_block(ptr_block, &mut buf)?;
+                let off = idx * 4;
+                let mut next =
+                    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+                if next == 0 {
+                    let allocated = if depth > 0 {
+                        self.alloc_zeroed_block()?
                     } else {
+                        self.alloc_block()
+                    };
+                    let Some(new_b) = allocated else {
                         return Ok(None);
-                    }
+                    };
+                    next = new_b;
+                    buf[off..off + 4].copy_from_slice(&next.to_le_bytes());
+                    self.write_block(ptr_block, &buf)?;
                 }
-
-                return Ok(Some(data_ptr));
+                ptr_block = next;
             }
 
-            // Triple-indirect allocation removed (no support beyond double-indirect)
-            // Any logical index past double-indirect range returns None.
-
-            Ok(None) // beyond supported range
+            Ok(Some(ptr_block))
         }
     }
 
-    // (Removed ensure_pointer helper)
-
-    // (Removed ensure_nth_pointer helper)
-
-    // (Removed alloc_in_pointer_block: single-indirect handled inline in get_or_alloc_block)
+    // File read (range) - assumes range is within file_size, holes read as zeros; maintains
+    // atime (the caller's copy of the inode is updated too)
+    pub(crate) fn read_file_range(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> crate::fs::error::Result<()> {
+        self.check_access(inode, crate::fs::perm::PERM_READ)?;
+        self.read_range(inode, offset, buf)?;
+        self.touch_atime(inode)
+    }
 
-    // (Removed alloc_nth_in_block: replaced by explicit inline manipulation in double-indirect branch)
+    // Relatime-style access time update: only written when atime is older than the last
+    // modification/change or more than a day old, so repeated reads don't rewrite the inode.
+    fn touch_atime(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+    ) -> crate::fs::error::Result<()> {
+        // Reading stays possible on a read-only filesystem, just without atime
+        if !self.sb.has_timestamps() || self.read_only || inode.link_count == 0 {
+            return Ok(());
+        }
+        let now = crate::fs::layout::now_timestamp();
+        if inode.atime < inode.mtime
+            || inode.atime < inode.ctime
+            || now >= inode.atime + 24 * 60 * 60
+        {
+            inode.atime = now;
+            self.write_inode(inode.id, inode)?;
+        }
+        Ok(())
+    }
 
-    // File read (range) - assumes range is within file_size
-    pub fn read_file_range(
+    // Raw range read used by directory internals (lookups don't count as access)
+    fn read_range(
         &mut self,
         inode: &crate::fs::layout::Inode,
         offset: u64,
         buf: &mut [u8],
-    ) -> std::io::Result<()> {
+    ) -> crate::fs::error::Result<()> {
         use std::cmp::min;
         let block_size = crate::fs::consts::BLOCK_SIZE as u64;
         let end = offset + buf.len() as u64;
         if end > inode.file_size {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
                 "read beyond file_size",
             ));
         }
+        if inode.is_extent_mapped() {
+            return self.read_extent_range(inode, offset, buf);
+        }
         let mut remaining = buf.len();
         let mut cursor = offset;
         let mut dst_pos = 0;
@@ -476,16 +500,11 @@ impl FileSystem {
             let logical = cursor / block_size;
             let within = (cursor % block_size) as usize;
             let to_take = min(remaining, (block_size as usize) - within);
-            let abs_block = self.get_block(inode, logical).ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::InvalidData, "missing block")
-            })?;
+            // Unallocated blocks are holes and read back as zeros
             let mut block_buf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
-            crate::fs::io::read_block(
-                &mut self.file,
-                crate::fs::consts::BLOCK_SIZE,
-                abs_block as u64,
-                &mut block_buf,
-            )?;
+            if let Some(abs_block) = self.get_block(inode, logical) {
+                self.read_block(abs_block, &mut block_buf)?;
+            }
             buf[dst_pos..dst_pos + to_take].copy_from_slice(&block_buf[within..within + to_take]);
             cursor += to_take as u64;
             dst_pos += to_take;
@@ -494,14 +513,274 @@ impl FileSystem {
         Ok(())
     }
 
-    pub fn write_file_range(
+    pub(crate) fn write_file_range(
         &mut self,
         inode: &mut crate::fs::layout::Inode,
 
         offset: u64,
 
         data: &[u8],
-    ) -> std::io::Result<()> {
+    ) -> crate::fs::error::Result<()> {
+        self.check_access(inode, crate::fs::perm::PERM_WRITE)?;
+
+        if inode.is_extent_mapped() {
+            self.write_extent_range(inode, offset, data)?;
+        } else {
+            self.write_pointer_range(inode, offset, data)?;
+        }
+
+        let new_end = offset + data.len() as u64;
+        if new_end > inode.file_size {
+            inode.file_size = new_end;
+        }
+        let now = crate::fs::layout::now_timestamp();
+        inode.mtime = now;
+        inode.ctime = now;
+        self.write_inode(inode.id, inode)?;
+        Ok(())
+    }
+
+    // Sets the logical size of a regular file. Growing only moves file_size: the new range is
+    // a hole that reads as zeros and takes no space until written. Shrinking releases data
+    // and indirect/extent blocks past the new end.
+    pub(crate) fn truncate(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        new_len: u64,
+    ) -> crate::fs::error::Result<()> {
+        self.check_access(inode, crate::fs::perm::PERM_WRITE)?;
+        if inode.file_type != 0 {
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
+                "not a regular file",
+            ));
+        }
+        let block_size = crate::fs::consts::BLOCK_SIZE as u64;
+        let capacity = if inode.is_extent_mapped() {
+            u32::MAX as u64
+        } else {
+            self.max_file_blocks()
+        };
+        if new_len.div_ceil(block_size) > capacity {
+            return Err(crate::fs::error::ElfsError::FileTooLarge);
+        }
+        self.resize(inode, new_len)?;
+        let now = crate::fs::layout::now_timestamp();
+        inode.mtime = now;
+        inode.ctime = now;
+        self.write_inode(inode.id, inode)
+    }
+
+    // Size change without permission/type checks (directories are compacted through this).
+    // The tail of the last partial block is zeroed so a later extension reads zeros.
+    fn resize(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        new_len: u64,
+    ) -> crate::fs::error::Result<()> {
+        let block_size = crate::fs::consts::BLOCK_SIZE as u64;
+        if new_len < inode.file_size {
+            let keep = new_len.div_ceil(block_size);
+            if inode.is_extent_mapped() {
+                self.release_extents_from(inode, keep)?;
+            } else {
+                self.release_pointers_from(inode, keep)?;
+            }
+            let within = new_len % block_size;
+            if within != 0
+                && let Some(b) = self.get_block(inode, new_len / block_size)
+            {
+                let zeros = vec![0u8; (block_size - within) as usize];
+                self.write_content_at(inode, b as u64 * block_size + within, &zeros)?;
+            }
+        }
+        inode.file_size = new_len;
+        self.write_inode(inode.id, inode)
+    }
+
+    // Frees every block mapped at logical index >= keep (pointer inodes)
+    fn release_pointers_from(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        keep: u64,
+    ) -> crate::fs::error::Result<()> {
+        for (i, b) in inode.single_directs.iter_mut().enumerate() {
+            if i as u64 >= keep && *b != 0 {
+                self.free_block(*b)?;
+                *b = 0;
+            }
+        }
+        let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
+        let mut level_start = 5;
+        for level in 1..=3 {
+            let root = indirect_root(inode, level);
+            if root != 0 && self.prune_tree(root, level, 0, keep.saturating_sub(level_start))? {
+                set_indirect_root(inode, level, 0);
+            }
+            level_start += ptrs_per_block.pow(level as u32);
+        }
+        Ok(())
+    }
+
+    // Drops blocks at relative index >= keep from a pointer tree whose first block has relative
+    // index `base`. Returns true when the whole subtree (block_id included) got freed.
+    fn prune_tree(
+        &mut self,
+        block_id: u32,
+        depth: usize,
+        base: u64,
+        keep: u64,
+    ) -> crate::fs::error::Result<bool> {
+        if depth == 0 {
+            if base >= keep {
+                self.free_block(block_id)?;
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+        let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
+        let span = ptrs_per_block.pow(depth as u32 - 1);
+        let mut raw = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+        self.read_block(block_id, &mut raw)?;
+        let mut changed = false;
+        for (i, chunk) in raw.chunks_exact_mut(4).enumerate() {
+            let child_base = base + i as u64 * span;
+            let p = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            if p == 0 || child_base + span <= keep {
+                continue;
+            }
+            if self.prune_tree(p, depth - 1, child_base, keep)? {
+                chunk.fill(0);
+                changed = true;
+            }
+        }
+        if raw.iter().all(|&b| b == 0) {
+            self.free_block(block_id)?;
+            return Ok(true);
+        }
+        if changed {
+            self.write_block(block_id, &raw)?;
+        }
+        Ok(false)
+    }
+
+    // Frees every block mapped at logical index >= keep (extent inodes)
+    fn release_extents_from(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        keep: u64,
+    ) -> crate::fs::error::Result<()> {
+        let (extents, nodes) = self.load_extents(inode)?;
+        let mut kept = Vec::with_capacity(extents.len());
+        for mut e in extents {
+            let cut = keep.clamp(e.logical as u64, e.logical_end());
+            let keep_len = (cut - e.logical as u64) as u32;
+            for b in e.physical + keep_len..e.physical + e.len {
+                self.free_block(b)?;
+            }
+            if keep_len > 0 {
+                e.len = keep_len;
+                kept.push(e);
+            }
+        }
+        self.store_extents(inode, &kept, nodes)
+    }
+
+    // Blocks actually held by the inode: data blocks plus pointer blocks / extent tree nodes
+    pub(crate) fn allocated_blocks(
+        &mut self,
+        inode: &crate::fs::layout::Inode,
+    ) -> crate::fs::error::Result<u64> {
+        if inode.is_extent_mapped() {
+            let (extents, nodes) = self.load_extents(inode)?;
+            return Ok(extents.iter().map(|e| e.len as u64).sum::<u64>() + nodes.len() as u64);
+        }
+        let mut count = inode.single_directs.iter().filter(|&&b| b != 0).count() as u64;
+        for level in 1..=3 {
+            let root = indirect_root(inode, level);
+            if root != 0 {
+                count += self.count_tree(root, level)?;
+            }
+        }
+        Ok(count)
+    }
+
+    // Blocks in a pointer tree of the given depth, the pointer block itself included
+    fn count_tree(&mut self, block_id: u32, depth: usize) -> crate::fs::error::Result<u64> {
+        let mut count = 1;
+        if depth > 0 {
+            let mut raw = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+            self.read_block(block_id, &mut raw)?;
+            for chunk in raw.chunks_exact(4) {
+                let p = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                if p != 0 {
+                    count += self.count_tree(p, depth - 1)?;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    // Logical block ranges [start, end) backed by data blocks, in order; the rest of the file
+    // is holes
+    fn mapped_ranges(
+        &mut self,
+        inode: &crate::fs::layout::Inode,
+    ) -> crate::fs::error::Result<Vec<(u64, u64)>> {
+        let mut ranges = Vec::new();
+        if inode.is_extent_mapped() {
+            for e in self.load_extents(inode)?.0 {
+                push_range(&mut ranges, e.logical as u64, e.logical_end());
+            }
+            return Ok(ranges);
+        }
+        for (i, &b) in inode.single_directs.iter().enumerate() {
+            if b != 0 {
+                push_range(&mut ranges, i as u64, i as u64 + 1);
+            }
+        }
+        let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
+        let mut level_start = 5;
+        for level in 1..=3 {
+            let root = indirect_root(inode, level);
+            if root != 0 {
+                self.collect_mapped(root, level, level_start, &mut ranges)?;
+            }
+            level_start += ptrs_per_block.pow(level as u32);
+        }
+        Ok(ranges)
+    }
+
+    // mapped_ranges for a pointer tree of the given depth whose first block is `base`
+    fn collect_mapped(
+        &mut self,
+        block_id: u32,
+        depth: usize,
+        base: u64,
+        ranges: &mut Vec<(u64, u64)>,
+    ) -> crate::fs::error::Result<()> {
+        if depth == 0 {
+            push_range(ranges, base, base + 1);
+            return Ok(());
+        }
+        let span = ((crate::fs::consts::BLOCK_SIZE as u64) / 4).pow(depth as u32 - 1);
+        let mut raw = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+        self.read_block(block_id, &mut raw)?;
+        for (i, chunk) in raw.chunks_exact(4).enumerate() {
+            let p = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            if p != 0 {
+                self.collect_mapped(p, depth - 1, base + i as u64 * span, ranges)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Block-by-block write for classic pointer inodes (read-modify-write of partial blocks)
+    fn write_pointer_range(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        offset: u64,
+        data: &[u8],
+    ) -> crate::fs::error::Result<()> {
         use std::cmp::min;
 
         let block_size = crate::fs::consts::BLOCK_SIZE as u64;
@@ -515,16 +794,9 @@ impl FileSystem {
         while remaining > 0 {
             let logical = cursor / block_size;
 
-            // Compute maximum logical block with double-indirect support only
-            let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
-            let max_logical = 5                    // direct
-                + ptrs_per_block                   // single
-                + ptrs_per_block * ptrs_per_block; // double
-            if logical >= max_logical {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "file too large (exceeds double-indirect capacity)",
-                ));
+            // Direct + single + double (+ triple on 128 B inodes)
+            if logical >= self.max_file_blocks() {
+                return Err(crate::fs::error::ElfsError::FileTooLarge);
             }
 
             let within = (cursor % block_size) as usize;
@@ -535,9 +807,9 @@ impl FileSystem {
                 Some(b) => (b, true),
 
                 None => {
-                    let allocated = self.get_or_alloc_block(inode, logical)?.ok_or_else(|| {
-                        std::io::Error::new(std::io::ErrorKind::Other, "no space for block")
-                    })?;
+                    let allocated = self
+                        .get_or_alloc_block(inode, logical)?
+                        .ok_or(crate::fs::error::ElfsError::NoSpace)?;
 
                     (allocated, false)
                 }
@@ -548,383 +820,725 @@ impl FileSystem {
             let mut block_buf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
 
             if existed {
-                crate::fs::io::read_block(
-                    &mut self.file,
-                    crate::fs::consts::BLOCK_SIZE,
-                    abs_block as u64,
-                    &mut block_buf,
-                )?;
+                self.read_block(abs_block, &mut block_buf)?;
             }
 
             block_buf[within..within + to_write]
                 .copy_from_slice(&data[src_pos..src_pos + to_write]);
 
-            crate::fs::io::write_block(
-                &mut self.file,
-                crate::fs::consts::BLOCK_SIZE,
-                abs_block as u64,
-                &block_buf,
-            )?;
+            self.write_content_at(inode, abs_block as u64 * block_size, &block_buf)?;
 
             cursor += to_write as u64;
             src_pos += to_write;
             remaining -= to_write;
         }
-        let new_end = offset + data.len() as u64;
-        if new_end > inode.file_size {
-            inode.file_size = new_end;
-            self.write_inode(inode.id, inode)?;
+        Ok(())
+    }
+
+    // Extent mapping (inodes with INODE_FLAG_EXTENTS)
+    //
+    // The whole extent list is loaded, modified in memory and written back as a tree whose
+    // node blocks are reused where possible. Data is transferred one extent at a time.
+
+    // All extents of the inode (sorted by logical block) and the blocks holding tree nodes
+    fn load_extents(
+        &mut self,
+        inode: &crate::fs::layout::Inode,
+    ) -> crate::fs::error::Result<(Vec<crate::fs::extent::Extent>, Vec<u32>)> {
+        let root = crate::fs::extent::ExtentNode::from_inode(inode);
+        let mut extents = Vec::new();
+        let mut nodes = Vec::new();
+        self.collect_extents(root, &mut extents, &mut nodes)?;
+        Ok((extents, nodes))
+    }
+
+    fn collect_extents(
+        &mut self,
+        node: crate::fs::extent::ExtentNode,
+        extents: &mut Vec<crate::fs::extent::Extent>,
+        nodes: &mut Vec<u32>,
+    ) -> crate::fs::error::Result<()> {
+        if node.depth == 0 {
+            extents.extend(node.entries);
+            return Ok(());
+        }
+        for idx in node.entries {
+            if idx.physical < self.sb.block_start
+                || idx.physical - self.sb.block_start >= self.sb.block_count
+            {
+                return Err(crate::fs::error::ElfsError::Corrupt(
+                    "extent node outside data area",
+                ));
+            }
+            let mut buf = vec![0u8; crate::fs::consts::BLOCK_SIZE as usize];
+            self.read_block(idx.physical, &mut buf)?;
+            let child = crate::fs::extent::ExtentNode::from_block(&buf)
+                .filter(|c| c.depth + 1 == node.depth)
+                .ok_or(crate::fs::error::ElfsError::Corrupt("corrupt extent node"))?;
+            nodes.push(idx.physical);
+            self.collect_extents(child, extents, nodes)?;
         }
         Ok(())
     }
 
-    // Directory operations (direct file content of entries)
+    // Writes `extents` as the inode's tree: up to EXTENT_INLINE_MAX stay in the inode, more
+    // go to leaf blocks indexed from above. `old_nodes` are reused first, leftovers freed.
+    // Only updates the in-memory inode; the caller persists it.
+    fn store_extents(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        extents: &[crate::fs::extent::Extent],
+        old_nodes: Vec<u32>,
+    ) -> crate::fs::error::Result<()> {
+        let mut reuse = old_nodes.into_iter();
+        let mut level = extents.to_vec();
+        let mut depth = 0u16;
+        while level.len() > crate::fs::consts::EXTENT_INLINE_MAX {
+            let mut parents = Vec::new();
+            for chunk in level.chunks(crate::fs::extent::entries_per_block()) {
+                let block = match reuse.next() {
+                    Some(b) => b,
+                    None => self
+                        .alloc_block()
+                        .ok_or(crate::fs::error::ElfsError::NoSpace)?,
+                };
+                let node = crate::fs::extent::ExtentNode {
+                    depth,
+                    entries: chunk.to_vec(),
+                };
+                self.write_block(block, &node.to_block())?;
+                parents.push(crate::fs::extent::Extent {
+                    logical: chunk[0].logical,
+                    physical: block,
+                    len: 0,
+                });
+            }
+            level = parents;
+            depth += 1;
+        }
+        crate::fs::extent::ExtentNode {
+            depth,
+            entries: level,
+        }
+        .write_to_inode(inode);
+        for b in reuse {
+            let _ = self.free_block(b);
+        }
+        Ok(())
+    }
 
-    fn dir_entry_count(&self, dir_inode: &crate::fs::layout::Inode) -> usize {
-        (dir_inode.file_size as usize) / crate::fs::consts::DIR_ENTRY_SIZE
+    // Contiguous allocation of up to `want` blocks, preferably right after `goal`
+    fn alloc_run(&mut self, goal: Option<u32>, want: u32) -> Option<(u32, u32)> {
+        let run = crate::fs::io::alloc_data_run(&mut self.data_bitmap, &self.sb, goal, want)?;
+        self.bitmap_dirty = true;
+        Some(run)
     }
 
-    pub(crate) fn dir_find(
+    fn read_extent_range(
+        &mut self,
+        inode: &crate::fs::layout::Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> crate::fs::error::Result<()> {
+        let block_size = crate::fs::consts::BLOCK_SIZE as u64;
+        let end = offset + buf.len() as u64;
+        let (extents, _) = self.load_extents(inode)?;
+        // Gaps between extents are holes
+        buf.fill(0);
+        for e in extents {
+            let from = offset.max(e.logical as u64 * block_size);
+            let to = end.min(e.logical_end() * block_size);
+            if from >= to {
+                continue;
+            }
+            // One read for the part of this extent inside the range
+            let phys = e.physical as u64 * block_size + (from - e.logical as u64 * block_size);
+            let dst = (from - offset) as usize..(to - offset) as usize;
+            self.read_at(phys, &mut buf[dst])?;
+        }
+        Ok(())
+    }
+
+    fn write_extent_range(
+        &mut self,
+        inode: &mut crate::fs::layout::Inode,
+        offset: u64,
+        data: &[u8],
+    ) -> crate::fs::error::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        // File data goes straight to the device below
+        self.check_writable()?;
+        let block_size = crate::fs::consts::BLOCK_SIZE as u64;
+        let end = offset + data.len() as u64;
+        let first = offset / block_size;
+        let last = (end - 1) / block_size;
+        if last >= u32::MAX as u64 {
+            return Err(crate::fs::error::ElfsError::FileTooLarge);
+        }
+
+        let (mut extents, nodes) = self.load_extents(inode)?;
+        let mut changed = false;
+        let mut result = Ok(());
+        let mut lb = first;
+        while lb <= last {
+            if let Some(e) = extents.iter().find(|e| e.map(lb).is_some()).copied() {
+                // Mapped part: overwrite the bytes in place with a single write
+                let seg_end = e.logical_end().min(last + 1);
+                let from = offset.max(lb * block_size);
+                let to = end.min(seg_end * block_size);
+                let phys = e.physical as u64 * block_size + (from - e.logical as u64 * block_size);
+                let src = (from - offset) as usize..(to - offset) as usize;
+                if let Err(err) = self.dev.write_at(phys, &data[src]) {
+                    result = Err(err.into());
+                    break;
+                }
+                lb = seg_end;
+                continue;
+            }
+
+            // Unmapped gap up to the next extent: allocate as one run if possible,
+            // continuing physically after the extent that ends right here
+            let gap_end = extents
+                .iter()
+                .map(|e| e.logical as u64)
+                .filter(|&l| l > lb)
+                .min()
+                .unwrap_or(u64::MAX)
+                .min(last + 1);
+            let goal = extents
+                .iter()
+                .find(|e| e.logical_end() == lb)
+                .map(|e| e.physical + e.len);
+            let Some((phys, got)) = self.alloc_run(goal, (gap_end - lb) as u32) else {
+                result = Err(crate::fs::error::ElfsError::NoSpace);
+                break;
+            };
+
+            // New blocks are written whole: data where the range covers them, zeros elsewhere
+            let run_start = lb * block_size;
+            let mut buf = vec![0u8; (got as u64 * block_size) as usize];
+            let from = offset.max(run_start);
+            let to = end.min(run_start + buf.len() as u64);
+            buf[(from - run_start) as usize..(to - run_start) as usize]
+                .copy_from_slice(&data[(from - offset) as usize..(to - offset) as usize]);
+            crate::fs::extent::insert_extent(
+                &mut extents,
+                crate::fs::extent::Extent {
+                    logical: lb as u32,
+                    physical: phys,
+                    len: got,
+                },
+            );
+            changed = true;
+            if let Err(err) = crate::fs::io::write_span(
+                self.dev.as_mut(),
+                phys as u64,
+                got as u64,
+                crate::fs::consts::BLOCK_SIZE,
+                &buf,
+            ) {
+                result = Err(err.into());
+                break;
+            }
+            lb += got as u64;
+        }
+
+        // Keep whatever got mapped, even after a failure, so free_inode can release it
+        if changed {
+            self.store_extents(inode, &extents, nodes)?;
+            self.write_inode(inode.id, inode)?;
+        }
+        result
+    }
+
+    // Directory operations (direct file content of entries)
+    //
+    // Entries are addressed by their byte offset inside the directory. Images with
+    // FEATURE_LONG_NAMES use variable-length records, older ones fixed 16 B slots.
+
+    // Longest entry name the directory format of this image can store
+    pub fn max_name_len(&self) -> usize {
+        if self.sb.has_long_names() {
+            crate::fs::consts::DIR_NAME_MAX_LEN
+        } else {
+            crate::fs::consts::DIR_NAME_LEN
+        }
+    }
+
+    // Parses all records (used and free) of a directory: (offset, record length, entry)
+    fn dir_records(
         &mut self,
         dir_inode: &crate::fs::layout::Inode,
-        name: &str,
-    ) -> std::io::Result<Option<(usize, crate::fs::layout::DirectoryEntry)>> {
+    ) -> crate::fs::error::Result<Vec<(u64, usize, crate::fs::layout::DirectoryEntry)>> {
         if dir_inode.file_type != 1 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "not a directory",
-            ));
+            return Err(crate::fs::error::ElfsError::NotADirectory);
         }
-        let slots = self.dir_entry_count(dir_inode);
-        let mut slot_buf = vec![0u8; crate::fs::consts::DIR_ENTRY_SIZE];
-        for i in 0..slots {
-            self.read_file_range(
-                dir_inode,
-                (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
-                &mut slot_buf,
-            )?;
-            let entry = crate::fs::layout::DirectoryEntry::deserialize(&slot_buf);
-            if !entry.is_unused() {
-                let end = entry
-                    .name
-                    .iter()
-                    .position(|&b| b == 0)
-                    .unwrap_or(crate::fs::consts::DIR_NAME_LEN);
-                let entry_name = std::str::from_utf8(&entry.name[..end]).unwrap_or("");
-                if entry_name == name {
-                    return Ok(Some((i, entry)));
-                }
+        let mut raw = vec![0u8; dir_inode.file_size as usize];
+        self.read_range(dir_inode, 0, &mut raw)?;
+
+        let mut records = Vec::new();
+        if self.sb.has_long_names() {
+            let mut off = 0;
+            while off < raw.len() {
+                let (entry, rec_len) =
+                    crate::fs::layout::DirectoryEntry::deserialize_var(&raw[off..]).ok_or(
+                        crate::fs::error::ElfsError::Corrupt("corrupt directory record"),
+                    )?;
+                records.push((off as u64, rec_len, entry));
+                off += rec_len;
+            }
+        } else {
+            for (i, slot) in raw
+                .chunks_exact(crate::fs::consts::DIR_ENTRY_SIZE)
+                .enumerate()
+            {
+                let entry = crate::fs::layout::DirectoryEntry::deserialize(slot);
+                records.push((
+                    (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
+                    crate::fs::consts::DIR_ENTRY_SIZE,
+                    entry,
+                ));
             }
         }
-        Ok(None)
+        Ok(records)
+    }
+
+    // Serialized form of `entry` for a record of `rec_len` bytes in this image's format
+    fn encode_dir_record(
+        &self,
+        entry: &crate::fs::layout::DirectoryEntry,
+        rec_len: usize,
+    ) -> Vec<u8> {
+        let mut buf = vec![0u8; rec_len];
+        if self.sb.has_long_names() {
+            entry.serialize_var(&mut buf);
+        } else {
+            entry.serialize(&mut buf);
+        }
+        buf
+    }
+
+    // Lists used entries of a directory without "." and ".." (needs read permission, counts as access)
+    pub(crate) fn read_dir(
+        &mut self,
+        dir_inode: &mut crate::fs::layout::Inode,
+    ) -> crate::fs::error::Result<Vec<crate::fs::layout::DirectoryEntry>> {
+        self.check_access(dir_inode, crate::fs::perm::PERM_READ)?;
+        let entries = self
+            .dir_records(dir_inode)?
+            .into_iter()
+            .filter(|(_, _, e)| !e.is_unused() && !is_dot_name(&e.name))
+            .map(|(_, _, e)| e)
+            .collect();
+        self.touch_atime(dir_inode)?;
+        Ok(entries)
     }
 
+    // Looks up `name`; returns the entry and its byte offset in the directory
+    pub(crate) fn dir_find(
+        &mut self,
+        dir_inode: &crate::fs::layout::Inode,
+        name: &str,
+    ) -> crate::fs::error::Result<Option<(usize, crate::fs::layout::DirectoryEntry)>> {
+        Ok(self
+            .dir_records(dir_inode)?
+            .into_iter()
+            .find(|(_, _, e)| !e.is_unused() && e.name == name)
+            .map(|(off, _, e)| (off as usize, e)))
+    }
+
+    // Empty = nothing but "." and ".."
     pub(crate) fn dir_is_empty(
         &mut self,
         dir_inode: &crate::fs::layout::Inode,
-    ) -> std::io::Result<bool> {
-        let slots = self.dir_entry_count(dir_inode);
-        let mut slot_buf = vec![0u8; crate::fs::consts::DIR_ENTRY_SIZE];
-        for i in 0..slots {
-            self.read_file_range(
-                dir_inode,
-                (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
-                &mut slot_buf,
-            )?;
-            let entry = crate::fs::layout::DirectoryEntry::deserialize(&slot_buf);
-            if !entry.is_unused() {
-                return Ok(false);
-            }
+    ) -> crate::fs::error::Result<bool> {
+        Ok(self
+            .dir_records(dir_inode)?
+            .iter()
+            .all(|(_, _, e)| e.is_unused() || is_dot_name(&e.name)))
+    }
+
+    // Writes the "." and ".." entries of a freshly created directory.
+    // Images formatted without FEATURE_DOT_ENTRIES keep directories free of them.
+    pub(crate) fn init_dir(
+        &mut self,
+        dir_inode: &mut crate::fs::layout::Inode,
+        parent_id: u32,
+    ) -> crate::fs::error::Result<()> {
+        if !self.sb.has_dot_entries() {
+            return Ok(());
         }
-        Ok(true)
+        let self_id = dir_inode.id;
+        self.dir_add_entry(dir_inode, ".", self_id)?;
+        self.dir_add_entry(dir_inode, "..", parent_id)
     }
 
-    pub fn dir_add_entry(
+    // Repoints an existing entry to another inode in place (used for ".." after a move)
+    pub(crate) fn dir_set_entry(
         &mut self,
         dir_inode: &mut crate::fs::layout::Inode,
         name: &str,
         inode_id: u32,
-    ) -> std::io::Result<()> {
-        if name.is_empty() || name.len() > crate::fs::consts::DIR_NAME_LEN {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "invalid name length",
-            ));
-        }
-        if let Some(_) = self.dir_find(dir_inode, name)? {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::AlreadyExists,
-                "entry exists",
-            ));
+    ) -> crate::fs::error::Result<()> {
+        self.check_access(
+            dir_inode,
+            crate::fs::perm::PERM_WRITE | crate::fs::perm::PERM_EXEC,
+        )?;
+        let found = self
+            .dir_records(dir_inode)?
+            .into_iter()
+            .find(|(_, _, e)| !e.is_unused() && e.name == name);
+        let Some((offset, rec_len, mut entry)) = found else {
+            return Err(crate::fs::error::ElfsError::NotFound);
+        };
+
+        entry.inode_id = inode_id;
+        let buf = self.encode_dir_record(&entry, rec_len);
+        self.write_file_range(dir_inode, offset, &buf)?;
+        self.write_inode(dir_inode.id, dir_inode)?;
+        Ok(())
+    }
+
+    pub(crate) fn dir_add_entry(
+        &mut self,
+        dir_inode: &mut crate::fs::layout::Inode,
+        name: &str,
+        inode_id: u32,
+    ) -> crate::fs::error::Result<()> {
+        self.check_access(
+            dir_inode,
+            crate::fs::perm::PERM_WRITE | crate::fs::perm::PERM_EXEC,
+        )?;
+        if name.is_empty() {
+            return Err(crate::fs::error::ElfsError::InvalidName);
         }
-        let slots = self.dir_entry_count(dir_inode);
-        let mut slot_buf = vec![0u8; crate::fs::consts::DIR_ENTRY_SIZE];
-        // Try free slot
-        for i in 0..slots {
-            self.read_file_range(
-                dir_inode,
-                (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
-                &mut slot_buf,
-            )?;
-            let entry = crate::fs::layout::DirectoryEntry::deserialize(&slot_buf);
-            if entry.is_unused() {
-                let new_e = crate::fs::layout::DirectoryEntry::from_name(name, inode_id).unwrap();
-                new_e.serialize(&mut slot_buf);
-                self.write_file_range(
-                    dir_inode,
-                    (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
-                    &slot_buf,
-                )?;
-                self.write_inode(dir_inode.id, dir_inode)?;
-                return Ok(());
-            }
+        if name.len() > self.max_name_len() {
+            return Err(crate::fs::error::ElfsError::NameTooLong);
         }
-        // Append new slot
-        let new_e = crate::fs::layout::DirectoryEntry::from_name(name, inode_id).unwrap();
-        new_e.serialize(&mut slot_buf);
+        let new_e = crate::fs::layout::DirectoryEntry::from_name(name, inode_id)
+            .map_err(|_| crate::fs::error::ElfsError::NameTooLong)?;
+        let needed = if self.sb.has_long_names() {
+            crate::fs::layout::DirectoryEntry::var_record_len(name.len())
+        } else {
+            crate::fs::consts::DIR_ENTRY_SIZE
+        };
 
-        let offset = dir_inode.file_size;
+        let records = self.dir_records(dir_inode)?;
+        if records
+            .iter()
+            .any(|(_, _, e)| !e.is_unused() && e.name == name)
+        {
+            return Err(crate::fs::error::ElfsError::AlreadyExists);
+        }
 
-        self.write_file_range(dir_inode, offset, &slot_buf)?;
+        // Try a free record large enough for the name, otherwise append a new one
+        let (offset, rec_len) = records
+            .iter()
+            .find(|(_, len, e)| e.is_unused() && *len >= needed)
+            .map(|(off, len, _)| (*off, *len))
+            .unwrap_or((dir_inode.file_size, needed));
 
-        // write_file_range updates file_size; avoid double increment
+        let buf = self.encode_dir_record(&new_e, rec_len);
+        // write_file_range updates file_size when appending
+        self.write_file_range(dir_inode, offset, &buf)?;
         self.write_inode(dir_inode.id, dir_inode)?;
 
         Ok(())
     }
 
-    pub fn dir_remove_entry(
+    pub(crate) fn dir_remove_entry(
         &mut self,
         dir_inode: &mut crate::fs::layout::Inode,
         name: &str,
-    ) -> std::io::Result<()> {
-        let slots = self.dir_entry_count(dir_inode);
-        let mut slot_buf = vec![0u8; crate::fs::consts::DIR_ENTRY_SIZE];
-        for i in 0..slots {
-            self.read_file_range(
-                dir_inode,
-                (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
-                &mut slot_buf,
-            )?;
-            let mut entry = crate::fs::layout::DirectoryEntry::deserialize(&slot_buf);
-            let end = entry
-                .name
-                .iter()
-                .position(|&b| b == 0)
-                .unwrap_or(crate::fs::consts::DIR_NAME_LEN);
-            let entry_name = std::str::from_utf8(&entry.name[..end]).unwrap_or("");
-            if !entry.is_unused() && entry_name == name {
-                entry.mark_unused();
-                entry.serialize(&mut slot_buf);
-                self.write_file_range(
-                    dir_inode,
-                    (i * crate::fs::consts::DIR_ENTRY_SIZE) as u64,
-                    &slot_buf,
-                )?;
-                self.write_inode(dir_inode.id, dir_inode)?;
-                return Ok(());
-            }
+    ) -> crate::fs::error::Result<()> {
+        self.check_access(
+            dir_inode,
+            crate::fs::perm::PERM_WRITE | crate::fs::perm::PERM_EXEC,
+        )?;
+        let found = self
+            .dir_records(dir_inode)?
+            .into_iter()
+            .find(|(_, _, e)| !e.is_unused() && e.name == name);
+        let Some((offset, rec_len, mut entry)) = found else {
+            return Err(crate::fs::error::ElfsError::NotFound);
+        };
+
+        // Keep the record (and its rec_len) so the space can be reused later
+        entry.mark_unused();
+        let buf = self.encode_dir_record(&entry, rec_len);
+        self.write_file_range(dir_inode, offset, &buf)?;
+
+        // Free records at the end of the directory are cut off (releasing emptied blocks)
+        let used_end = self
+            .dir_records(dir_inode)?
+            .iter()
+            .filter(|(_, _, e)| !e.is_unused())
+            .map(|(off, len, _)| off + *len as u64)
+            .max()
+            .unwrap_or(0);
+        if used_end < dir_inode.file_size {
+            self.resize(dir_inode, used_end)?;
         }
-        Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "entry not found",
-        ))
+        self.write_inode(dir_inode.id, dir_inode)?;
+        Ok(())
+    }
+
+    // Hard link: adds another directory entry for an existing inode and bumps its link_count.
+    pub(crate) fn link(
+        &mut self,
+        dir_inode: &mut crate::fs::layout::Inode,
+        name: &str,
+        inode_id: u32,
+    ) -> crate::fs::error::Result<()> {
+        let mut target = self.read_inode(inode_id)?;
+        if target.link_count == 0 {
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
+                "inode is not in use",
+            ));
+        }
+        if target.file_type == 1 {
+            return Err(crate::fs::error::ElfsError::IsADirectory);
+        }
+        if target.link_count == u8::MAX {
+            return Err(crate::fs::error::ElfsError::TooManyLinks);
+        }
+
+        self.dir_add_entry(dir_inode, name, inode_id)?;
+
+        target.link_count += 1;
+        target.ctime = crate::fs::layout::now_timestamp();
+        if let Err(e) = self.write_inode(inode_id, &target) {
+            let _ = self.dir_remove_entry(dir_inode, name);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Changes permission bits; only the owner or root may do so.
+    pub fn chmod(&mut self, inode_id: u32, mode: u16) -> crate::fs::error::Result<()> {
+        if !self.sb.has_permissions() {
+            return Err(crate::fs::error::ElfsError::Unsupported("permissions"));
+        }
+        let mut inode = self.read_inode(inode_id)?;
+        if !self.creds.is_root() && self.creds.uid != inode.uid {
+            return Err(crate::fs::error::ElfsError::PermissionDenied);
+        }
+        inode.mode = mode & 0o777;
+        inode.ctime = crate::fs::layout::now_timestamp();
+        self.write_inode(inode_id, &inode)
+    }
+
+    // Changes owner and/or group; reserved for root.
+    pub fn chown(
+        &mut self,
+        inode_id: u32,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> crate::fs::error::Result<()> {
+        if !self.sb.has_permissions() {
+            return Err(crate::fs::error::ElfsError::Unsupported("ownership"));
+        }
+        if !self.creds.is_root() {
+            return Err(crate::fs::error::ElfsError::PermissionDenied);
+        }
+        let mut inode = self.read_inode(inode_id)?;
+        if let Some(uid) = uid {
+            inode.uid = uid;
+        }
+        if let Some(gid) = gid {
+            inode.gid = gid;
+        }
+        inode.ctime = crate::fs::layout::now_timestamp();
+        self.write_inode(inode_id, &inode)
     }
 
     // Symlink target reader (returns UTF-8 path stored in the symlink inode)
 
-    pub fn readlink_target(&mut self, inode_id: u32) -> std::io::Result<String> {
-        let inode = self.read_inode(inode_id)?;
+    pub(crate) fn readlink_target(&mut self, inode_id: u32) -> crate::fs::error::Result<String> {
+        let mut inode = self.read_inode(inode_id)?;
 
         if inode.file_type != 2 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "not a symlink",
-            ));
+            return Err(crate::fs::error::ElfsError::NotASymlink);
         }
 
         let size = inode.file_size as usize;
 
         let mut buf = vec![0u8; size];
 
-        self.read_file_range(&inode, 0, &mut buf)?;
+        self.read_file_range(&mut inode, 0, &mut buf)?;
 
         Ok(String::from_utf8_lossy(&buf).into_owned())
     }
 
-    // Public path resolver using parent stack; returns final inode id.
-    pub fn resolve_path(&mut self, path: &str) -> std::io::Result<u32> {
-        let (id, _) = self.resolve_path_with_stack(path)?;
-        Ok(id)
-    }
-
-    // Resolve path and return also resulting parent stack.
-    fn resolve_path_with_stack(&mut self, path: &str) -> std::io::Result<(u32, Vec<u32>)> {
+    // Public path resolver; returns final inode id.
+    pub fn resolve_path(&mut self, path: &str) -> crate::fs::error::Result<u32> {
         let max_depth = 16;
 
         if path.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "empty path",
-            ));
+            return Err(crate::fs::error::ElfsError::InvalidName);
         }
 
-        let mut comps: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
-        let is_abs = path.starts_with('/');
-        let mut current_id = if is_abs {
+        let comps: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let current_id = if path.starts_with('/') {
             self.sb.root_inode_id
         } else {
             self.cwd_inode
         };
 
-        let mut parents: Vec<u32> = if is_abs {
-            Vec::new()
-        } else {
-            self.cwd_stack.clone()
-        };
-
-        self.resolve_components_with_stack(current_id, &mut parents, &mut comps, 0, max_depth)
-            .map(|final_id| (final_id, parents))
+        self.resolve_components(current_id, &comps, 0, max_depth)
     }
 
     // Resolve parent and final name component (does not require that final exists).
-    pub fn resolve_parent_and_name(&mut self, path: &str) -> std::io::Result<(u32, String)> {
+    pub(crate) fn resolve_parent_and_name(
+        &mut self,
+        path: &str,
+    ) -> crate::fs::error::Result<(u32, String)> {
         if path.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "empty path",
-            ));
+            return Err(crate::fs::error::ElfsError::InvalidName);
         }
         let mut comps: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
 
         if comps.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "missing name",
-            ));
+            return Err(crate::fs::error::ElfsError::InvalidName);
         }
 
         // Globální normalizace: finální komponenta nesmí být "." ani ".." – ty se chovají
         // jako traversal, ne jako jméno. Pokud cesta končí na "." nebo "..", považujeme ji
         // za neplatnou pro účely získání jména (tj. chybí cílové jméno souboru/diru).
-        if let Some(last) = comps.last() {
-            if *last == "." || *last == ".." {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "missing name",
-                ));
-            }
+        if let Some(last) = comps.last()
+            && (*last == "." || *last == "..")
+        {
+            return Err(crate::fs::error::ElfsError::InvalidName);
         }
 
         let name = comps.pop().unwrap().to_string();
-        let is_abs = path.starts_with('/');
-        let mut parents: Vec<u32> = if is_abs {
-            Vec::new()
-        } else {
-            self.cwd_stack.clone()
-        };
-        let start = if is_abs {
+        let start = if path.starts_with('/') {
             self.sb.root_inode_id
         } else {
             self.cwd_inode
         };
         let max_depth = 16;
 
-        // Normalizace zbylých komponent ('.' a '..') před delegací na resolver.
-        // Lokálně odstraníme '.'; '..' se pokusíme zpracovat popem předchozí komponenty.
-        // Pokud není co odebrat (nebo předchozí je už '..'), ponecháme '..' pro resolver,
-        // který správně upraví parents stack (umožní vícenásobné výstupy vzhůru).
-        let mut normalized: Vec<&str> = Vec::new();
-        for c in comps {
-            if c == "." {
-                continue;
-            } else if c == ".." {
-                if let Some(last) = normalized.last() {
-                    if *last != ".." {
-                        normalized.pop();
-                        continue;
-                    }
-                }
-                normalized.push("..");
-            } else {
-                normalized.push(c);
-            }
-        }
-
-        let parent_id =
-            self.resolve_components_with_stack(start, &mut parents, &mut normalized, 0, max_depth)?;
+        // '..' uvnitř cesty řeší resolver přes uložené položky (fyzicky, ne lexikálně)
+        let parent_id = self.resolve_components(start, &comps, 0, max_depth)?;
 
         Ok((parent_id, name))
     }
 
-    pub(crate) fn cd(&mut self, path: &str) -> std::io::Result<()> {
-        let (id, stack) = self.resolve_path_with_stack(path)?;
+    /// Changes the directory relative paths start from.
+    pub fn cd(&mut self, path: &str) -> crate::fs::error::Result<()> {
+        let id = self.resolve_path(path)?;
 
         let inode = self.read_inode(id)?;
 
         if inode.file_type != 1 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "not a directory",
-            ));
+            return Err(crate::fs::error::ElfsError::NotADirectory);
         }
 
-        // Reconstruct path string from stack + current
-        if id == self.sb.root_inode_id {
-            self.cwd_path = "/".to_string();
-        } else {
-            // We only have inode IDs in stack; simplest approach: if we cannot map IDs back to names,
-            // fall back to the provided input path normalization:
-            // If absolute -> normalize; if relative -> append to existing and re-normalize.
-            let mut new_path = if path.starts_with('/') {
-                path.to_string()
-            } else {
-                if self.cwd_path.ends_with('/') {
-                    format!("{}{}", self.cwd_path, path)
-                } else {
-                    format!("{}/{}", self.cwd_path, path)
-                }
-            };
-            // Normalize: collapse //, /./, and trailing / (except root)
-            let mut parts = Vec::new();
-            for comp in new_path.split('/') {
-                if comp.is_empty() || comp == "." {
+        // The path string is rebuilt from the directory tree, so symlinks and ".." in the
+        // input never leak into it
+        self.cwd_path = self.path_of(id)?;
+        self.cwd_inode = id;
+        Ok(())
+    }
+
+    // Parent directory of `dir_id` (the root is its own parent).
+    // Reads the ".." entry; images without dot entries fall back to searching from root.
+    pub(crate) fn parent_of(&mut self, dir_id: u32) -> crate::fs::error::Result<u32> {
+        if dir_id == self.sb.root_inode_id {
+            return Ok(dir_id);
+        }
+        let dir = self.read_inode(dir_id)?;
+        if self.sb.has_dot_entries()
+            && let Some((_, entry)) = self.dir_find(&dir, "..")?
+        {
+            return Ok(entry.inode_id);
+        }
+        self.scan_parent(dir_id)
+    }
+
+    // Breadth-first search from root for the directory containing `dir_id`
+    fn scan_parent(&mut self, dir_id: u32) -> crate::fs::error::Result<u32> {
+        let mut queue = std::collections::VecDeque::from([self.sb.root_inode_id]);
+        let mut visited = std::collections::HashSet::new();
+        while let Some(id) = queue.pop_front() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let dir = self.read_inode(id)?;
+            for (_, _, entry) in self.dir_records(&dir)? {
+                if entry.is_unused() || is_dot_name(&entry.name) {
                     continue;
                 }
-                if comp == ".." {
-                    if !parts.is_empty() {
-                        parts.pop();
-                    }
-                    continue;
+                if entry.inode_id == dir_id {
+                    return Ok(id);
+                }
+                if self.read_inode(entry.inode_id)?.file_type == 1 {
+                    queue.push_back(entry.inode_id);
                 }
-                parts.push(comp);
             }
-            if parts.is_empty() {
-                new_path = "/".to_string();
-            } else {
-                new_path = format!("/{}", parts.join("/"));
+        }
+        Err(crate::fs::error::ElfsError::Corrupt(
+            "directory is not reachable from root",
+        ))
+    }
+
+    // Absolute path of a directory, built by walking ".." up to the root
+    pub(crate) fn path_of(&mut self, dir_id: u32) -> crate::fs::error::Result<String> {
+        let mut names = Vec::new();
+        let mut id = dir_id;
+        while id != self.sb.root_inode_id {
+            let parent_id = self.parent_of(id)?;
+            let parent = self.read_inode(parent_id)?;
+            let entry = self
+                .dir_records(&parent)?
+                .into_iter()
+                .map(|(_, _, e)| e)
+                .find(|e| !e.is_unused() && !is_dot_name(&e.name) && e.inode_id == id)
+                .ok_or(crate::fs::error::ElfsError::Corrupt(
+                    "directory has no entry in its parent",
+                ))?;
+            names.push(entry.name);
+            if names.len() > self.sb.inode_count as usize {
+                return Err(crate::fs::error::ElfsError::Corrupt("directory loop"));
             }
-            self.cwd_path = new_path;
+            id = parent_id;
         }
-        self.cwd_inode = id;
-        self.cwd_stack = stack;
-        Ok(())
+        names.reverse();
+        Ok(format!("/{}", names.join("/")))
+    }
+
+    // True when `dir_id` is `ancestor` itself or lies somewhere below it
+    pub(crate) fn is_ancestor(
+        &mut self,
+        ancestor: u32,
+        dir_id: u32,
+    ) -> crate::fs::error::Result<bool> {
+        let mut id = dir_id;
+        for _ in 0..=self.sb.inode_count {
+            if id == ancestor {
+                return Ok(true);
+            }
+            if id == self.sb.root_inode_id {
+                return Ok(false);
+            }
+            id = self.parent_of(id)?;
+        }
+        Err(crate::fs::error::ElfsError::Corrupt("directory loop"))
     }
 
-    // Core resolver that traverses components with a parent stack and symlink expansion.
-    fn resolve_components_with_stack(
+    // Core resolver that traverses components with symlink expansion.
+    fn resolve_components(
         &mut self,
         mut current_id: u32,
-        parents: &mut Vec<u32>,
-        comps: &mut Vec<&str>,
+        comps: &[&str],
         depth: usize,
         max_depth: usize,
-    ) -> std::io::Result<u32> {
+    ) -> crate::fs::error::Result<u32> {
         if depth > max_depth {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "symlink depth exceeded",
-            ));
+            return Err(crate::fs::error::ElfsError::SymlinkLoop);
         }
 
         let mut idx = 0;
@@ -935,22 +1549,18 @@ impl FileSystem {
                 idx += 1;
                 continue;
             }
-            if comp == ".." {
-                if let Some(parent) = parents.pop() {
-                    current_id = parent;
-                } else {
-                    current_id = self.sb.root_inode_id;
-                }
-                idx += 1;
-                continue;
-            }
 
             let cur_inode = self.read_inode(current_id)?;
             if cur_inode.file_type != 1 {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "not a directory",
-                ));
+                return Err(crate::fs::error::ElfsError::NotADirectory);
+            }
+            // Searching a directory requires execute permission
+            self.check_access(&cur_inode, crate::fs::perm::PERM_EXEC)?;
+
+            if comp == ".." {
+                current_id = self.parent_of(current_id)?;
+                idx += 1;
+                continue;
             }
 
             match self.dir_find(&cur_inode, comp)? {
@@ -962,41 +1572,25 @@ impl FileSystem {
                     if next_inode.file_type == 2 {
                         let target = self.readlink_target(next_id)?;
 
-                        let remaining: Vec<&str> = comps[idx + 1..].iter().copied().collect();
+                        let remaining: Vec<&str> = comps[idx + 1..].to_vec();
                         let mut new_comps: Vec<&str> =
                             target.split('/').filter(|c| !c.is_empty()).collect();
 
                         new_comps.extend(remaining);
-                        if target.starts_with('/') {
-                            parents.clear();
-
-                            return self.resolve_components_with_stack(
-                                self.sb.root_inode_id,
-                                parents,
-                                &mut new_comps,
-                                depth + 1,
-                                max_depth,
-                            );
+                        // Relative targets continue from the directory holding the link
+                        let start = if target.starts_with('/') {
+                            self.sb.root_inode_id
                         } else {
-                            return self.resolve_components_with_stack(
-                                current_id,
-                                parents,
-                                &mut new_comps,
-                                depth + 1,
-                                max_depth,
-                            );
-                        }
+                            current_id
+                        };
+                        return self.resolve_components(start, &new_comps, depth + 1, max_depth);
                     } else {
-                        parents.push(current_id);
                         current_id = next_id;
                         idx += 1;
                     }
                 }
                 None => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        "component not found",
-                    ));
+                    return Err(crate::fs::error::ElfsError::NotFound);
                 }
             }
         }
@@ -1004,7 +1598,398 @@ impl FileSystem {
         Ok(current_id)
     }
 
-    pub(crate) fn pwd(&self) -> &str {
+    /// Absolute path of the working directory.
+    pub fn pwd(&self) -> &str {
         &self.cwd_path
     }
 }
+
+// Data and inode bitmap as stored in the image. Images formatted before the inode bitmap
+// existed get an in-memory one built from a scan.
+fn load_bitmaps(fs: &mut FileSystem) -> crate::fs::error::Result<(Vec<u8>, Vec<u8>)> {
+    use crate::fs::io::{load_bitmap, load_inode_bitmap, scan_inode_bitmap};
+    let data_bitmap = load_bitmap(fs.dev.as_mut(), &fs.sb)?;
+    let inode_bitmap = if fs.sb.inode_bitmap_count == 0 {
+        scan_inode_bitmap(fs.dev.as_mut(), &fs.sb)?
+    } else {
+        load_inode_bitmap(fs.dev.as_mut(), &fs.sb)?
+    };
+    Ok((data_bitmap, inode_bitmap))
+}
+
+// Indirect level (1 = single, 2 = double, 3 = triple) of a logical block past the direct
+// pointers, plus its index relative to the start of that level's range
+fn indirect_position(logical: u64) -> Option<(usize, u64)> {
+    let ptrs_per_block = (crate::fs::consts::BLOCK_SIZE as u64) / 4;
+    let mut rel = logical.checked_sub(5)?;
+    for level in 1..=3 {
+        let span = ptrs_per_block.pow(level);
+        if rel < span {
+            return Some((level as usize, rel));
+        }
+        rel -= span;
+    }
+    None
+}
+
+// Appends [start, end) to sorted ranges, merging it with the last one when they touch
+fn push_range(ranges: &mut Vec<(u64, u64)>, start: u64, end: u64) {
+    match ranges.last_mut() {
+        Some(last) if last.1 == start => last.1 = end,
+        _ => ranges.push((start, end)),
+    }
+}
+
+fn indirect_root(inode: &crate::fs::layout::Inode, level: usize) -> u32 {
+    match level {
+        1 => inode.single_indirect,
+        2 => inode.double_indirect,
+        _ => inode.triple_indirect,
+    }
+}
+
+fn set_indirect_root(inode: &mut crate::fs::layout::Inode, level: usize, block: u32) {
+    match level {
+        1 => inode.single_indirect = block,
+        2 => inode.double_indirect = block,
+        _ => inode.triple_indirect = block,
+    }
+}
+
+// "." and ".." are bookkeeping entries, not directory contents
+fn is_dot_name(name: &str) -> bool {
+    name == "." || name == ".."
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::error::ElfsError;
+    use crate::fs::io::bitmap_is_set;
+    use crate::fs::layout::now_timestamp;
+    use crate::fs::perm::Credentials;
+    use crate::testing::{IMAGE_SIZE, image, pointer_file};
+    use std::io::{Read, Write};
+
+    const BS: usize = crate::fs::consts::BLOCK_SIZE as usize;
+
+    fn denied<T>(result: crate::fs::error::Result<T>) -> bool {
+        matches!(result, Err(ElfsError::PermissionDenied))
+    }
+
+    #[test]
+    fn hard_link_keeps_data_until_last_name() {
+        let mut fs = image();
+        let free_before = fs.data_bitmap().to_vec();
+        let data = vec![7u8; 3 * BS];
+        fs.write_file("/a", &data).unwrap();
+        let id = fs.resolve_path("/a").unwrap();
+
+        let mut root = fs.read_inode(fs.sb.root_inode_id).unwrap();
+        fs.link(&mut root, "b", id).unwrap();
+        assert_eq!(fs.stat("/b").unwrap().inode, id);
+        assert_eq!(fs.stat("/a").unwrap().links, 2);
+
+        // Removing one name leaves the data reachable through the other
+        fs.remove_file("/a").unwrap();
+        assert_eq!(fs.read_file("/b").unwrap(), data);
+        assert_eq!(fs.stat("/b").unwrap().links, 1);
+        assert!(bitmap_is_set(fs.inode_bitmap(), id));
+        assert!(fs.check(false).unwrap().is_clean());
+
+        // The last name takes the blocks and the inode with it
+        fs.remove_file("/b").unwrap();
+        assert!(!bitmap_is_set(fs.inode_bitmap(), id));
+        assert_eq!(fs.data_bitmap(), free_before);
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn inode_bitmap_survives_reopen() {
+        let dev = crate::fs::device::FailingDevice::new(IMAGE_SIZE);
+        let mut fs = FileSystem::format(dev.clone(), IMAGE_SIZE).unwrap();
+        let a = fs.create_file("/a").unwrap();
+        let b = fs.create_dir("/b").unwrap();
+        let c = fs.create_file("/c").unwrap();
+        fs.remove_file("/a").unwrap();
+        let stored = fs.inode_bitmap().to_vec();
+        drop(fs);
+
+        let mut fs = FileSystem::open(dev.image()).unwrap();
+        assert_eq!(fs.inode_bitmap(), stored);
+        assert!(!bitmap_is_set(fs.inode_bitmap(), a));
+        assert!(bitmap_is_set(fs.inode_bitmap(), b));
+        assert!(bitmap_is_set(fs.inode_bitmap(), c));
+        // The freed id is handed out again, the ones in use are not
+        assert_eq!(fs.create_file("/d").unwrap(), a);
+        let e = fs.create_file("/e").unwrap();
+        assert!(e != b && e != c);
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn timestamps_and_relatime() {
+        let mut fs = image();
+        let before = now_timestamp();
+        fs.write_file("/f", b"data").unwrap();
+        let meta = fs.stat("/f").unwrap();
+        assert!(before <= meta.crtime && meta.crtime <= meta.mtime);
+        assert_eq!(meta.mtime, meta.ctime);
+        let id = meta.inode;
+
+        let day = 24 * 60 * 60;
+        let now = now_timestamp();
+        let set_times = |fs: &mut FileSystem, changed: u64, accessed: u64| {
+            let mut inode = fs.read_inode(id).unwrap();
+            (inode.mtime, inode.ctime, inode.atime) = (changed, changed, accessed);
+            fs.write_inode(id, &inode).unwrap();
+        };
+        // (mtime and ctime, atime) before a read -> whether the read moves atime
+        for (changed, accessed, touched) in [
+            (now - 10, now - 20, true),
+            (now - 20, now - 10, false),
+            (now - 3 * day, now - 2 * day, true),
+        ] {
+            set_times(&mut fs, changed, accessed);
+            fs.read_file("/f").unwrap();
+            let atime = fs.stat("/f").unwrap().atime;
+            assert_eq!(atime >= now, touched, "mtime {changed}, atime {accessed}");
+        }
+
+        // A handle writing after a read keeps the atime the read set
+        set_times(&mut fs, now - 10, now - 20);
+        {
+            let mut f = fs
+                .open_file("/f", OpenOptions::new().read(true).write(true))
+                .unwrap();
+            f.read_exact(&mut [0u8; 4]).unwrap();
+            f.write_all(b"more").unwrap();
+        }
+        let meta = fs.stat("/f").unwrap();
+        assert_eq!(meta.size, 8);
+        assert!(meta.atime >= now && meta.mtime >= now);
+    }
+
+    #[test]
+    fn permissions_deny_other_users() {
+        let mut fs = image();
+        let private = fs.create_dir("/private").unwrap();
+        fs.write_file("/private/f", b"secret").unwrap();
+        fs.chmod(private, 0o700).unwrap();
+        let shared = fs.create_dir("/shared").unwrap();
+        fs.chown(shared, Some(1000), Some(100)).unwrap();
+        let team = fs.create_file("/team").unwrap();
+        fs.chown(team, Some(0), Some(100)).unwrap();
+        fs.chmod(team, 0o640).unwrap();
+
+        fs.set_credentials(Credentials {
+            uid: 1000,
+            gid: 100,
+        });
+        // Others have no bits on /private: no listing, no lookups through it
+        assert!(denied(fs.list_dir("/private")));
+        assert!(denied(fs.read_file("/private/f")));
+        // The root directory is 755, so only root creates entries in it
+        assert!(denied(fs.create_file("/mine")));
+        assert!(denied(fs.chmod(private, 0o777)));
+        assert!(denied(fs.chown(shared, Some(1001), None)));
+        // Group class: read allowed, write not
+        assert_eq!(fs.read_file("/team").unwrap(), b"");
+        assert!(denied(fs.write_file("/team", b"x")));
+
+        // The owner may do what the owner bits allow
+        let mine = fs.create_file("/shared/mine").unwrap();
+        assert_eq!(fs.stat("/shared/mine").unwrap().uid, 1000);
+        fs.chmod(mine, 0o200).unwrap();
+        fs.write_file("/shared/mine", b"x").unwrap();
+        assert!(denied(fs.read_file("/shared/mine")));
+
+        fs.set_credentials(Credentials::ROOT);
+        assert_eq!(fs.read_file("/private/f").unwrap(), b"secret");
+        assert_eq!(fs.read_file("/shared/mine").unwrap(), b"x");
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn long_names() {
+        use crate::fs::layout::DirectoryEntry;
+        let long = "n".repeat(crate::fs::consts::DIR_NAME_MAX_LEN);
+
+        // A record keeps its rec_len (slack after the name included) through a round trip
+        let entry = DirectoryEntry::from_name(&long, 42).unwrap();
+        let mut record = vec![0xff; DirectoryEntry::var_record_len(long.len()) + 8];
+        entry.serialize_var(&mut record);
+        let (parsed, rec_len) = DirectoryEntry::deserialize_var(&record).unwrap();
+        assert_eq!((parsed.name.as_str(), parsed.inode_id), (long.as_str(), 42));
+        assert_eq!(rec_len, record.len());
+        assert!(DirectoryEntry::deserialize_var(&record[..rec_len - 1]).is_none());
+
+        let mut fs = image();
+        assert_eq!(fs.max_name_len(), long.len());
+        fs.create_dir(&format!("/{long}")).unwrap();
+        fs.write_file(&format!("/{long}/{long}"), b"deep").unwrap();
+        assert_eq!(fs.read_file(&format!("/{long}/{long}")).unwrap(), b"deep");
+        assert_eq!(fs.list_dir("/").unwrap()[0].name, long);
+        assert!(matches!(
+            fs.create_file(&format!("/{long}x")),
+            Err(ElfsError::NameTooLong)
+        ));
+
+        // A short name reuses the freed long record in the middle of the directory
+        fs.write_file(&format!("/{long}/z"), b"").unwrap();
+        let dir_size = fs.stat(&format!("/{long}")).unwrap().size;
+        fs.remove_file(&format!("/{long}/{long}")).unwrap();
+        fs.write_file(&format!("/{long}/a"), b"").unwrap();
+        assert_eq!(fs.stat(&format!("/{long}")).unwrap().size, dir_size);
+        let names: Vec<String> = fs
+            .list_dir(&format!("/{long}"))
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["a", "z"]);
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn dot_dot_follows_the_stored_parent() {
+        let mut fs = image();
+        let root = fs.sb.root_inode_id;
+        let a = fs.create_dir("/a").unwrap();
+        let b = fs.create_dir("/a/b").unwrap();
+        let c = fs.create_dir("/a/b/c").unwrap();
+        fs.symlink("/a/b/c", "/link").unwrap();
+
+        assert_eq!(fs.resolve_path("/a/b/c/..").unwrap(), b);
+        assert_eq!(fs.resolve_path("/a/b/../../a/./b").unwrap(), b);
+        assert_eq!(fs.resolve_path("/..").unwrap(), root);
+        // Through a symlink ".." is the parent of the target, not of the link
+        assert_eq!(fs.resolve_path("/link/..").unwrap(), b);
+        assert_eq!(fs.parent_of(c).unwrap(), b);
+        assert!(fs.is_ancestor(a, c).unwrap());
+        assert!(!fs.is_ancestor(c, a).unwrap());
+
+        fs.cd("/link").unwrap();
+        assert_eq!(fs.pwd(), "/a/b/c");
+        assert_eq!(fs.resolve_path("../..").unwrap(), a);
+        fs.cd("../..").unwrap();
+        assert_eq!(fs.pwd(), "/a");
+        assert!(matches!(
+            fs.resolve_parent_and_name("b/.."),
+            Err(ElfsError::InvalidName)
+        ));
+    }
+
+    #[test]
+    fn triple_indirect_addressing() {
+        let mut fs = image();
+        let free_before = fs.data_bitmap().to_vec();
+        let mut inode = pointer_file(&mut fs, "/big");
+
+        // Last block of the double-indirect range and the first one after it
+        let ptrs = (BS / 4) as u64;
+        let first_triple = 5 + ptrs + ptrs * ptrs;
+        let at = |logical: u64| logical * BS as u64;
+        fs.write_file_range(&mut inode, at(first_triple - 1), b"double")
+            .unwrap();
+        fs.write_file_range(&mut inode, at(first_triple), b"triple")
+            .unwrap();
+        assert!(!inode.is_extent_mapped());
+        assert_ne!(inode.double_indirect, 0);
+        assert_ne!(inode.triple_indirect, 0);
+        assert_eq!(inode.single_indirect, 0);
+        // Double: top + one pointer block + data; triple: three levels + data
+        assert_eq!(fs.allocated_blocks(&inode).unwrap(), 3 + 4);
+
+        let mut buf = [0u8; 6];
+        fs.read_file_range(&mut inode, at(first_triple), &mut buf)
+            .unwrap();
+        assert_eq!(&buf, b"triple");
+        fs.read_file_range(&mut inode, at(first_triple - 1), &mut buf)
+            .unwrap();
+        assert_eq!(&buf, b"double");
+        fs.read_file_range(&mut inode, at(first_triple / 2), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [0; 6]);
+        assert!(fs.check(false).unwrap().is_clean());
+
+        // Cutting the file back to the double-indirect range drops the whole triple tree
+        fs.truncate(&mut inode, at(first_triple)).unwrap();
+        assert_eq!(inode.triple_indirect, 0);
+        assert_eq!(fs.allocated_blocks(&inode).unwrap(), 3);
+        fs.remove_file("/big").unwrap();
+        assert_eq!(fs.data_bitmap(), free_before);
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn holes_read_as_zeros() {
+        let mut fs = image();
+        let id = fs.create_file("/extents").unwrap();
+        let extents = fs.read_inode(id).unwrap();
+        let pointers = pointer_file(&mut fs, "/pointers");
+
+        for mut inode in [extents, pointers] {
+            fs.write_file_range(&mut inode, 0, b"head").unwrap();
+            fs.write_file_range(&mut inode, 10 * BS as u64 + 1, b"tail")
+                .unwrap();
+            // Only the two written blocks take space (plus the indirect block reaching the
+            // second one in a pointer file)
+            let mapping = if inode.is_extent_mapped() { 0 } else { 1 };
+            assert_eq!(fs.allocated_blocks(&inode).unwrap(), 2 + mapping);
+
+            let mut content = vec![0xff; inode.file_size as usize];
+            fs.read_file_range(&mut inode, 0, &mut content).unwrap();
+            assert_eq!(&content[..4], b"head");
+            assert!(content[4..10 * BS + 1].iter().all(|&b| b == 0));
+            assert_eq!(&content[10 * BS + 1..], b"tail");
+
+            // Growing with truncate adds a hole and no blocks
+            fs.truncate(&mut inode, 20 * BS as u64).unwrap();
+            assert_eq!(fs.allocated_blocks(&inode).unwrap(), 2 + mapping);
+            let mut tail = vec![0xff; BS];
+            fs.read_file_range(&mut inode, 19 * BS as u64, &mut tail)
+                .unwrap();
+            assert!(tail.iter().all(|&b| b == 0));
+        }
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn truncate_shrinks_and_frees_blocks() {
+        let used =
+            |fs: &FileSystem| -> u32 { fs.data_bitmap().iter().map(|b| b.count_ones()).sum() };
+        let mut fs = image();
+        let id = fs.create_file("/extents").unwrap();
+        let extents = fs.read_inode(id).unwrap();
+        let pointers = pointer_file(&mut fs, "/pointers");
+        let data: Vec<u8> = (0..300 * BS).map(|i| (i % 251) as u8 + 1).collect();
+
+        for mut inode in [extents, pointers] {
+            let before = used(&fs);
+            fs.write_file_range(&mut inode, 0, &data).unwrap();
+            let mapping = if inode.is_extent_mapped() { 0 } else { 1 };
+            assert_eq!(fs.allocated_blocks(&inode).unwrap(), 300 + mapping);
+
+            // Down to 2 blocks and a bit: the single-indirect block goes with the data
+            let len = 2 * BS as u64 + 10;
+            fs.truncate(&mut inode, len).unwrap();
+            assert_eq!(inode.file_size, len);
+            assert_eq!(fs.allocated_blocks(&inode).unwrap(), 3);
+            assert_eq!(used(&fs), before + 3);
+            assert_eq!(inode.single_indirect, 0);
+
+            // Growing again shows zeros after the cut, not the old content
+            fs.truncate(&mut inode, 3 * BS as u64).unwrap();
+            let mut content = vec![0xff; 3 * BS];
+            fs.read_file_range(&mut inode, 0, &mut content).unwrap();
+            assert_eq!(content[..len as usize], data[..len as usize]);
+            assert!(content[len as usize..].iter().all(|&b| b == 0));
+
+            fs.truncate(&mut inode, 0).unwrap();
+            assert_eq!(fs.allocated_blocks(&inode).unwrap(), 0);
+            assert_eq!(used(&fs), before);
+        }
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+}
@@ -0,0 +1,325 @@
+// Path-based operations for library users.
+//
+// Everything here builds on the inode level primitives of filesystem.rs and runs as one
+// transaction per call, so a crash never leaves half of an operation on disk.
+
+use super::FileSystem;
+use crate::fs::error::{ElfsError, Result};
+use crate::fs::layout::Inode;
+
+/// Type of object an inode holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+impl FileKind {
+    pub(super) fn of(inode: &Inode) -> Self {
+        match inode.file_type {
+            1 => FileKind::Dir,
+            2 => FileKind::Symlink,
+            _ => FileKind::File,
+        }
+    }
+
+    fn file_type(self) -> u8 {
+        match self {
+            FileKind::File => 0,
+            FileKind::Dir => 1,
+            FileKind::Symlink => 2,
+        }
+    }
+}
+
+/// Attributes reported by [`FileSystem::stat`] and [`FileSystem::lstat`].
+///
+/// Timestamps are seconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub inode: u32,
+    pub kind: FileKind,
+    /// Logical size in bytes (holes included)
+    pub size: u64,
+    /// Data and mapping blocks actually allocated
+    pub blocks: u64,
+    pub links: u8,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub crtime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atime: u64,
+}
+
+/// One entry returned by [`FileSystem::list_dir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub inode: u32,
+    pub kind: FileKind,
+}
+
+impl FileSystem {
+    /// Creates an empty regular file and returns its inode id.
+    ///
+    /// Fails with [`ElfsError::AlreadyExists`] when the name is taken.
+    pub fn create_file(&mut self, path: &str) -> Result<u32> {
+        self.atomically(|fs| fs.create_node(path, FileKind::File, &[]))
+    }
+
+    /// Replaces the content of the file at `path` with `data`, creating the file when it
+    /// doesn't exist yet. Symlinks are followed.
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        self.atomically(|fs| {
+            let id = match fs.resolve_path(path) {
+                Ok(id) => id,
+                Err(ElfsError::NotFound) => {
+                    return fs.create_node(path, FileKind::File, data).map(|_| ());
+                }
+                Err(e) => return Err(e),
+            };
+            let mut inode = fs.read_inode(id)?;
+            if inode.file_type != 0 {
+                return Err(ElfsError::IsADirectory);
+            }
+            // Overwrite in place, then cut off whatever the old content had beyond `data`
+            if !data.is_empty() {
+                fs.write_file_range(&mut inode, 0, data)?;
+            }
+            fs.truncate(&mut inode, data.len() as u64)
+        })
+    }
+
+    /// Reads the whole content of the file at `path`. Symlinks are followed.
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>> {
+        let id = self.resolve_path(path)?;
+        let mut inode = self.read_inode(id)?;
+        if inode.file_type != 0 {
+            return Err(ElfsError::IsADirectory);
+        }
+        let mut buf = vec![0u8; inode.file_size as usize];
+        self.read_file_range(&mut inode, 0, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Removes a file or symlink (the link itself, not its target). Data blocks are released
+    /// with the last hard link.
+    pub fn remove_file(&mut self, path: &str) -> Result<()> {
+        self.atomically(|fs| {
+            let (mut parent, name, inode) = fs.lookup_entry(path)?;
+            if inode.file_type == 1 {
+                return Err(ElfsError::IsADirectory);
+            }
+            fs.dir_remove_entry(&mut parent, &name)?;
+            fs.free_inode(inode.id)
+        })
+    }
+
+    /// Creates an empty directory and returns its inode id.
+    pub fn create_dir(&mut self, path: &str) -> Result<u32> {
+        self.atomically(|fs| fs.create_node(path, FileKind::Dir, &[]))
+    }
+
+    /// Removes an empty directory.
+    pub fn remove_dir(&mut self, path: &str) -> Result<()> {
+        self.atomically(|fs| {
+            let (mut parent, name, inode) = fs.lookup_entry(path)?;
+            if inode.file_type != 1 {
+                return Err(ElfsError::NotADirectory);
+            }
+            if !fs.dir_is_empty(&inode)? {
+                return Err(ElfsError::DirectoryNotEmpty);
+            }
+            fs.dir_remove_entry(&mut parent, &name)?;
+            fs.free_inode(inode.id)
+        })
+    }
+
+    /// Lists a directory without its "." and ".." entries, in on-disk order.
+    pub fn list_dir(&mut self, path: &str) -> Result<Vec<DirEntry>> {
+        let id = self.resolve_path(path)?;
+        let mut dir = self.read_inode(id)?;
+        if dir.file_type != 1 {
+            return Err(ElfsError::NotADirectory);
+        }
+        self.read_dir(&mut dir)?
+            .into_iter()
+            .map(|e| {
+                let inode = self.read_inode(e.inode_id)?;
+                Ok(DirEntry {
+                    name: e.name,
+                    inode: e.inode_id,
+                    kind: FileKind::of(&inode),
+                })
+            })
+            .collect()
+    }
+
+    /// Creates a symlink at `link` pointing to `target`. The target is stored as given and
+    /// doesn't have to exist.
+    pub fn symlink(&mut self, target: &str, link: &str) -> Result<u32> {
+        self.atomically(|fs| fs.create_node(link, FileKind::Symlink, target.as_bytes()))
+    }
+
+    /// Target stored in the symlink at `path`. Fails with [`ElfsError::NotASymlink`] for
+    /// anything else.
+    pub fn read_link(&mut self, path: &str) -> Result<String> {
+        let id = self.lookup_nofollow(path)?;
+        self.readlink_target(id)
+    }
+
+    /// Metadata of what `path` points to, following symlinks.
+    pub fn stat(&mut self, path: &str) -> Result<Metadata> {
+        let id = self.resolve_path(path)?;
+        self.metadata(id)
+    }
+
+    /// Metadata of `path` itself; a trailing symlink is not followed.
+    pub fn lstat(&mut self, path: &str) -> Result<Metadata> {
+        let id = self.lookup_nofollow(path)?;
+        self.metadata(id)
+    }
+
+    /// Metadata of the inode `id`.
+    pub fn metadata(&mut self, id: u32) -> Result<Metadata> {
+        let inode = self.read_inode(id)?;
+        Ok(Metadata {
+            inode: id,
+            kind: FileKind::of(&inode),
+            size: inode.file_size,
+            blocks: self.allocated_blocks(&inode)?,
+            links: inode.link_count,
+            mode: inode.mode,
+            uid: inode.uid,
+            gid: inode.gid,
+            crtime: inode.crtime,
+            mtime: inode.mtime,
+            ctime: inode.ctime,
+            atime: inode.atime,
+        })
+    }
+
+    // Runs `op` as one transaction (joining an already open one)
+    pub(super) fn atomically<T>(&mut self, op: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.begin_transaction();
+        let result = op(self);
+        let committed = self.commit_transaction();
+        let value = result?;
+        committed?;
+        Ok(value)
+    }
+
+    // Parent directory, entry name and inode of the last component of `path` (not followed)
+    pub(super) fn lookup_entry(&mut self, path: &str) -> Result<(Inode, String, Inode)> {
+        let (parent_id, name) = self.resolve_parent_and_name(path)?;
+        let parent = self.read_inode(parent_id)?;
+        if parent.file_type != 1 {
+            return Err(ElfsError::NotADirectory);
+        }
+        let Some((_, entry)) = self.dir_find(&parent, &name)? else {
+            return Err(ElfsError::NotFound);
+        };
+        let inode = self.read_inode(entry.inode_id)?;
+        Ok((parent, name, inode))
+    }
+
+    // Like resolve_path, but a trailing symlink is returned instead of followed
+    pub(super) fn lookup_nofollow(&mut self, path: &str) -> Result<u32> {
+        match self.lookup_entry(path) {
+            Ok((_, _, inode)) => Ok(inode.id),
+            // "/", "." and ".." name no entry of their own and can't be symlinks
+            Err(ElfsError::InvalidName) if !path.is_empty() => self.resolve_path(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    // New inode of `kind` linked in at `path`; `content` becomes the file or symlink data.
+    // Nothing stays allocated when a step fails.
+    pub(super) fn create_node(
+        &mut self,
+        path: &str,
+        kind: FileKind,
+        content: &[u8],
+    ) -> Result<u32> {
+        let (parent_id, name) = self.resolve_parent_and_name(path)?;
+        self.create_child(parent_id, &name, kind, content)
+    }
+
+    // create_node for a parent directory that is already resolved
+    pub(super) fn create_child(
+        &mut self,
+        parent_id: u32,
+        name: &str,
+        kind: FileKind,
+        content: &[u8],
+    ) -> Result<u32> {
+        if name.len() > self.max_name_len() {
+            return Err(ElfsError::NameTooLong);
+        }
+        let mut parent = self.read_inode(parent_id)?;
+        if parent.file_type != 1 {
+            return Err(ElfsError::NotADirectory);
+        }
+        if self.dir_find(&parent, name)?.is_some() {
+            return Err(ElfsError::AlreadyExists);
+        }
+        let Some(mut inode) = self.new_inode(kind.file_type())? else {
+            return Err(ElfsError::NoInodes);
+        };
+        let id = inode.id;
+        let filled = match kind {
+            FileKind::Dir => self.init_dir(&mut inode, parent_id),
+            _ if content.is_empty() => Ok(()),
+            _ => self.write_file_range(&mut inode, 0, content),
+        };
+        if let Err(e) = filled.and_then(|_| self.dir_add_entry(&mut parent, name, id)) {
+            let _ = self.free_inode(id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image;
+
+    #[test]
+    fn path_operations() {
+        let mut fs = image();
+        fs.create_dir("/docs").unwrap();
+        fs.write_file("/docs/a.txt", b"first version").unwrap();
+        fs.write_file("/docs/a.txt", b"second").unwrap();
+        fs.symlink("/docs/a.txt", "/latest").unwrap();
+
+        assert_eq!(fs.read_file("/latest").unwrap(), b"second");
+        assert_eq!(fs.read_link("/latest").unwrap(), "/docs/a.txt");
+        assert_eq!(fs.lstat("/latest").unwrap().kind, FileKind::Symlink);
+        assert_eq!(fs.stat("/latest").unwrap().size, 6);
+        let names: Vec<String> = fs
+            .list_dir("/")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["docs", "latest"]);
+
+        assert!(matches!(
+            fs.create_dir("/docs"),
+            Err(ElfsError::AlreadyExists)
+        ));
+        assert!(matches!(
+            fs.remove_dir("/docs"),
+            Err(ElfsError::DirectoryNotEmpty)
+        ));
+        fs.remove_file("/latest").unwrap();
+        fs.remove_file("/docs/a.txt").unwrap();
+        fs.remove_dir("/docs").unwrap();
+        assert!(fs.list_dir("/").unwrap().is_empty());
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+}
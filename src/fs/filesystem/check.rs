@@ -0,0 +1,910 @@
+// Consistency checker behind the `fsck` command.
+//
+// The tree is walked from root_inode_id and everything the walk finds is compared with what the
+// image stores: link counts, both bitmaps, file sizes and "."/".." entries. With `repair` the
+// differences are written back; otherwise the image is left untouched.
+
+use super::{FileSystem, indirect_root, is_dot_name};
+use crate::fs::consts::BLOCK_SIZE;
+use crate::fs::layout::Inode;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+const LOST_FOUND: &str = "lost+found";
+
+// One inconsistency found by FileSystem::check()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    // Entry points at an inode that is out of range or not in use
+    DanglingEntry {
+        dir: u32,
+        name: String,
+        inode: u32,
+    },
+    // Directory records cannot be parsed
+    CorruptDirectory {
+        dir: u32,
+    },
+    // "." or ".." missing or pointing elsewhere
+    BadDotEntry {
+        dir: u32,
+        name: String,
+        expected: u32,
+    },
+    // In-use inode that no directory entry reaches
+    OrphanedInode {
+        inode: u32,
+    },
+    WrongLinkCount {
+        inode: u32,
+        stored: u8,
+        actual: u32,
+    },
+    // Blocks mapped past the end of the file
+    WrongSize {
+        inode: u32,
+        size: u64,
+        mapped_end: u64,
+    },
+    // Extent tree nodes cannot be read back; the inode's blocks are unknown
+    CorruptExtentTree {
+        inode: u32,
+    },
+    // Block pointer outside the data area
+    BadBlockPointer {
+        inode: u32,
+        block: u32,
+    },
+    // Marked in the bitmap but referenced by no inode
+    LeakedBlocks {
+        first: u32,
+        count: u32,
+    },
+    // Referenced by an inode but free in the bitmap
+    UnmarkedBlocks {
+        first: u32,
+        count: u32,
+    },
+    // Referenced more than once (data blocks are cloned for every extra owner)
+    DuplicateBlocks {
+        first: u32,
+        count: u32,
+        inodes: Vec<u32>,
+    },
+    // Inode bitmap bit disagrees with the inode's state
+    InodeBitmapMismatch {
+        inode: u32,
+        marked: bool,
+    },
+}
+
+impl Problem {
+    // Problems `check(true)` cannot fix
+    pub fn is_repairable(&self) -> bool {
+        !matches!(
+            self,
+            Problem::CorruptDirectory { .. }
+                | Problem::CorruptExtentTree { .. }
+                | Problem::BadBlockPointer { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::DanglingEntry { dir, name, inode } => write!(
+                f,
+                "dangling entry '{}' in directory {} -> i-node {}",
+                name, dir, inode
+            ),
+            Problem::CorruptDirectory { dir } => write!(f, "corrupt directory i-node {}", dir),
+            Problem::BadDotEntry {
+                dir,
+                name,
+                expected,
+            } => write!(
+                f,
+                "directory {}: '{}' should point to i-node {}",
+                dir, name, expected
+            ),
+            Problem::OrphanedInode { inode } => write!(f, "orphaned i-node {}", inode),
+            Problem::WrongLinkCount {
+                inode,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "i-node {}: link count {} should be {}",
+                inode, stored, actual
+            ),
+            Problem::WrongSize {
+                inode,
+                size,
+                mapped_end,
+            } => write!(
+                f,
+                "i-node {}: size {} B but blocks mapped up to {} B",
+                inode, size, mapped_end
+            ),
+            Problem::CorruptExtentTree { inode } => {
+                write!(f, "i-node {}: corrupt extent tree", inode)
+            }
+            Problem::BadBlockPointer { inode, block } => {
+                write!(f, "i-node {}: invalid block pointer {}", inode, block)
+            }
+            Problem::LeakedBlocks { first, count } => {
+                write!(f, "leaked {}", BlockRange(*first, *count))
+            }
+            Problem::UnmarkedBlocks { first, count } => {
+                write!(
+                    f,
+                    "{} in use but free in bitmap",
+                    BlockRange(*first, *count)
+                )
+            }
+            Problem::DuplicateBlocks {
+                first,
+                count,
+                inodes,
+            } => write!(
+                f,
+                "{} claimed by i-nodes {:?}",
+                BlockRange(*first, *count),
+                inodes
+            ),
+            Problem::InodeBitmapMismatch { inode, marked } => write!(
+                f,
+                "i-node {} is {} in the inode bitmap",
+                inode,
+                if *marked {
+                    "free but marked"
+                } else {
+                    "in use but not marked"
+                }
+            ),
+        }
+    }
+}
+
+// "block N" or "blocks N-M"
+struct BlockRange(u32, u32);
+
+impl std::fmt::Display for BlockRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.1 {
+            1 => write!(f, "block {}", self.0),
+            n => write!(f, "blocks {}-{}", self.0, self.0 + n - 1),
+        }
+    }
+}
+
+// Result of FileSystem::check()
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub problems: Vec<Problem>,
+    // Whether the problems were written back as fixed (check(true))
+    pub repaired: bool,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+// Inodes referencing one block, with the logical index for data blocks (None for node blocks)
+type Owners = Vec<(u32, Option<u64>)>;
+
+// Blocks an inode holds: (logical index, block) for data, plus pointer/extent node blocks
+struct BlockMap {
+    data: Vec<(u64, u32)>,
+    meta: Vec<u32>,
+    bad: Vec<u32>,
+    // The extent tree could not be walked, so data and meta are incomplete
+    corrupt: bool,
+}
+
+impl FileSystem {
+    // Checks the image and, with `repair`, fixes what it can. Runs with root credentials.
+    pub fn check(&mut self, repair: bool) -> crate::fs::error::Result<CheckReport> {
+        let saved = self.creds;
+        self.creds = crate::fs::perm::Credentials::ROOT;
+        let result = self.run_check(repair);
+        self.creds = saved;
+        result
+    }
+
+    fn run_check(&mut self, repair: bool) -> crate::fs::error::Result<CheckReport> {
+        let mut report = CheckReport {
+            problems: Vec::new(),
+            repaired: repair,
+        };
+        let root = self.sb.root_inode_id;
+        let live: BTreeSet<u32> = (0..self.sb.inode_count)
+            .filter(|&id| id == root || self.is_live(id))
+            .collect();
+
+        // 1. Blocks of every in-use inode (read-only; the bitmaps have to be right before any
+        //    repair below allocates or frees something)
+        let block_size = BLOCK_SIZE as u64;
+        let mut claims: BTreeMap<u32, Owners> = BTreeMap::new();
+        let mut oversized: Vec<(u32, u64)> = Vec::new();
+        let mut unknown_owner = false;
+        for &id in &live {
+            let ino = self.read_inode(id)?;
+            let map = self.block_map(&ino)?;
+            if map.corrupt {
+                report
+                    .problems
+                    .push(Problem::CorruptExtentTree { inode: id });
+                unknown_owner = true;
+            }
+            let mapped_end = map
+                .data
+                .iter()
+                .map(|&(l, _)| (l + 1) * block_size)
+                .max()
+                .unwrap_or(0);
+            if mapped_end > ino.file_size.div_ceil(block_size) * block_size {
+                report.problems.push(Problem::WrongSize {
+                    inode: id,
+                    size: ino.file_size,
+                    mapped_end,
+                });
+                oversized.push((id, mapped_end));
+            }
+            for block in map.bad {
+                report
+                    .problems
+                    .push(Problem::BadBlockPointer { inode: id, block });
+            }
+            for (logical, block) in map.data {
+                claims.entry(block).or_default().push((id, Some(logical)));
+            }
+            for block in map.meta {
+                claims.entry(block).or_default().push((id, None));
+            }
+        }
+
+        // 2. Data bitmap vs. claimed blocks (unclaimed blocks stay marked while some inode's
+        //    blocks are unknown: they may still be mapped through its broken extent tree)
+        let mut leaked = Vec::new();
+        let mut unmarked = Vec::new();
+        for rel in 0..self.sb.block_count {
+            let block = self.sb.block_start + rel;
+            let marked = crate::fs::io::bitmap_is_set(&self.data_bitmap, rel);
+            let claimed = claims.contains_key(&block);
+            if marked == claimed {
+                continue;
+            }
+            if claimed {
+                unmarked.push(block);
+            } else {
+                leaked.push(block);
+            }
+            if repair {
+                if claimed {
+                    crate::fs::io::bitmap_set(&mut self.data_bitmap, rel);
+                    self.bitmap_dirty = true;
+                } else if !unknown_owner {
+                    crate::fs::io::bitmap_clear(&mut self.data_bitmap, rel);
+                    self.bitmap_dirty = true;
+                }
+            }
+        }
+        for (first, count) in runs(&leaked) {
+            report.problems.push(Problem::LeakedBlocks { first, count });
+        }
+        for (first, count) in runs(&unmarked) {
+            report
+                .problems
+                .push(Problem::UnmarkedBlocks { first, count });
+        }
+
+        // 3. Inode bitmap vs. in-use inodes
+        if !self.inode_bitmap.is_empty() {
+            for id in 0..self.sb.inode_count {
+                let marked = crate::fs::io::bitmap_is_set(&self.inode_bitmap, id);
+                let in_use = live.contains(&id);
+                if marked == in_use {
+                    continue;
+                }
+                report
+                    .problems
+                    .push(Problem::InodeBitmapMismatch { inode: id, marked });
+                if repair {
+                    if in_use {
+                        crate::fs::io::bitmap_set(&mut self.inode_bitmap, id);
+                    } else {
+                        crate::fs::io::bitmap_clear(&mut self.inode_bitmap, id);
+                    }
+                    self.inode_bitmap_dirty = true;
+                }
+            }
+        }
+
+        // 4. Directory tree: references per inode
+        let mut refs: HashMap<u32, u32> = HashMap::new();
+        let mut reachable: BTreeSet<u32> = BTreeSet::new();
+        self.walk_tree(root, root, repair, &mut refs, &mut reachable, &mut report)?;
+
+        // 5. In-use inodes the walk did not reach
+        let orphans: Vec<u32> = live.difference(&reachable).copied().collect();
+        for &id in &orphans {
+            report.problems.push(Problem::OrphanedInode { inode: id });
+        }
+        if repair {
+            self.reconnect_orphans(&orphans, &mut refs, &mut reachable, &mut report)?;
+        }
+
+        // 6. Link counts (directories count once: "." and ".." are not links)
+        for &id in &live {
+            if id == root {
+                continue;
+            }
+            let actual = refs.get(&id).copied().unwrap_or(0);
+            let mut ino = self.read_inode(id)?;
+            if actual == 0 || ino.link_count as u32 == actual {
+                continue;
+            }
+            report.problems.push(Problem::WrongLinkCount {
+                inode: id,
+                stored: ino.link_count,
+                actual,
+            });
+            if repair {
+                ino.link_count = actual.min(u8::MAX as u32) as u8;
+                self.write_inode(id, &ino)?;
+                self.checkpoint()?;
+            }
+        }
+
+        // 7. Shared blocks: the first owner keeps the block, every further data owner gets a copy
+        let shared: Vec<(u32, Owners)> = claims
+            .into_iter()
+            .filter(|(_, owners)| owners.len() > 1)
+            .collect();
+        let mut i = 0;
+        while i < shared.len() {
+            let inodes: Vec<u32> = shared[i].1.iter().map(|&(id, _)| id).collect();
+            let mut count = 1;
+            while let Some((block, owners)) = shared.get(i + count as usize)
+                && *block == shared[i].0 + count
+                && owners.iter().map(|&(id, _)| id).eq(inodes.iter().copied())
+            {
+                count += 1;
+            }
+            report.problems.push(Problem::DuplicateBlocks {
+                first: shared[i].0,
+                count,
+                inodes,
+            });
+            i += count as usize;
+        }
+        if repair {
+            for (block, owners) in &shared {
+                for &(id, logical) in &owners[1..] {
+                    if let Some(logical) = logical {
+                        self.clone_block(id, logical, *block)?;
+                        self.checkpoint()?;
+                    }
+                }
+            }
+        }
+
+        // 8. Blocks past EOF go back to the bitmap
+        if repair {
+            for (id, mapped_end) in oversized {
+                let mut ino = self.read_inode(id)?;
+                let size = ino.file_size;
+                ino.file_size = mapped_end;
+                self.resize(&mut ino, size)?;
+                self.checkpoint()?;
+            }
+            self.flush()?;
+        }
+        Ok(report)
+    }
+
+    fn is_live(&mut self, id: u32) -> bool {
+        match self.read_inode(id) {
+            Ok(ino) => ino.link_count > 0 && ino.file_type <= 2,
+            Err(_) => false,
+        }
+    }
+
+    // Breadth-first walk from `start` (whose parent is `parent`), counting entry references
+    fn walk_tree(
+        &mut self,
+        start: u32,
+        parent: u32,
+        repair: bool,
+        refs: &mut HashMap<u32, u32>,
+        reachable: &mut BTreeSet<u32>,
+        report: &mut CheckReport,
+    ) -> crate::fs::error::Result<()> {
+        let mut queue = VecDeque::from([(start, parent)]);
+        reachable.insert(start);
+        while let Some((dir_id, parent_id)) = queue.pop_front() {
+            self.checkpoint()?;
+            let mut dir = self.read_inode(dir_id)?;
+            let records = match self.dir_records(&dir) {
+                Ok(r) => r,
+                Err(_) => {
+                    report
+                        .problems
+                        .push(Problem::CorruptDirectory { dir: dir_id });
+                    continue;
+                }
+            };
+
+            if self.sb.has_dot_entries() {
+                for (name, expected) in [(".", dir_id), ("..", parent_id)] {
+                    let found = records
+                        .iter()
+                        .find(|(_, _, e)| !e.is_unused() && e.name == name)
+                        .map(|(_, _, e)| e.inode_id);
+                    if found == Some(expected) {
+                        continue;
+                    }
+                    report.problems.push(Problem::BadDotEntry {
+                        dir: dir_id,
+                        name: name.to_string(),
+                        expected,
+                    });
+                    if repair {
+                        match found {
+                            Some(_) => self.dir_set_entry(&mut dir, name, expected)?,
+                            None => self.dir_add_entry(&mut dir, name, expected)?,
+                        }
+                    }
+                }
+            }
+
+            for (_, _, entry) in records {
+                if entry.is_unused() || is_dot_name(&entry.name) {
+                    continue;
+                }
+                let child = entry.inode_id;
+                let valid = child < self.sb.inode_count
+                    && child != self.sb.root_inode_id
+                    && self.is_live(child);
+                if !valid {
+                    report.problems.push(Problem::DanglingEntry {
+                        dir: dir_id,
+                        name: entry.name.clone(),
+                        inode: child,
+                    });
+                    if repair {
+                        self.dir_remove_entry(&mut dir, &entry.name)?;
+                    }
+                    continue;
+                }
+                *refs.entry(child).or_insert(0) += 1;
+                if reachable.insert(child) && self.read_inode(child)?.file_type == 1 {
+                    queue.push_back((child, dir_id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Links unreachable inodes into /lost+found as "#<id>". For orphaned directory subtrees only
+    // the topmost directory is linked; the rest becomes reachable through it.
+    fn reconnect_orphans(
+        &mut self,
+        orphans: &[u32],
+        refs: &mut HashMap<u32, u32>,
+        reachable: &mut BTreeSet<u32>,
+        report: &mut CheckReport,
+    ) -> crate::fs::error::Result<()> {
+        if orphans.is_empty() {
+            return Ok(());
+        }
+        let lost_found = self.lost_found_dir(refs, reachable)?;
+
+        // Directories first so their contents don't get linked individually
+        let mut ordered: Vec<(u32, bool)> = Vec::new();
+        for &id in orphans {
+            ordered.push((id, self.read_inode(id)?.file_type == 1));
+        }
+        ordered.sort_by_key(|&(id, is_dir)| (!is_dir, id));
+
+        for (id, is_dir) in ordered {
+            if reachable.contains(&id) {
+                continue;
+            }
+            self.checkpoint()?;
+            let top = if is_dir {
+                self.orphan_top(id, reachable)?
+            } else {
+                id
+            };
+            let mut lf = self.read_inode(lost_found)?;
+            self.dir_add_entry(&mut lf, &format!("#{}", top), top)?;
+            *refs.entry(top).or_insert(0) += 1;
+            if self.read_inode(top)?.file_type == 1 {
+                if self.sb.has_dot_entries() {
+                    let mut dir = self.read_inode(top)?;
+                    if self.dir_find(&dir, "..")?.is_some() {
+                        self.dir_set_entry(&mut dir, "..", lost_found)?;
+                    }
+                }
+                self.walk_tree(top, lost_found, true, refs, reachable, report)?;
+            } else {
+                reachable.insert(top);
+            }
+        }
+        Ok(())
+    }
+
+    // Follows ".." upwards while the parent is an unreachable directory that still lists us
+    fn orphan_top(
+        &mut self,
+        dir_id: u32,
+        reachable: &BTreeSet<u32>,
+    ) -> crate::fs::error::Result<u32> {
+        let mut id = dir_id;
+        let mut seen = BTreeSet::new();
+        while seen.insert(id) && self.sb.has_dot_entries() {
+            let dir = self.read_inode(id)?;
+            let Ok(Some((_, dotdot))) = self.dir_find(&dir, "..") else {
+                break;
+            };
+            let parent = dotdot.inode_id;
+            if reachable.contains(&parent) || parent >= self.sb.inode_count || !self.is_live(parent)
+            {
+                break;
+            }
+            let parent_inode = self.read_inode(parent)?;
+            let lists_us = match self.dir_records(&parent_inode) {
+                Ok(records) => records
+                    .iter()
+                    .any(|(_, _, e)| !e.is_unused() && !is_dot_name(&e.name) && e.inode_id == id),
+                Err(_) => false,
+            };
+            if parent_inode.file_type != 1 || !lists_us {
+                break;
+            }
+            id = parent;
+        }
+        Ok(id)
+    }
+
+    // Existing /lost+found or a freshly created one
+    fn lost_found_dir(
+        &mut self,
+        refs: &mut HashMap<u32, u32>,
+        reachable: &mut BTreeSet<u32>,
+    ) -> crate::fs::error::Result<u32> {
+        let root_id = self.sb.root_inode_id;
+        let mut root = self.read_inode(root_id)?;
+        if let Some((_, entry)) = self.dir_find(&root, LOST_FOUND)?
+            && self.read_inode(entry.inode_id)?.file_type == 1
+        {
+            return Ok(entry.inode_id);
+        }
+        let mut dir = self
+            .new_inode(1)?
+            .ok_or(crate::fs::error::ElfsError::NoInodes)?;
+        self.init_dir(&mut dir, root_id)?;
+        self.dir_add_entry(&mut root, LOST_FOUND, dir.id)?;
+        refs.insert(dir.id, 1);
+        reachable.insert(dir.id);
+        Ok(dir.id)
+    }
+
+    // Every block the inode references, without following pointers outside the data area
+    fn block_map(&mut self, inode: &Inode) -> crate::fs::error::Result<BlockMap> {
+        let mut map = BlockMap {
+            data: Vec::new(),
+            meta: Vec::new(),
+            bad: Vec::new(),
+            corrupt: false,
+        };
+        if inode.is_extent_mapped() {
+            let (extents, nodes) = match self.load_extents(inode) {
+                Ok(v) => v,
+                Err(crate::fs::error::ElfsError::Corrupt(_)) => {
+                    map.corrupt = true;
+                    return Ok(map);
+                }
+                Err(e) => return Err(e),
+            };
+            for e in extents {
+                for i in 0..e.len {
+                    let block = e.physical.wrapping_add(i);
+                    if self.in_data_area(block) {
+                        map.data.push((e.logical as u64 + i as u64, block));
+                    } else {
+                        map.bad.push(block);
+                    }
+                }
+            }
+            map.meta = nodes;
+            return Ok(map);
+        }
+
+        for (i, &b) in inode.single_directs.iter().enumerate() {
+            if b == 0 {
+                continue;
+            }
+            if self.in_data_area(b) {
+                map.data.push((i as u64, b));
+            } else {
+                map.bad.push(b);
+            }
+        }
+        let ptrs_per_block = (BLOCK_SIZE as u64) / 4;
+        let mut level_start = 5;
+        for level in 1..=3 {
+            let root = indirect_root(inode, level);
+            if root != 0 {
+                self.map_tree(root, level, level_start, &mut map)?;
+            }
+            level_start += ptrs_per_block.pow(level as u32);
+        }
+        Ok(map)
+    }
+
+    fn map_tree(
+        &mut self,
+        block: u32,
+        depth: usize,
+        first_logical: u64,
+        map: &mut BlockMap,
+    ) -> crate::fs::error::Result<()> {
+        if !self.in_data_area(block) {
+            map.bad.push(block);
+            return Ok(());
+        }
+        if depth == 0 {
+            map.data.push((first_logical, block));
+            return Ok(());
+        }
+        map.meta.push(block);
+        let span = ((BLOCK_SIZE as u64) / 4).pow(depth as u32 - 1);
+        let mut raw = vec![0u8; BLOCK_SIZE as usize];
+        self.read_block(block, &mut raw)?;
+        for (i, chunk) in raw.chunks_exact(4).enumerate() {
+            let p = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            if p != 0 {
+                self.map_tree(p, depth - 1, first_logical + i as u64 * span, map)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn in_data_area(&self, block: u32) -> bool {
+        block >= self.sb.block_start && block - self.sb.block_start < self.sb.block_count
+    }
+
+    // Gives `inode_id` its own copy of the data block mapped at `logical`
+    fn clone_block(
+        &mut self,
+        inode_id: u32,
+        logical: u64,
+        shared: u32,
+    ) -> crate::fs::error::Result<()> {
+        let mut ino = self.read_inode(inode_id)?;
+        let mut data = vec![0u8; BLOCK_SIZE as usize];
+        self.read_block(shared, &mut data)?;
+        let size = ino.file_size;
+        self.unmap_block(&mut ino, logical)?;
+        // The write allocates a fresh block for the now unmapped position
+        self.write_file_range(&mut ino, logical * BLOCK_SIZE as u64, &data)?;
+        ino.file_size = size;
+        self.write_inode(inode_id, &ino)
+    }
+
+    // Removes the mapping of one logical block without freeing the block itself
+    fn unmap_block(&mut self, inode: &mut Inode, logical: u64) -> crate::fs::error::Result<()> {
+        if inode.is_extent_mapped() {
+            let (extents, nodes) = self.load_extents(inode)?;
+            let mut out = Vec::with_capacity(extents.len() + 1);
+            for e in extents {
+                match e.map(logical) {
+                    None => out.push(e),
+                    Some(_) => {
+                        let head = (logical - e.logical as u64) as u32;
+                        if head > 0 {
+                            out.push(crate::fs::extent::Extent { len: head, ..e });
+                        }
+                        if head + 1 < e.len {
+                            out.push(crate::fs::extent::Extent {
+                                logical: e.logical + head + 1,
+                                physical: e.physical + head + 1,
+                                len: e.len - head - 1,
+                            });
+                        }
+                    }
+                }
+            }
+            self.store_extents(inode, &out, nodes)?;
+            return self.write_inode(inode.id, inode);
+        }
+
+        if logical < 5 {
+            inode.single_directs[logical as usize] = 0;
+            return self.write_inode(inode.id, inode);
+        }
+        let Some((level, rel)) = super::indirect_position(logical) else {
+            return Ok(());
+        };
+        let ptrs_per_block = (BLOCK_SIZE as u64) / 4;
+        let mut block = indirect_root(inode, level);
+        for depth in (0..level as u32).rev() {
+            if block == 0 {
+                return Ok(());
+            }
+            let idx = ((rel / ptrs_per_block.pow(depth)) % ptrs_per_block) as usize;
+            let mut raw = vec![0u8; BLOCK_SIZE as usize];
+            self.read_block(block, &mut raw)?;
+            if depth == 0 {
+                raw[idx * 4..idx * 4 + 4].fill(0);
+                return self.write_block(block, &raw);
+            }
+            block = u32::from_le_bytes(raw[idx * 4..idx * 4 + 4].try_into().unwrap());
+        }
+        Ok(())
+    }
+}
+
+// Sorted block numbers folded into (first, count) runs of consecutive blocks
+fn runs(blocks: &[u32]) -> Vec<(u32, u32)> {
+    let mut out: Vec<(u32, u32)> = Vec::new();
+    for &b in blocks {
+        match out.last_mut() {
+            Some((first, count)) if *first + *count == b => *count += 1,
+            _ => out.push((b, 1)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::extent::Extent;
+    use crate::testing::image;
+
+    const BS: usize = BLOCK_SIZE as usize;
+
+    fn first_block(fs: &mut FileSystem, path: &str) -> (Inode, u32) {
+        let id = fs.resolve_path(path).unwrap();
+        let ino = fs.read_inode(id).unwrap();
+        let (extents, _) = fs.load_extents(&ino).unwrap();
+        (ino, extents[0].physical)
+    }
+
+    #[test]
+    fn check_finds_and_repairs_damage() {
+        let mut fs = image();
+        fs.create_dir_all("/dir/sub").unwrap();
+        fs.write_file("/dir/sub/deep.txt", b"deep").unwrap();
+        fs.write_file("/loose", b"loose").unwrap();
+        fs.write_file("/a", &[1; 3 * BS]).unwrap();
+        fs.write_file("/b", &[2; BS]).unwrap();
+        fs.write_file("/big", &[3; 2 * BS]).unwrap();
+        assert!(fs.check(false).unwrap().is_clean());
+
+        let dir = fs.resolve_path("/dir").unwrap();
+        let loose = fs.resolve_path("/loose").unwrap();
+        let (mut a, a_first) = first_block(&mut fs, "/a");
+        let (mut b, b_old) = first_block(&mut fs, "/b");
+        let big_id = fs.resolve_path("/big").unwrap();
+        let mut big = fs.read_inode(big_id).unwrap();
+
+        // Entries unlinked without freeing their inodes: a directory subtree and a file
+        let mut root = fs.read_inode(fs.sb.root_inode_id).unwrap();
+        fs.dir_remove_entry(&mut root, "dir").unwrap();
+        fs.dir_remove_entry(&mut root, "loose").unwrap();
+        // Link count off, /b mapping /a's first block, /big shorter than its blocks
+        a.link_count = 3;
+        fs.write_inode(a.id, &a).unwrap();
+        let shared = Extent {
+            logical: 0,
+            physical: a_first,
+            len: 1,
+        };
+        fs.store_extents(&mut b, &[shared], Vec::new()).unwrap();
+        fs.write_inode(b.id, &b).unwrap();
+        big.file_size = 10;
+        fs.write_inode(big.id, &big).unwrap();
+        // A block of /a free in the data bitmap, /big free in the inode bitmap
+        crate::fs::io::bitmap_clear(&mut fs.data_bitmap, a_first + 2 - fs.sb.block_start);
+        fs.bitmap_dirty = true;
+        crate::fs::io::bitmap_clear(&mut fs.inode_bitmap, big.id);
+        fs.inode_bitmap_dirty = true;
+        fs.flush().unwrap();
+
+        let found = fs.check(false).unwrap();
+        assert!(!found.repaired);
+        for problem in [
+            Problem::OrphanedInode { inode: dir },
+            Problem::OrphanedInode { inode: loose },
+            Problem::WrongLinkCount {
+                inode: a.id,
+                stored: 3,
+                actual: 1,
+            },
+            Problem::DuplicateBlocks {
+                first: a_first,
+                count: 1,
+                inodes: vec![a.id, b.id],
+            },
+            Problem::WrongSize {
+                inode: big.id,
+                size: 10,
+                mapped_end: 2 * BLOCK_SIZE as u64,
+            },
+            Problem::UnmarkedBlocks {
+                first: a_first + 2,
+                count: 1,
+            },
+            Problem::LeakedBlocks {
+                first: b_old,
+                count: 1,
+            },
+            Problem::InodeBitmapMismatch {
+                inode: big.id,
+                marked: false,
+            },
+        ] {
+            assert!(found.problems.contains(&problem), "missing {problem}");
+        }
+        // -n leaves the image alone
+        assert_eq!(fs.check(false).unwrap().problems, found.problems);
+
+        let fixed = fs.check(true).unwrap();
+        assert!(fixed.repaired);
+        assert_eq!(fixed.problems, found.problems);
+        assert!(fs.check(false).unwrap().is_clean());
+
+        let lost = format!("/lost+found/#{dir}/sub/deep.txt");
+        assert_eq!(fs.read_file(&lost).unwrap(), b"deep");
+        assert_eq!(
+            fs.read_file(&format!("/lost+found/#{loose}")).unwrap(),
+            b"loose"
+        );
+        assert_eq!(fs.stat("/a").unwrap().links, 1);
+        // /b keeps a copy of the block it shared, /a the original
+        assert_eq!(fs.read_file("/b").unwrap(), [1; BS]);
+        assert_ne!(first_block(&mut fs, "/b").1, a_first);
+        assert_eq!(fs.read_file("/a").unwrap(), [1; 3 * BS]);
+        assert_eq!(fs.stat("/big").unwrap().blocks, 1);
+    }
+
+    #[test]
+    fn corrupt_extent_tree_keeps_its_blocks() {
+        let mut fs = image();
+        fs.write_file("/a", &[1; 3 * BS]).unwrap();
+        let (mut a, a_first) = first_block(&mut fs, "/a");
+
+        // Root points at a node block that does not exist on the device
+        crate::fs::extent::ExtentNode {
+            depth: 1,
+            entries: vec![Extent {
+                logical: 0,
+                physical: u32::MAX,
+                len: 0,
+            }],
+        }
+        .write_to_inode(&mut a);
+        fs.write_inode(a.id, &a).unwrap();
+        fs.flush().unwrap();
+
+        let found = fs.check(false).unwrap();
+        let corrupt = Problem::CorruptExtentTree { inode: a.id };
+        assert!(found.problems.contains(&corrupt));
+        assert!(!corrupt.is_repairable());
+
+        // Repair must not free blocks the broken tree may still map
+        let fixed = fs.check(true).unwrap();
+        assert!(fixed.problems.contains(&corrupt));
+        for block in a_first..a_first + 3 {
+            let rel = block - fs.sb.block_start;
+            assert!(crate::fs::io::bitmap_is_set(&fs.data_bitmap, rel));
+        }
+    }
+}
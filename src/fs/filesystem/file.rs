@@ -0,0 +1,272 @@
+// Open file handles.
+//
+// An ElfsFile borrows the filesystem and keeps a transaction open for its whole life:
+// Write::flush commits what was written so far and dropping the handle commits the rest.
+
+use super::FileSystem;
+use super::api::FileKind;
+use crate::fs::error::{ElfsError, Result};
+use crate::fs::layout::Inode;
+use std::io::{self, SeekFrom};
+
+/// How [`FileSystem::open_file`] opens a file; mirrors [`std::fs::OpenOptions`].
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+impl OpenOptions {
+    /// All options off; at least one of `read`, `write` or `append` has to be set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Every write goes to the current end of the file. Implies `write`.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Cuts an existing file to zero length. Needs `write`.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Creates the file when it doesn't exist. Needs `write` or `append`.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the file and fails with `AlreadyExists` when it's there already.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Same as `fs.open_file(path, self)`.
+    pub fn open<'a>(&self, fs: &'a mut FileSystem, path: &str) -> Result<ElfsFile<'a>> {
+        fs.open_file(path, self)
+    }
+}
+
+/// Handle to a regular file inside an image, with [`io::Read`], [`io::Write`] and
+/// [`io::Seek`]. Reading stops at the file size; writing past it grows the file (a gap left by
+/// seeking reads back as zeros).
+pub struct ElfsFile<'a> {
+    fs: &'a mut FileSystem,
+    inode: Inode,
+    pos: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+impl FileSystem {
+    /// Opens the regular file at `path` (symlinks are followed) as an [`ElfsFile`].
+    pub fn open_file(&mut self, path: &str, options: &OpenOptions) -> Result<ElfsFile<'_>> {
+        let write = options.write || options.append;
+        if !options.read && !write {
+            return Err(ElfsError::InvalidArgument("neither read nor write"));
+        }
+        if (options.create || options.create_new) && !write || options.truncate && !options.write {
+            return Err(ElfsError::InvalidArgument(
+                "create and truncate need write access",
+            ));
+        }
+
+        self.begin_transaction();
+        match self.open_inode(path, options, write) {
+            Ok(inode) => Ok(ElfsFile {
+                fs: self,
+                inode,
+                pos: 0,
+                read: options.read,
+                write,
+                append: options.append,
+            }),
+            Err(e) => {
+                let _ = self.commit_transaction();
+                Err(e)
+            }
+        }
+    }
+
+    fn open_inode(&mut self, path: &str, options: &OpenOptions, write: bool) -> Result<Inode> {
+        let id = match self.resolve_path(path) {
+            Ok(_) if options.create_new => return Err(ElfsError::AlreadyExists),
+            Ok(id) => id,
+            Err(ElfsError::NotFound) if options.create || options.create_new => {
+                self.create_node(path, FileKind::File, &[])?
+            }
+            Err(e) => return Err(e),
+        };
+        let mut inode = self.read_inode(id)?;
+        if inode.file_type != 0 {
+            return Err(ElfsError::IsADirectory);
+        }
+        let mut want = 0;
+        if options.read {
+            want |= crate::fs::perm::PERM_READ;
+        }
+        if write {
+            want |= crate::fs::perm::PERM_WRITE;
+        }
+        self.check_access(&inode, want)?;
+        if options.truncate && inode.file_size > 0 {
+            self.truncate(&mut inode, 0)?;
+        }
+        Ok(inode)
+    }
+}
+
+impl ElfsFile<'_> {
+    /// Inode of the open file.
+    pub fn inode_id(&self) -> u32 {
+        self.inode.id
+    }
+
+    /// Current size in bytes.
+    pub fn len(&self) -> u64 {
+        self.inode.file_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inode.file_size == 0
+    }
+
+    /// Grows (with a hole) or shrinks the file; the position stays where it is.
+    pub fn set_len(&mut self, len: u64) -> Result<()> {
+        if !self.write {
+            return Err(ElfsError::PermissionDenied);
+        }
+        self.fs.truncate(&mut self.inode, len)
+    }
+}
+
+impl io::Read for ElfsFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.read {
+            return Err(ElfsError::PermissionDenied.into());
+        }
+        let left = self.inode.file_size.saturating_sub(self.pos);
+        let n = (buf.len() as u64).min(left) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.fs
+            .read_file_range(&mut self.inode, self.pos, &mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Write for ElfsFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.write {
+            return Err(ElfsError::PermissionDenied.into());
+        }
+        if self.append {
+            self.pos = self.inode.file_size;
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.fs.write_file_range(&mut self.inode, self.pos, buf)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    // Commits the handle's transaction so far; it stays open for further writes
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(self.fs.flush()?)
+    }
+}
+
+impl io::Seek for ElfsFile<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(d) => (self.inode.file_size, d),
+            SeekFrom::Current(d) => (self.pos, d),
+        };
+        let Some(new) = base.checked_add_signed(delta) else {
+            return Err(ElfsError::InvalidArgument("seek before the start of the file").into());
+        };
+        self.pos = new;
+        Ok(new)
+    }
+}
+
+impl Drop for ElfsFile<'_> {
+    fn drop(&mut self) {
+        // Errors can't be reported from here; call flush() first to see them
+        let _ = self.fs.commit_transaction();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image;
+    use std::io::{Read, Seek, Write};
+
+    #[test]
+    fn read_write_seek() {
+        let mut fs = image();
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        {
+            let mut f = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .open(&mut fs, "/f")
+                .unwrap();
+            io::copy(&mut &data[..], &mut f).unwrap();
+            // Past the end: leaves a hole
+            f.seek(SeekFrom::Start(20_000)).unwrap();
+            f.write_all(b"tail").unwrap();
+            f.flush().unwrap();
+        }
+        assert!(!fs.in_transaction());
+
+        let mut f = fs.open_file("/f", OpenOptions::new().read(true)).unwrap();
+        assert_eq!(f.len(), 20_004);
+        let mut head = vec![0u8; data.len()];
+        f.read_exact(&mut head).unwrap();
+        assert_eq!(head, data);
+        f.seek(SeekFrom::End(-8)).unwrap();
+        let mut rest = Vec::new();
+        f.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"\0\0\0\0tail");
+        assert_eq!(
+            f.write(b"x").unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        drop(f);
+
+        assert!(matches!(
+            fs.open_file("/f", OpenOptions::new().write(true).create_new(true)),
+            Err(ElfsError::AlreadyExists)
+        ));
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+}
@@ -0,0 +1,764 @@
+// tar archives read into and written out of the image, without a detour over the host
+// filesystem.
+//
+// Both directions speak ustar. On export, whatever doesn't fit its fields (long paths and link
+// targets, big sizes and ids) goes into a pax extended header; import reads pax headers (also
+// global ones) and GNU long-name records. Regular files, directories, symlinks and hard links
+// map to inodes; mode, owner and times are filled in where the image stores them. Anything
+// else (devices, FIFOs, paths that would leave the destination) is skipped and reported.
+
+use super::api::FileKind;
+use super::tree::TreeReport;
+use super::{FileSystem, OpenOptions};
+use crate::fs::error::{ElfsError, Result};
+use crate::fs::layout::Inode;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+const BLOCK: usize = 512;
+// Archives end padded to whole records of 20 blocks, as tar writes them
+const RECORD: u64 = 20 * BLOCK as u64;
+// Largest pax or GNU long-name record accepted on import
+const MAX_META_SIZE: u64 = 1024 * 1024;
+
+// Member types (typeflag)
+const REGULAR: u8 = b'0';
+const REGULAR_OLD: u8 = 0;
+const HARD_LINK: u8 = b'1';
+const SYMLINK: u8 = b'2';
+const DIRECTORY: u8 = b'5';
+const CONTIGUOUS: u8 = b'7';
+const PAX_NEXT: u8 = b'x';
+const PAX_GLOBAL: u8 = b'g';
+const GNU_LONG_NAME: u8 = b'L';
+const GNU_LONG_LINK: u8 = b'K';
+
+// One archive member with pax and GNU long-name records applied
+#[derive(Debug, Clone, Default)]
+struct Member {
+    path: String,
+    kind: u8,
+    link: String,
+    size: u64,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    atime: Option<u64>,
+}
+
+impl FileSystem {
+    /// Extracts a tar archive into the directory `dest`, which is created with its parents when
+    /// missing. Existing files are replaced, existing directories are merged into. Mode, owner
+    /// (only when running as root) and times come from the archive where the image stores
+    /// them. Members that can't be extracted are skipped and listed under their archive path;
+    /// a damaged archive ends the import with [`ElfsError::Corrupt`].
+    pub fn import_tar<R: Read>(&mut self, archive: R, dest: &str) -> Result<TreeReport> {
+        self.atomically(|fs| fs.run_import(archive, dest))
+    }
+
+    /// Writes the directory `src` with everything below it as a tar archive. Member paths are
+    /// relative to `src`; a file with several hard links is stored once and linked to after
+    /// that. Entries that can't be read are listed in the report (a file failing halfway
+    /// keeps its size in the archive, with zeros for the rest).
+    pub fn export_tar<W: Write>(&mut self, src: &str, archive: W) -> Result<TreeReport> {
+        let id = self.resolve_path(src)?;
+        let mut dir = self.read_inode(id)?;
+        if dir.file_type != 1 {
+            return Err(ElfsError::NotADirectory);
+        }
+        let mut out = TarWriter {
+            out: archive,
+            written: 0,
+        };
+        let mut report = TreeReport::default();
+        let mut links = HashMap::new();
+        self.export_children(&mut out, &mut dir, src, "", &mut links, &mut report)?;
+        out.finish()?;
+        Ok(report)
+    }
+
+    fn run_import<R: Read>(&mut self, mut archive: R, dest: &str) -> Result<TreeReport> {
+        self.create_dir_all(dest)?;
+        let mut report = TreeReport::default();
+        // Symlinks are created last, so no member gets written through a link the archive
+        // itself brought along; directory metadata after that, when nothing changes them
+        let mut symlinks = Vec::new();
+        let mut dirs = Vec::new();
+        let mut global = HashMap::new();
+        let mut next = HashMap::new();
+        let mut block = [0u8; BLOCK];
+
+        while read_block(&mut archive, &mut block)? {
+            if block.iter().all(|&b| b == 0) {
+                break;
+            }
+            verify_checksum(&block)?;
+            let size = parse_number(&block[124..136])?;
+            match block[156] {
+                PAX_NEXT => next.extend(parse_pax(&read_meta(&mut archive, size)?)?),
+                PAX_GLOBAL => global.extend(parse_pax(&read_meta(&mut archive, size)?)?),
+                GNU_LONG_NAME => {
+                    let name = c_string(&read_meta(&mut archive, size)?);
+                    next.insert("path".to_string(), name);
+                }
+                GNU_LONG_LINK => {
+                    let link = c_string(&read_meta(&mut archive, size)?);
+                    next.insert("linkpath".to_string(), link);
+                }
+                _ => {
+                    self.checkpoint()?;
+                    let member = Member::parse(&block, &global, &std::mem::take(&mut next))?;
+                    let member_size = member.size;
+                    let mut data = (&mut archive).take(member_size);
+                    match member_path(dest, &member.path) {
+                        None => report.failed.push((member.path, ElfsError::InvalidName)),
+                        Some(path) if member.kind == SYMLINK => symlinks.push((path, member)),
+                        Some(path) => match self.extract(dest, &path, &member, &mut data) {
+                            Ok(id) => {
+                                report.done += 1;
+                                if member.kind == DIRECTORY {
+                                    dirs.push((id, member));
+                                }
+                            }
+                            Err(e) => report.failed.push((member.path, e)),
+                        },
+                    }
+                    // Whatever the member didn't use, so the next header is found
+                    let left = data.limit();
+                    if io::copy(&mut data, &mut io::sink())? != left {
+                        return Err(ElfsError::Corrupt("truncated tar archive"));
+                    }
+                    skip_padding(&mut archive, member_size)?;
+                }
+            }
+        }
+
+        for (path, member) in symlinks {
+            self.checkpoint()?;
+            let created = self
+                .prepare_target(&path)
+                .and_then(|_| self.create_node(&path, FileKind::Symlink, member.link.as_bytes()))
+                .and_then(|id| self.apply_metadata(id, &member));
+            match created {
+                Ok(()) => report.done += 1,
+                Err(e) => report.failed.push((member.path, e)),
+            }
+        }
+        for (id, member) in dirs.into_iter().rev() {
+            self.checkpoint()?;
+            if let Err(e) = self.apply_metadata(id, &member) {
+                report.failed.push((member.path, e));
+            }
+        }
+        Ok(report)
+    }
+
+    // Creates what `member` describes at `path` and returns its inode id
+    fn extract(
+        &mut self,
+        dest: &str,
+        path: &str,
+        member: &Member,
+        data: &mut impl Read,
+    ) -> Result<u32> {
+        let id = match member.kind {
+            DIRECTORY => return self.create_dir_all(path),
+            REGULAR | REGULAR_OLD | CONTIGUOUS => {
+                self.prepare_target(path)?;
+                let mut file =
+                    self.open_file(path, OpenOptions::new().write(true).create_new(true))?;
+                let id = file.inode_id();
+                let copied = io::copy(data, &mut file).and_then(|_| file.flush());
+                drop(file);
+                if let Err(e) = copied {
+                    let _ = self.remove_file(path);
+                    return Err(e.into());
+                }
+                id
+            }
+            HARD_LINK => {
+                let target = member_path(dest, &member.link).ok_or(ElfsError::InvalidName)?;
+                let target_id = self.lookup_nofollow(&target)?;
+                self.prepare_target(path)?;
+                let (parent_id, name) = self.resolve_parent_and_name(path)?;
+                let mut parent = self.read_inode(parent_id)?;
+                self.link(&mut parent, &name, target_id)?;
+                // Metadata belongs to the inode the link shares
+                return Ok(target_id);
+            }
+            _ => return Err(ElfsError::Unsupported("special files")),
+        };
+        self.apply_metadata(id, member)?;
+        Ok(id)
+    }
+
+    // Makes room for a non-directory member: missing parents are created, a file or symlink
+    // in the way is removed, a directory is not
+    fn prepare_target(&mut self, path: &str) -> Result<()> {
+        let parent = match path.rfind('/') {
+            Some(0) => "/",
+            Some(i) => &path[..i],
+            None => ".",
+        };
+        self.create_dir_all(parent)?;
+        match self.lookup_entry(path) {
+            Ok((_, _, inode)) if inode.file_type == 1 => Err(ElfsError::AlreadyExists),
+            Ok((mut parent, name, inode)) => {
+                self.dir_remove_entry(&mut parent, &name)?;
+                self.free_inode(inode.id)
+            }
+            Err(ElfsError::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn apply_metadata(&mut self, id: u32, member: &Member) -> Result<()> {
+        let mut inode = self.read_inode(id)?;
+        // Like chmod/chown: mode for the owner, ownership only for root (a user extracting
+        // an archive ends up owning its files, as with tar)
+        if self.sb.has_permissions() && (self.creds.is_root() || self.creds.uid == inode.uid) {
+            inode.mode = member.mode & 0o777;
+            if self.creds.is_root() {
+                inode.uid = member.uid;
+                inode.gid = member.gid;
+            }
+        }
+        if self.sb.has_timestamps() {
+            inode.mtime = member.mtime;
+            inode.atime = member.atime.unwrap_or(member.mtime);
+        }
+        self.write_inode(id, &inode)
+    }
+
+    // Archive members for the entries of `dir`, sorted by name. Only writing the archive
+    // fails the export; everything else goes to the report.
+    fn export_children<W: Write>(
+        &mut self,
+        out: &mut TarWriter<W>,
+        dir: &mut Inode,
+        fs_path: &str,
+        prefix: &str,
+        links: &mut HashMap<u32, String>,
+        report: &mut TreeReport,
+    ) -> Result<()> {
+        let mut entries = match self.read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.failed.push((fs_path.to_string(), e));
+                return Ok(());
+            }
+        };
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        for entry in entries {
+            let child_path = format!("{}/{}", fs_path.trim_end_matches('/'), entry.name);
+            let archive_path = format!("{prefix}{}", entry.name);
+            match self.read_inode(entry.inode_id) {
+                Ok(mut inode) => {
+                    self.export_entry(out, &mut inode, &child_path, archive_path, links, report)?
+                }
+                Err(e) => report.failed.push((child_path, e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn export_entry<W: Write>(
+        &mut self,
+        out: &mut TarWriter<W>,
+        inode: &mut Inode,
+        fs_path: &str,
+        archive_path: String,
+        links: &mut HashMap<u32, String>,
+        report: &mut TreeReport,
+    ) -> Result<()> {
+        let mut member = Member {
+            path: archive_path,
+            kind: REGULAR,
+            ..self.member_metadata(inode)
+        };
+        match FileKind::of(inode) {
+            FileKind::Dir => {
+                member.kind = DIRECTORY;
+                member.path.push('/');
+            }
+            FileKind::Symlink => match self.readlink_target(inode.id) {
+                Ok(target) => {
+                    member.kind = SYMLINK;
+                    member.link = target;
+                }
+                Err(e) => {
+                    report.failed.push((fs_path.to_string(), e));
+                    return Ok(());
+                }
+            },
+            FileKind::File => match links.get(&inode.id) {
+                Some(first) => {
+                    member.kind = HARD_LINK;
+                    member.link = first.clone();
+                }
+                None => {
+                    if inode.link_count > 1 {
+                        links.insert(inode.id, member.path.clone());
+                    }
+                    member.size = inode.file_size;
+                }
+            },
+        }
+
+        out.header(&member)?;
+        let complete = member.kind != REGULAR || self.export_data(out, inode, fs_path, report)?;
+        if complete {
+            report.done += 1;
+        }
+        if member.kind == DIRECTORY {
+            self.export_children(out, inode, fs_path, &member.path, links, report)?;
+        }
+        Ok(())
+    }
+
+    // Streams a file's content into the archive. After a read error the rest is written as
+    // zeros, so the archive stays readable; returns whether the content is complete.
+    fn export_data<W: Write>(
+        &mut self,
+        out: &mut TarWriter<W>,
+        inode: &mut Inode,
+        fs_path: &str,
+        report: &mut TreeReport,
+    ) -> io::Result<bool> {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut offset = 0;
+        let mut complete = true;
+        while offset < inode.file_size {
+            let n = (inode.file_size - offset).min(buf.len() as u64) as usize;
+            if complete && let Err(e) = self.read_file_range(inode, offset, &mut buf[..n]) {
+                report.failed.push((fs_path.to_string(), e));
+                complete = false;
+            }
+            if !complete {
+                buf[..n].fill(0);
+            }
+            out.write(&buf[..n])?;
+            offset += n as u64;
+        }
+        out.pad()?;
+        Ok(complete)
+    }
+
+    // Mode, owner and mtime as the archive should carry them; images without the fields get
+    // the defaults new inodes get
+    fn member_metadata(&self, inode: &Inode) -> Member {
+        let mut member = Member {
+            mode: crate::fs::perm::default_mode(inode.file_type),
+            mtime: crate::fs::layout::now_timestamp(),
+            ..Member::default()
+        };
+        if self.sb.has_permissions() {
+            member.mode = inode.mode;
+            member.uid = inode.uid;
+            member.gid = inode.gid;
+        }
+        if self.sb.has_timestamps() {
+            member.mtime = inode.mtime;
+        }
+        member
+    }
+}
+
+impl Member {
+    // Header block plus the pax records (`next` for this member, `global` for all) that
+    // override its fields
+    fn parse(
+        block: &[u8; BLOCK],
+        global: &HashMap<String, String>,
+        next: &HashMap<String, String>,
+    ) -> Result<Member> {
+        // An empty value cancels the record
+        let pax = |key: &str| {
+            next.get(key)
+                .or_else(|| global.get(key))
+                .filter(|v| !v.is_empty())
+        };
+        let bad = || ElfsError::Corrupt("bad pax record");
+
+        let mut path = c_string(&block[0..100]);
+        // The prefix field is POSIX ustar only; old GNU archives keep other data there
+        if &block[257..263] == b"ustar\0" {
+            let prefix = c_string(&block[345..500]);
+            if !prefix.is_empty() {
+                path = format!("{prefix}/{path}");
+            }
+        }
+        let mut member = Member {
+            path,
+            kind: block[156],
+            link: c_string(&block[157..257]),
+            size: parse_number(&block[124..136])?,
+            mode: (parse_number(&block[100..108])? & 0o7777) as u16,
+            uid: parse_id(&block[108..116])?,
+            gid: parse_id(&block[116..124])?,
+            mtime: parse_number(&block[136..148])?,
+            atime: None,
+        };
+        if let Some(path) = pax("path") {
+            member.path = path.clone();
+        }
+        if let Some(link) = pax("linkpath") {
+            member.link = link.clone();
+        }
+        if let Some(size) = pax("size") {
+            member.size = size.parse().map_err(|_| bad())?;
+        }
+        if let Some(uid) = pax("uid") {
+            member.uid = uid.parse().map_err(|_| bad())?;
+        }
+        if let Some(gid) = pax("gid") {
+            member.gid = gid.parse().map_err(|_| bad())?;
+        }
+        if let Some(mtime) = pax("mtime") {
+            member.mtime = parse_pax_time(mtime).ok_or_else(bad)?;
+        }
+        if let Some(atime) = pax("atime") {
+            member.atime = Some(parse_pax_time(atime).ok_or_else(bad)?);
+        }
+        Ok(member)
+    }
+}
+
+// Output side of export; counts what was written to pad the archive at the end
+struct TarWriter<W> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.out.write_all(data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    // Zeros up to the next block boundary
+    fn pad(&mut self) -> io::Result<()> {
+        let used = (self.written % BLOCK as u64) as usize;
+        if used == 0 {
+            return Ok(());
+        }
+        self.write(&[0; BLOCK][used..])
+    }
+
+    // ustar header for `member`, preceded by a pax header for what ustar can't hold
+    fn header(&mut self, member: &Member) -> io::Result<()> {
+        let mut records = Vec::new();
+        if split_path(&member.path).is_none() {
+            records.push(("path", member.path.clone()));
+        }
+        if member.link.len() > 100 {
+            records.push(("linkpath", member.link.clone()));
+        }
+        if member.size > 0o77777777777 {
+            records.push(("size", member.size.to_string()));
+        }
+        if member.uid > 0o7777777 {
+            records.push(("uid", member.uid.to_string()));
+        }
+        if member.gid > 0o7777777 {
+            records.push(("gid", member.gid.to_string()));
+        }
+
+        if !records.is_empty() {
+            let data = pax_records(&records);
+            let name = member.path.trim_end_matches('/');
+            let pax = Member {
+                path: format!(
+                    "PaxHeaders/{}",
+                    &name[name.rfind('/').map_or(0, |i| i + 1)..]
+                ),
+                kind: PAX_NEXT,
+                size: data.len() as u64,
+                mode: 0o644,
+                mtime: member.mtime,
+                ..Member::default()
+            };
+            self.write(&ustar_block(&pax))?;
+            self.write(&data)?;
+            self.pad()?;
+        }
+        self.write(&ustar_block(member))
+    }
+
+    // End-of-archive marker (two zero blocks), then padding to a whole record
+    fn finish(mut self) -> io::Result<()> {
+        self.write(&[0; 2 * BLOCK])?;
+        let used = self.written % RECORD;
+        if used != 0 {
+            self.write(&vec![0; (RECORD - used) as usize])?;
+        }
+        self.out.flush()
+    }
+}
+
+fn ustar_block(member: &Member) -> [u8; BLOCK] {
+    let mut block = [0u8; BLOCK];
+    // Paths that don't fit are carried by a pax header; the ustar field gets what fits
+    let (name, prefix) = split_path(&member.path).unwrap_or((&member.path, ""));
+    put_bytes(&mut block[0..100], name);
+    put_octal(&mut block[100..108], u64::from(member.mode));
+    put_octal(&mut block[108..116], u64::from(member.uid));
+    put_octal(&mut block[116..124], u64::from(member.gid));
+    put_octal(&mut block[124..136], member.size);
+    put_octal(&mut block[136..148], member.mtime);
+    block[156] = member.kind;
+    put_bytes(&mut block[157..257], &member.link);
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    put_bytes(&mut block[345..500], prefix);
+
+    // Checksum counts its own field as spaces; stored as six digits, NUL and space
+    block[148..156].fill(b' ');
+    let sum: u64 = block.iter().map(|&b| u64::from(b)).sum();
+    put_octal(&mut block[148..155], sum);
+    block
+}
+
+// ustar name and prefix for `path`, or None when it needs a pax header
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some((path, ""));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| i)
+        .find(|&i| i <= 155 && path.len() - i - 1 <= 100 && i + 1 < path.len())
+        .map(|i| (&path[i + 1..], &path[..i]))
+}
+
+fn put_bytes(field: &mut [u8], value: &str) {
+    let n = value.len().min(field.len());
+    field[..n].copy_from_slice(&value.as_bytes()[..n]);
+}
+
+// Zero-padded octal and a NUL; values that don't fit stay zero (a pax record carries them)
+fn put_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{value:0digits$o}");
+    if text.len() == digits {
+        field[..digits].copy_from_slice(text.as_bytes());
+    } else {
+        field[..digits].fill(b'0');
+    }
+    field[digits] = 0;
+}
+
+// "<length> <key>=<value>\n" records; the length counts its own digits
+fn pax_records(records: &[(&str, String)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (key, value) in records {
+        let base = key.len() + value.len() + 3;
+        let mut len = base + base.to_string().len();
+        if len.to_string().len() + base != len {
+            len = base + len.to_string().len();
+        }
+        data.extend_from_slice(format!("{len} {key}={value}\n").as_bytes());
+    }
+    data
+}
+
+fn parse_pax(data: &[u8]) -> Result<HashMap<String, String>> {
+    let bad = || ElfsError::Corrupt("bad pax record");
+    let mut records = HashMap::new();
+    let mut rest = data;
+    while !rest.is_empty() && rest[0] != 0 {
+        let space = rest.iter().position(|&b| b == b' ').ok_or_else(bad)?;
+        let len: usize = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(bad)?;
+        if len <= space + 1 || len > rest.len() || rest[len - 1] != b'\n' {
+            return Err(bad());
+        }
+        let record = &rest[space + 1..len - 1];
+        let eq = record.iter().position(|&b| b == b'=').ok_or_else(bad)?;
+        records.insert(
+            String::from_utf8_lossy(&record[..eq]).into_owned(),
+            String::from_utf8_lossy(&record[eq + 1..]).into_owned(),
+        );
+        rest = &rest[len..];
+    }
+    Ok(records)
+}
+
+// pax times are decimal seconds, possibly with a fraction; times before 1970 become 0
+fn parse_pax_time(value: &str) -> Option<u64> {
+    let seconds: i64 = value.split('.').next()?.parse().ok()?;
+    Some(seconds.max(0) as u64)
+}
+
+// Octal as tar writes it, or the GNU base-256 form for values that don't fit
+fn parse_number(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        if field[0] & 0x40 != 0 {
+            return Err(ElfsError::Corrupt("negative number in tar header"));
+        }
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x3f), |acc, &b| {
+                acc.checked_mul(256).map(|v| v | u64::from(b))
+            })
+            .ok_or(ElfsError::Corrupt("number in tar header too large"));
+    }
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| ElfsError::Corrupt("bad number in tar header"))
+}
+
+fn parse_id(field: &[u8]) -> Result<u32> {
+    u32::try_from(parse_number(field)?).map_err(|_| ElfsError::Corrupt("bad id in tar header"))
+}
+
+fn verify_checksum(block: &[u8; BLOCK]) -> Result<()> {
+    let stored = parse_number(&block[148..156])?;
+    let sum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u64::from(b)
+            }
+        })
+        .sum();
+    if sum != stored {
+        return Err(ElfsError::Corrupt("tar header checksum mismatch"));
+    }
+    Ok(())
+}
+
+// Text of a NUL-terminated field
+fn c_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// Where an archive path lands under `dest`. Leading "/" and "." components are dropped;
+// None for paths with ".." that could leave `dest`.
+fn member_path(dest: &str, archive_path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for comp in archive_path.split('/') {
+        match comp {
+            "" | "." => {}
+            ".." => return None,
+            c => parts.push(c),
+        }
+    }
+    let base = dest.trim_end_matches('/');
+    if parts.is_empty() {
+        return Some(if base.is_empty() { "/" } else { base }.to_string());
+    }
+    Some(format!("{base}/{}", parts.join("/")))
+}
+
+// Next header block; false at the end of the input
+fn read_block<R: Read>(archive: &mut R, block: &mut [u8; BLOCK]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < BLOCK {
+        match archive.read(&mut block[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    match filled {
+        0 => Ok(false),
+        BLOCK => Ok(true),
+        _ => Err(ElfsError::Corrupt("truncated tar archive")),
+    }
+}
+
+// Content of a pax or GNU long-name member
+fn read_meta<R: Read>(archive: &mut R, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_META_SIZE {
+        return Err(ElfsError::Corrupt("tar metadata record too large"));
+    }
+    let mut data = vec![0u8; size as usize];
+    read_exact(archive, &mut data)?;
+    skip_padding(archive, size)?;
+    Ok(data)
+}
+
+fn skip_padding<R: Read>(archive: &mut R, size: u64) -> Result<()> {
+    let used = (size % BLOCK as u64) as usize;
+    if used == 0 {
+        return Ok(());
+    }
+    read_exact(archive, &mut [0; BLOCK][used..])
+}
+
+fn read_exact<R: Read>(archive: &mut R, buf: &mut [u8]) -> Result<()> {
+    archive.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ElfsError::Corrupt("truncated tar archive"),
+        _ => e.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image;
+
+    #[test]
+    fn tar_round_trip() {
+        let mut fs = image();
+        let long_dir = "d".repeat(120);
+        fs.create_dir_all(&format!("/src/{long_dir}")).unwrap();
+        fs.write_file("/src/a.txt", b"hello").unwrap();
+        fs.write_file(&format!("/src/{long_dir}/big"), &vec![7u8; 70_000])
+            .unwrap();
+        fs.symlink("a.txt", "/src/link").unwrap();
+        let root = fs.resolve_path("/").unwrap();
+        let mut root_dir = fs.read_inode(root).unwrap();
+        let a = fs.resolve_path("/src/a.txt").unwrap();
+        fs.link(&mut root_dir, "hard", a).unwrap();
+        fs.create_dir("/src/h").unwrap();
+        let h = fs.resolve_path("/src/h").unwrap();
+        let mut h = fs.read_inode(h).unwrap();
+        fs.link(&mut h, "again", a).unwrap();
+        fs.chmod(a, 0o600).unwrap();
+
+        let mut archive = Vec::new();
+        let report = fs.export_tar("/src", &mut archive).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(archive.len() as u64 % RECORD, 0);
+
+        let report = fs.import_tar(&archive[..], "/dst/nested").unwrap();
+        assert!(report.is_complete(), "{:?}", report.failed);
+        assert_eq!(fs.read_file("/dst/nested/a.txt").unwrap(), b"hello");
+        assert_eq!(
+            fs.read_file(&format!("/dst/nested/{long_dir}/big"))
+                .unwrap(),
+            vec![7u8; 70_000]
+        );
+        assert_eq!(fs.read_link("/dst/nested/link").unwrap(), "a.txt");
+        let copy = fs.stat("/dst/nested/h/again").unwrap();
+        assert_eq!(copy.inode, fs.stat("/dst/nested/a.txt").unwrap().inode);
+        assert_eq!((copy.mode, copy.links), (0o600, 2));
+        assert!(fs.check(false).unwrap().is_clean());
+
+        // Paths leaving the destination are refused
+        let mut evil = Member {
+            path: "../escape".to_string(),
+            kind: REGULAR,
+            ..Member::default()
+        };
+        let mut archive = ustar_block(&evil).to_vec();
+        evil.path = "ok".to_string();
+        archive.extend_from_slice(&ustar_block(&evil));
+        let report = fs.import_tar(&archive[..], "/evil").unwrap();
+        assert_eq!(report.done, 1);
+        assert_eq!(report.failed[0].0, "../escape");
+    }
+}
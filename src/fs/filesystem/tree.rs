@@ -0,0 +1,358 @@
+// Recursive operations on whole directory trees: creating missing parents, removing and copying.
+//
+// Removal and copying keep going past entries they cannot handle and list them in a TreeReport;
+// only a failure at the top (missing source, taken destination) ends the call with an error.
+// Symlinks are never followed below the top path: they are removed or copied as links.
+
+use super::FileSystem;
+use super::api::FileKind;
+use crate::fs::consts::BLOCK_SIZE;
+use crate::fs::error::{ElfsError, Result};
+use crate::fs::layout::Inode;
+
+/// What [`FileSystem::remove_tree`] and [`FileSystem::copy_tree`] got through.
+#[derive(Debug, Default)]
+pub struct TreeReport {
+    /// Entries removed or created
+    pub done: usize,
+    /// Entries that failed, with the path they have in the source tree. A directory whose
+    /// content could not be removed entirely stays without being listed itself.
+    pub failed: Vec<(String, ElfsError)>,
+}
+
+impl TreeReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl FileSystem {
+    /// Creates the directory at `path` together with every missing parent and returns its
+    /// inode id. An existing directory (or a symlink to one) is fine, anything else in the
+    /// way fails with [`ElfsError::AlreadyExists`] or [`ElfsError::NotADirectory`].
+    pub fn create_dir_all(&mut self, path: &str) -> Result<u32> {
+        if path.is_empty() {
+            return Err(ElfsError::InvalidName);
+        }
+        self.atomically(|fs| {
+            let mut prefix = if path.starts_with('/') {
+                String::from("/")
+            } else {
+                String::new()
+            };
+            let mut id = if path.starts_with('/') {
+                fs.sb.root_inode_id
+            } else {
+                fs.cwd_inode
+            };
+            for comp in path.split('/').filter(|c| !c.is_empty()) {
+                prefix = join(&prefix, comp);
+                id = match fs.resolve_path(&prefix) {
+                    Ok(id) => id,
+                    Err(ElfsError::NotFound) => fs.create_node(&prefix, FileKind::Dir, &[])?,
+                    Err(e) => return Err(e),
+                };
+            }
+            if fs.read_inode(id)?.file_type != 1 {
+                return Err(ElfsError::AlreadyExists);
+            }
+            Ok(id)
+        })
+    }
+
+    /// Removes `path` with everything below it. A symlink is removed itself, its target is
+    /// left alone. The directory the shell is in (or one of its parents) is refused.
+    pub fn remove_tree(&mut self, path: &str) -> Result<TreeReport> {
+        self.atomically(|fs| {
+            let (mut parent, name, inode) = fs.lookup_entry(path)?;
+            if inode.file_type == 1 && fs.is_ancestor(inode.id, fs.cwd_inode)? {
+                return Err(ElfsError::InvalidArgument(
+                    "cannot remove the working directory",
+                ));
+            }
+            let mut report = TreeReport::default();
+            fs.remove_entry(&mut parent, &name, inode.id, path, &mut report)?;
+            Ok(report)
+        })
+    }
+
+    /// Copies `src` to `dst`, which must not exist yet. Directories are copied with their
+    /// content, symlinks as symlinks (the top one included) and every hard link of a file as
+    /// a file of its own.
+    pub fn copy_tree(&mut self, src: &str, dst: &str) -> Result<TreeReport> {
+        self.atomically(|fs| {
+            let mut src_inode = fs.lookup_nofollow(src).and_then(|id| fs.read_inode(id))?;
+            let (parent_id, name) = fs.resolve_parent_and_name(dst)?;
+            if fs.read_inode(parent_id)?.file_type != 1 {
+                return Err(ElfsError::NotADirectory);
+            }
+            if src_inode.file_type == 1 && fs.is_ancestor(src_inode.id, parent_id)? {
+                return Err(ElfsError::InvalidArgument(
+                    "cannot copy a directory into itself",
+                ));
+            }
+            let mut report = TreeReport::default();
+            fs.copy_entry(&mut src_inode, parent_id, &name, src, &mut report)?;
+            Ok(report)
+        })
+    }
+
+    /// Copies the regular file `src` (symlinks followed) to `dst`. An existing file there is
+    /// overwritten in place, so its hard links see the new content; a missing one is created
+    /// and removed again when it can't be filled whole. Holes stay holes.
+    pub fn copy_file(&mut self, src: &str, dst: &str) -> Result<()> {
+        self.atomically(|fs| {
+            let mut src_inode = fs.resolve_path(src).and_then(|id| fs.read_inode(id))?;
+            if src_inode.file_type != 0 {
+                return Err(ElfsError::IsADirectory);
+            }
+            let (parent_id, name) = fs.resolve_parent_and_name(dst)?;
+            let parent = fs.read_inode(parent_id)?;
+            if parent.file_type != 1 {
+                return Err(ElfsError::NotADirectory);
+            }
+            match fs.dir_find(&parent, &name)? {
+                Some((_, entry)) if entry.inode_id == src_inode.id => Ok(()),
+                Some((_, entry)) => {
+                    let mut dst_inode = fs.read_inode(entry.inode_id)?;
+                    if dst_inode.file_type != 0 {
+                        return Err(ElfsError::AlreadyExists);
+                    }
+                    fs.truncate(&mut dst_inode, 0)?;
+                    fs.copy_content(&mut src_inode, dst_inode.id)
+                }
+                None => {
+                    let id = fs.create_child(parent_id, &name, FileKind::File, &[])?;
+                    fs.fill_new_file(&mut src_inode, parent_id, &name, id)
+                }
+            }
+        })
+    }
+
+    // Unlinks `name` from `dir`; a directory goes only after everything below it did.
+    // Failures below are reported, the error returned is for the entry itself.
+    fn remove_entry(
+        &mut self,
+        dir: &mut Inode,
+        name: &str,
+        inode_id: u32,
+        path: &str,
+        report: &mut TreeReport,
+    ) -> Result<()> {
+        let mut inode = self.read_inode(inode_id)?;
+        if inode.file_type == 1 {
+            let failed_before = report.failed.len();
+            self.remove_children(&mut inode, path, report)?;
+            if report.failed.len() > failed_before {
+                return Ok(());
+            }
+        }
+        self.dir_remove_entry(dir, name)?;
+        self.free_inode(inode_id)?;
+        report.done += 1;
+        Ok(())
+    }
+
+    // Fails only when committing the work done so far does
+    fn remove_children(
+        &mut self,
+        dir: &mut Inode,
+        path: &str,
+        report: &mut TreeReport,
+    ) -> Result<()> {
+        let entries = match self.read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.failed.push((path.to_string(), e));
+                return Ok(());
+            }
+        };
+        for entry in entries {
+            self.checkpoint()?;
+            let child_path = join(path, &entry.name);
+            if let Err(e) = self.remove_entry(dir, &entry.name, entry.inode_id, &child_path, report)
+            {
+                report.failed.push((child_path, e));
+            }
+        }
+        Ok(())
+    }
+
+    // Copies `src` as `name` into the directory `parent_id`; for a directory the content
+    // follows, with failures reported under their source path
+    fn copy_entry(
+        &mut self,
+        src: &mut Inode,
+        parent_id: u32,
+        name: &str,
+        src_path: &str,
+        report: &mut TreeReport,
+    ) -> Result<()> {
+        let kind = FileKind::of(src);
+        let id = self.create_child(parent_id, name, kind, &[])?;
+        if kind != FileKind::Dir {
+            self.fill_new_file(src, parent_id, name, id)?;
+            report.done += 1;
+            return Ok(());
+        }
+        report.done += 1;
+
+        let entries = match self.read_dir(src) {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.failed.push((src_path.to_string(), e));
+                return Ok(());
+            }
+        };
+        for entry in entries {
+            self.checkpoint()?;
+            let child_path = join(src_path, &entry.name);
+            let copied = self.read_inode(entry.inode_id).and_then(|mut child| {
+                self.copy_entry(&mut child, id, &entry.name, &child_path, report)
+            });
+            if let Err(e) = copied {
+                report.failed.push((child_path, e));
+            }
+        }
+        Ok(())
+    }
+
+    // Copies `src` into `id`, just created as `name` in `parent_id`. A file that can't be
+    // copied whole doesn't stay behind half-filled.
+    fn fill_new_file(
+        &mut self,
+        src: &mut Inode,
+        parent_id: u32,
+        name: &str,
+        id: u32,
+    ) -> Result<()> {
+        let Err(e) = self.copy_content(src, id) else {
+            return Ok(());
+        };
+        let mut parent = self.read_inode(parent_id)?;
+        if self.dir_remove_entry(&mut parent, name).is_ok() {
+            let _ = self.free_inode(id);
+        }
+        Err(e)
+    }
+
+    // Copies the data of `src` into the empty inode `dst_id`, a bounded chunk at a time.
+    // Holes are skipped, so they stay holes in the copy.
+    fn copy_content(&mut self, src: &mut Inode, dst_id: u32) -> Result<()> {
+        const CHUNK: u64 = 64 * 1024;
+        let block_size = BLOCK_SIZE as u64;
+        let mut dst = self.read_inode(dst_id)?;
+        let mut buf = vec![0u8; CHUNK as usize];
+        for (start, end) in self.mapped_ranges(src)? {
+            let mut offset = start * block_size;
+            let end = (end * block_size).min(src.file_size);
+            while offset < end {
+                let n = (end - offset).min(CHUNK) as usize;
+                self.read_file_range(src, offset, &mut buf[..n])?;
+                self.write_file_range(&mut dst, offset, &buf[..n])?;
+                offset += n as u64;
+            }
+        }
+        // A hole at the end has no blocks, only the size
+        self.resize(&mut dst, src.file_size)
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() || dir.ends_with('/') {
+        format!("{dir}{name}")
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{image, image_of};
+
+    #[test]
+    fn tree_operations() {
+        let mut fs = image();
+        fs.create_dir_all("/a/b/c").unwrap();
+        fs.create_dir_all("a/b").unwrap();
+        fs.write_file("/a/b/c/f.txt", b"data").unwrap();
+        fs.write_file("/outside", b"keep").unwrap();
+        fs.symlink("/outside", "/a/b/link").unwrap();
+        assert!(matches!(
+            fs.create_dir_all("/a/b/c/f.txt"),
+            Err(ElfsError::AlreadyExists)
+        ));
+
+        let report = fs.copy_tree("/a", "/copy").unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.done, 5);
+        assert_eq!(fs.read_file("/copy/b/c/f.txt").unwrap(), b"data");
+        assert_eq!(fs.read_link("/copy/b/link").unwrap(), "/outside");
+        assert!(matches!(
+            fs.copy_tree("/a", "/a/b/inside"),
+            Err(ElfsError::InvalidArgument(_))
+        ));
+
+        assert_eq!(fs.remove_tree("/a").unwrap().done, 5);
+        assert_eq!(fs.read_file("/outside").unwrap(), b"keep");
+        fs.remove_tree("/copy").unwrap();
+        assert_eq!(fs.list_dir("/").unwrap().len(), 1);
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn copy_keeps_holes() {
+        let mut fs = image();
+        let bs = BLOCK_SIZE as u64;
+        // Several chunks of data, a hole, one more block and a hole at the end
+        let data: Vec<u8> = (0..40 * bs).map(|i| (i % 251) as u8).collect();
+        let id = fs.create_file("/sparse").unwrap();
+        let mut inode = fs.read_inode(id).unwrap();
+        fs.write_file_range(&mut inode, 0, &data).unwrap();
+        fs.write_file_range(&mut inode, 100 * bs + 7, b"middle")
+            .unwrap();
+        fs.truncate(&mut inode, 200 * bs).unwrap();
+        fs.create_dir("/dir").unwrap();
+        fs.symlink("../sparse", "/dir/link").unwrap();
+
+        fs.copy_tree("/sparse", "/dir/copy").unwrap();
+        let copy = fs.stat("/dir/copy").unwrap();
+        assert_eq!(copy.size, 200 * bs);
+        assert_eq!(copy.blocks, fs.stat("/sparse").unwrap().blocks);
+        assert_eq!(
+            fs.read_file("/dir/copy").unwrap(),
+            fs.read_file("/sparse").unwrap()
+        );
+
+        // Symlink content goes through the same path
+        let report = fs.copy_tree("/dir", "/dir2").unwrap();
+        assert_eq!(report.done, 3);
+        assert_eq!(fs.read_link("/dir2/link").unwrap(), "../sparse");
+        assert_eq!(fs.stat("/dir2/copy").unwrap().blocks, copy.blocks);
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn long_runs_outgrow_the_journal() {
+        let mut fs = image_of(16 * 1024 * 1024);
+        fs.create_dir("/src").unwrap();
+        for i in 0..120 {
+            fs.symlink("/target", &format!("/src/link{i}")).unwrap();
+        }
+        let mut archive = Vec::new();
+        fs.export_tar("/src", &mut archive).unwrap();
+
+        // One command's transaction, far more blocks than the journal takes at once
+        fs.begin_transaction();
+        assert_eq!(fs.copy_tree("/src", "/copy").unwrap().done, 121);
+        assert_eq!(fs.import_tar(&archive[..], "/tar").unwrap().done, 120);
+        assert_eq!(fs.remove_tree("/src").unwrap().done, 121);
+        fs.commit_transaction().unwrap();
+
+        assert_eq!(fs.read_link("/copy/link119").unwrap(), "/target");
+        assert_eq!(fs.read_link("/tar/link0").unwrap(), "/target");
+        assert!(matches!(fs.stat("/src"), Err(ElfsError::NotFound)));
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+}
@@ -0,0 +1,364 @@
+// Transactions and the block I/O underneath them.
+//
+// While a transaction is open, metadata writes (inodes, directory and symlink contents, pointer
+// blocks, extent nodes, bitmaps) are collected in memory and reads see them. Regular file data
+// goes straight to the image. The outermost commit_transaction() hands the collected blocks to
+// the journal as one unit, so a crash leaves either all of them or none. Recursive and bulk
+// operations commit in between at checkpoint()s, so those are atomic per entry, not as a whole.
+//
+// A commit that fails (a transaction too large for the journal, a device error) rolls the
+// in-memory state back to what the image holds; the transaction takes no more changes until
+// it is closed.
+
+use super::FileSystem;
+use crate::fs::consts::BLOCK_SIZE;
+use crate::fs::journal::{Recovery, Transaction};
+use crate::fs::layout::Inode;
+use std::collections::btree_map::Entry;
+
+impl FileSystem {
+    // Opens a transaction; nested calls join the one already open
+    pub fn begin_transaction(&mut self) {
+        self.txn.get_or_insert_with(Transaction::default).depth += 1;
+    }
+
+    // Closes a transaction; the outermost one commits everything through the journal
+    pub fn commit_transaction(&mut self) -> crate::fs::error::Result<()> {
+        let Some(txn) = self.txn.as_mut() else {
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
+                "no open transaction",
+            ));
+        };
+        txn.depth -= 1;
+        if txn.depth > 0 {
+            return Ok(());
+        }
+        let result = if txn.failed {
+            Err(ROLLED_BACK)
+        } else {
+            self.commit_pending()
+        };
+        self.txn = None;
+        result
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.txn.is_some()
+    }
+
+    /// What [`FileSystem::open`] found in the journal.
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+
+    // Commits what the open transaction collected so far, dirty bitmaps included.
+    // The transaction itself stays open; when the commit fails, it is rolled back.
+    pub(super) fn commit_pending(&mut self) -> crate::fs::error::Result<()> {
+        let Some(txn) = self.txn.as_ref() else {
+            return Ok(());
+        };
+        if txn.failed {
+            return Err(ROLLED_BACK);
+        }
+        let result = self.write_pending();
+        if result.is_err() {
+            self.roll_back();
+        }
+        result
+    }
+
+    // Called by recursive and bulk operations between entries, where the image is consistent:
+    // commits what the open transaction collected so far once it fills half the journal, so
+    // a long run goes through as several transactions the journal can hold
+    pub(crate) fn checkpoint(&mut self) -> crate::fs::error::Result<()> {
+        let Some(txn) = self.txn.as_ref() else {
+            return Ok(());
+        };
+        if !self.sb.has_journal() {
+            return Ok(());
+        }
+        // Dirty bitmaps (freed blocks dirty the data bitmap) are written whole at the commit
+        let blocks_of = |bitmap: &[u8]| bitmap.len().div_ceil(BLOCK_SIZE as usize);
+        let mut pending = txn.blocks.len();
+        if self.bitmap_dirty || !txn.freed.is_empty() {
+            pending += blocks_of(&self.data_bitmap);
+        }
+        if self.inode_bitmap_dirty {
+            pending += blocks_of(&self.inode_bitmap);
+        }
+        if pending * 2 < crate::fs::journal::capacity(&self.sb) {
+            return Ok(());
+        }
+        self.commit_pending()
+    }
+
+    fn write_pending(&mut self) -> crate::fs::error::Result<()> {
+        let Some(txn) = self.txn.as_mut() else {
+            return Ok(());
+        };
+        for b in std::mem::take(&mut txn.freed) {
+            crate::fs::io::free_data_block(&mut self.data_bitmap, &self.sb, b)?;
+            self.bitmap_dirty = true;
+        }
+        let block_size = BLOCK_SIZE as u64;
+        if self.bitmap_dirty {
+            let bitmap = std::mem::take(&mut self.data_bitmap);
+            let staged = self.write_meta_at(self.sb.bitmap_start as u64 * block_size, &bitmap);
+            self.data_bitmap = bitmap;
+            staged?;
+            self.bitmap_dirty = false;
+        }
+        if self.inode_bitmap_dirty {
+            // Nothing to write back for images without an on-disk inode bitmap
+            if self.sb.inode_bitmap_count != 0 {
+                let bitmap = std::mem::take(&mut self.inode_bitmap);
+                let staged =
+                    self.write_meta_at(self.sb.inode_bitmap_start as u64 * block_size, &bitmap);
+                self.inode_bitmap = bitmap;
+                staged?;
+            }
+            self.inode_bitmap_dirty = false;
+        }
+
+        let Some(txn) = self.txn.as_mut() else {
+            return Ok(());
+        };
+        if self.read_only && !txn.blocks.is_empty() {
+            return Err(crate::fs::error::ElfsError::ReadOnly);
+        }
+        let blocks = std::mem::take(&mut txn.blocks);
+        self.journal_seq =
+            crate::fs::journal::commit(self.dev.as_mut(), &self.sb, self.journal_seq, &blocks)?;
+        Ok(())
+    }
+
+    // After a failed commit: drops what the transaction collected and reloads the state from
+    // the image, recovering the journal first (a commit block that reached the disk is
+    // replayed, anything less discarded). If that fails too, the filesystem turns read-only.
+    fn roll_back(&mut self) {
+        if let Some(txn) = self.txn.as_mut() {
+            txn.blocks.clear();
+            txn.freed.clear();
+            txn.failed = true;
+        }
+        self.bitmap_dirty = false;
+        self.inode_bitmap_dirty = false;
+        if self.read_only {
+            return;
+        }
+        let reloaded = crate::fs::journal::recover(self.dev.as_mut(), &self.sb)
+            .map_err(crate::fs::error::ElfsError::from)
+            .and_then(|(_, seq)| {
+                self.journal_seq = seq;
+                self.sb = crate::fs::io::read_superblock(self.dev.as_mut())?;
+                (self.data_bitmap, self.inode_bitmap) = super::load_bitmaps(self)?;
+                Ok(())
+            });
+        if reloaded.is_err() {
+            self.read_only = true;
+        }
+    }
+
+    // Fails when nothing may be written: after a rollback the transaction takes no more
+    // changes, after a failed rollback the whole filesystem
+    pub(super) fn check_writable(&self) -> crate::fs::error::Result<()> {
+        if self.read_only {
+            return Err(crate::fs::error::ElfsError::ReadOnly);
+        }
+        if self.txn.as_ref().is_some_and(|txn| txn.failed) {
+            return Err(ROLLED_BACK);
+        }
+        Ok(())
+    }
+
+    // Releases a data block. Inside a transaction it stays allocated until the commit.
+    pub(crate) fn free_block(&mut self, abs_block: u32) -> crate::fs::error::Result<()> {
+        let Some(txn) = self.txn.as_mut() else {
+            crate::fs::io::free_data_block(&mut self.data_bitmap, &self.sb, abs_block)?;
+            self.bitmap_dirty = true;
+            return Ok(());
+        };
+        if abs_block < self.sb.block_start || abs_block - self.sb.block_start >= self.sb.block_count
+        {
+            return Err(crate::fs::error::ElfsError::InvalidArgument(
+                "block outside data area",
+            ));
+        }
+        // A pending metadata image of the block must not reach the disk after it changes owner
+        txn.blocks.remove(&abs_block);
+        txn.freed.push(abs_block);
+        Ok(())
+    }
+
+    // Reads bytes of the image as the open transaction sees them
+    pub(super) fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> crate::fs::error::Result<()> {
+        self.dev.read_at(offset, buf)?;
+        let Some(txn) = self.txn.as_ref() else {
+            return Ok(());
+        };
+        if buf.is_empty() || txn.blocks.is_empty() {
+            return Ok(());
+        }
+        let block_size = BLOCK_SIZE as u64;
+        let end = offset + buf.len() as u64;
+        let first = (offset / block_size) as u32;
+        let last = ((end - 1) / block_size) as u32;
+        for (&block, image) in txn.blocks.range(first..=last) {
+            let start = block as u64 * block_size;
+            let from = offset.max(start);
+            let to = end.min(start + block_size);
+            buf[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&image[(from - start) as usize..(to - start) as usize]);
+        }
+        Ok(())
+    }
+
+    pub(super) fn read_block(
+        &mut self,
+        block: u32,
+        buf: &mut [u8],
+    ) -> crate::fs::error::Result<()> {
+        self.read_at(block as u64 * BLOCK_SIZE as u64, buf)
+    }
+
+    // Metadata write: collected by the open transaction, written directly otherwise
+    pub(super) fn write_meta_at(
+        &mut self,
+        offset: u64,
+        data: &[u8],
+    ) -> crate::fs::error::Result<()> {
+        self.check_writable()?;
+        let Some(txn) = self.txn.as_mut() else {
+            return Ok(self.dev.write_at(offset, data)?);
+        };
+        let block_size = BLOCK_SIZE as usize;
+        let mut pos = 0;
+        while pos < data.len() {
+            let cursor = offset + pos as u64;
+            let block = (cursor / block_size as u64) as u32;
+            let within = (cursor % block_size as u64) as usize;
+            let n = (data.len() - pos).min(block_size - within);
+            let image = match txn.blocks.entry(block) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    // Partial update: start from the block's current content
+                    let mut image = vec![0u8; block_size];
+                    if n < block_size {
+                        crate::fs::io::read_block(
+                            self.dev.as_mut(),
+                            BLOCK_SIZE,
+                            block as u64,
+                            &mut image,
+                        )?;
+                    }
+                    e.insert(image)
+                }
+            };
+            image[within..within + n].copy_from_slice(&data[pos..pos + n]);
+            pos += n;
+        }
+        Ok(())
+    }
+
+    pub(super) fn write_block(&mut self, block: u32, buf: &[u8]) -> crate::fs::error::Result<()> {
+        self.write_meta_at(block as u64 * BLOCK_SIZE as u64, buf)
+    }
+
+    // Content of `inode` at an image offset: regular file data bypasses the journal,
+    // directory and symlink contents are metadata
+    pub(super) fn write_content_at(
+        &mut self,
+        inode: &Inode,
+        offset: u64,
+        data: &[u8],
+    ) -> crate::fs::error::Result<()> {
+        if inode.file_type == 0 {
+            self.check_writable()?;
+            Ok(self.dev.write_at(offset, data)?)
+        } else {
+            self.write_meta_at(offset, data)
+        }
+    }
+}
+
+const ROLLED_BACK: crate::fs::error::ElfsError =
+    crate::fs::error::ElfsError::InvalidArgument("transaction was rolled back");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::device::FailingDevice;
+    use crate::fs::error::ElfsError;
+
+    const SIZE: u64 = 4 * 1024 * 1024;
+
+    // Image with one file, ready for a transaction that removes it
+    fn image() -> (FileSystem, FailingDevice) {
+        let dev = FailingDevice::new(SIZE);
+        let mut fs = FileSystem::format(dev.clone(), SIZE).unwrap();
+        fs.write_file("/kept", b"kept").unwrap();
+        (fs, dev)
+    }
+
+    #[test]
+    fn failed_commit_rolls_back() {
+        let (mut fs, dev) = image();
+        let bitmaps = (fs.data_bitmap().to_vec(), fs.inode_bitmap().to_vec());
+
+        // Too many blocks for the journal: refused, nothing of it stays, not even in memory
+        fs.begin_transaction();
+        for i in 0..40 {
+            fs.symlink("/kept", &format!("/link{i}")).unwrap();
+        }
+        assert!(matches!(
+            fs.commit_transaction(),
+            Err(ElfsError::TransactionTooLarge)
+        ));
+        assert!(matches!(fs.lstat("/link0"), Err(ElfsError::NotFound)));
+        assert_eq!(
+            (fs.data_bitmap(), fs.inode_bitmap()),
+            (&*bitmaps.0, &*bitmaps.1)
+        );
+
+        // The device refuses the journal write: same, and the next commit goes through
+        dev.fail_after(Some(0));
+        assert!(matches!(fs.create_dir_all("/a/b"), Err(ElfsError::Io(_))));
+        dev.fail_after(None);
+        assert!(matches!(fs.stat("/a"), Err(ElfsError::NotFound)));
+        assert_eq!(
+            (fs.data_bitmap(), fs.inode_bitmap()),
+            (&*bitmaps.0, &*bitmaps.1)
+        );
+        assert!(fs.check(false).unwrap().is_clean());
+        fs.create_dir_all("/a/b").unwrap();
+        assert_eq!(fs.read_file("/kept").unwrap(), b"kept");
+    }
+
+    #[test]
+    fn failed_checkpoint_turns_read_only() {
+        // Same removal on a twin image to count its writes: descriptor, n blocks and commit,
+        // then n blocks of checkpoint and the journal clear
+        let (mut twin, twin_dev) = image();
+        let before = twin_dev.writes();
+        twin.remove_file("/kept").unwrap();
+        let n = (twin_dev.writes() - before - 3) / 2;
+
+        // Crash right after the commit block: the rollback would have to replay the journal,
+        // which the device doesn't let it
+        let (mut fs, dev) = image();
+        dev.fail_after(Some(n + 2));
+        assert!(fs.remove_file("/kept").is_err());
+        dev.fail_after(None);
+        assert!(matches!(
+            fs.write_file("/other", b"x"),
+            Err(ElfsError::ReadOnly)
+        ));
+
+        // Reopening replays the committed removal
+        drop(fs);
+        let mut fs = FileSystem::open(dev.clone()).unwrap();
+        assert!(matches!(fs.recovery(), Recovery::Replayed { .. }));
+        assert!(matches!(fs.stat("/kept"), Err(ElfsError::NotFound)));
+        assert!(fs.check(false).unwrap().is_clean());
+    }
+}
@@ -1,62 +1,63 @@
-use crate::fs::consts::{BLOCK_SIZE, FS_MAGIC, INODE_SIZE};
+use crate::fs::consts::{
+    BLOCK_SIZE, DEFAULT_FEATURES, FEATURE_JOURNAL, FS_MAGIC, INODE_SIZE, INODE_VERSION,
+    JOURNAL_MAX_BLOCKS, JOURNAL_MIN_BLOCKS,
+};
+use crate::fs::device::BlockDevice;
 use crate::fs::layout::{Inode, Superblock};
-use std::fs::File;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io;
 
 pub fn write_block(
-    f: &mut File,
+    dev: &mut dyn BlockDevice,
     block_size: u32,
     block_index: u64,
     buf: &[u8],
 ) -> std::io::Result<()> {
     debug_assert_eq!(buf.len(), block_size as usize);
-    f.seek(SeekFrom::Start(block_index * block_size as u64))?;
-    f.write_all(buf)?;
+    dev.write_at(block_index * block_size as u64, buf)?;
     Ok(())
 }
 
 pub fn read_block(
-    f: &mut File,
+    dev: &mut dyn BlockDevice,
     block_size: u32,
     block_index: u64,
     buf: &mut [u8],
 ) -> io::Result<()> {
     debug_assert_eq!(buf.len(), block_size as usize);
-    f.seek(SeekFrom::Start(block_index * block_size as u64))?;
-    f.read_exact(buf)?;
+    dev.read_at(block_index * block_size as u64, buf)?;
     Ok(())
 }
 
 pub fn write_span(
-    f: &mut File,
+    dev: &mut dyn BlockDevice,
     start_block: u64,
     count: u64,
     block_size: u32,
     buf: &[u8],
 ) -> std::io::Result<()> {
     debug_assert_eq!(buf.len(), (count as usize) * (block_size as usize));
-    f.seek(SeekFrom::Start(start_block * block_size as u64))?;
-    f.write_all(buf)?;
+    dev.write_at(start_block * block_size as u64, buf)?;
     Ok(())
 }
 
 pub fn read_span(
-    f: &mut File,
+    dev: &mut dyn BlockDevice,
     start_block: u64,
     count: u64,
     block_size: u32,
     buf: &mut [u8],
 ) -> std::io::Result<()> {
     debug_assert_eq!(buf.len(), (count as usize) * (block_size as usize));
-    f.seek(SeekFrom::Start(start_block * block_size as u64))?;
-    f.read_exact(buf)?;
+    dev.read_at(start_block * block_size as u64, buf)?;
     Ok(())
 }
 
-pub fn write_superblock(f: &mut File, sb: &Superblock) -> std::io::Result<()> {
+pub fn write_superblock(dev: &mut dyn BlockDevice, sb: &Superblock) -> std::io::Result<()> {
     // Serialize in the exact order defined in layout.rs:
     // fs_size, magic, root_inode_id, bitmap_start, bitmap_count,
-    // block_start, block_count, inode_start, inode_count
+    // block_start, block_count, inode_start, inode_count,
+    // inode_bitmap_start, inode_bitmap_count, inode_version, features,
+    // journal_start, journal_count
     let mut block0 = vec![0u8; BLOCK_SIZE as usize];
     block0[0..8].copy_from_slice(&sb.fs_size.to_le_bytes());
     block0[8..12].copy_from_slice(&sb.magic);
@@ -67,14 +68,20 @@ pub fn write_superblock(f: &mut File, sb: &Superblock) -> std::io::Result<()> {
     block0[28..32].copy_from_slice(&sb.block_count.to_le_bytes());
     block0[32..36].copy_from_slice(&sb.inode_start.to_le_bytes());
     block0[36..40].copy_from_slice(&sb.inode_count.to_le_bytes());
-
-    write_block(f, BLOCK_SIZE, 0, &block0)?;
+    block0[40..44].copy_from_slice(&sb.inode_bitmap_start.to_le_bytes());
+    block0[44..48].copy_from_slice(&sb.inode_bitmap_count.to_le_bytes());
+    block0[48..52].copy_from_slice(&sb.inode_version.to_le_bytes());
+    block0[52..56].copy_from_slice(&sb.features.to_le_bytes());
+    block0[56..60].copy_from_slice(&sb.journal_start.to_le_bytes());
+    block0[60..64].copy_from_slice(&sb.journal_count.to_le_bytes());
+
+    write_block(dev, BLOCK_SIZE, 0, &block0)?;
     Ok(())
 }
 
-pub fn read_superblock(f: &mut File) -> std::io::Result<Superblock> {
+pub fn read_superblock(dev: &mut dyn BlockDevice) -> std::io::Result<Superblock> {
     let mut block0 = vec![0u8; BLOCK_SIZE as usize];
-    read_block(f, BLOCK_SIZE, 0, &mut block0)?;
+    read_block(dev, BLOCK_SIZE, 0, &mut block0)?;
 
     let fs_size = u64::from_le_bytes(block0[0..8].try_into().unwrap());
     let magic: [u8; 4] = block0[8..12].try_into().unwrap();
@@ -89,6 +96,15 @@ pub fn read_superblock(f: &mut File) -> std::io::Result<Superblock> {
     let block_count = u32::from_le_bytes(block0[28..32].try_into().unwrap());
     let inode_start = u32::from_le_bytes(block0[32..36].try_into().unwrap());
     let inode_count = u32::from_le_bytes(block0[36..40].try_into().unwrap());
+    // Older images left these bytes zeroed -> no on-disk inode bitmap
+    let inode_bitmap_start = u32::from_le_bytes(block0[40..44].try_into().unwrap());
+    let inode_bitmap_count = u32::from_le_bytes(block0[44..48].try_into().unwrap());
+    // Zero on images with the original 48 B inodes
+    let inode_version = u32::from_le_bytes(block0[48..52].try_into().unwrap());
+    let features = u32::from_le_bytes(block0[52..56].try_into().unwrap());
+    // Zero on images formatted before the journal
+    let journal_start = u32::from_le_bytes(block0[56..60].try_into().unwrap());
+    let journal_count = u32::from_le_bytes(block0[60..64].try_into().unwrap());
 
     Ok(Superblock {
         fs_size,
@@ -100,6 +116,12 @@ pub fn read_superblock(f: &mut File) -> std::io::Result<Superblock> {
         block_count,
         inode_start,
         inode_count,
+        inode_bitmap_start,
+        inode_bitmap_count,
+        inode_version,
+        features,
+        journal_start,
+        journal_count,
     })
 }
 
@@ -115,17 +137,33 @@ pub fn compute_layout(fs_bytes: u64, block_size: u32, bytes_per_inode: u32) -> S
     let blocks_total_sb = (fs_bytes / block_size_bytes) as u32;
     let blocks_total = blocks_total_sb.saturating_sub(1); // exclude superblock
 
+    // Journal region right after the superblock; images too small for one go without
+    let journal_blocks = journal_size(blocks_total);
+    let features = if journal_blocks == 0 {
+        DEFAULT_FEATURES & !FEATURE_JOURNAL
+    } else {
+        DEFAULT_FEATURES
+    };
+    let first = 1 + journal_blocks;
+    let blocks_total = blocks_total - journal_blocks;
+
     if blocks_total == 0 {
         return Superblock {
             fs_size: fs_bytes,
             magic: FS_MAGIC,
             root_inode_id: 0,
-            block_start: 1,
+            block_start: first,
             block_count: 0,
-            inode_start: 1,
+            inode_start: first,
             inode_count: 0,
-            bitmap_start: 1,
+            bitmap_start: first,
             bitmap_count: 0,
+            inode_bitmap_start: first,
+            inode_bitmap_count: 0,
+            inode_version: INODE_VERSION,
+            features,
+            journal_start: 1,
+            journal_count: journal_blocks,
         };
     }
 
@@ -138,58 +176,79 @@ pub fn compute_layout(fs_bytes: u64, block_size: u32, bytes_per_inode: u32) -> S
     let inode_count_est = inode_count_est.max(1);
 
     // Step 2: Inode table and data blocks (estimate)
-    let inode_table_blocks_est =
-        ((inode_count_est as u64).saturating_mul(inode_size_bytes) + block_size_bytes - 1)
-            / block_size_bytes;
+    let inode_table_blocks_est = (inode_count_est as u64)
+        .saturating_mul(inode_size_bytes)
+        .div_ceil(block_size_bytes);
     let inode_table_blocks_est_u32 = (inode_table_blocks_est as u32).min(blocks_total);
     let data_blocks_est = blocks_total.saturating_sub(inode_table_blocks_est_u32);
 
     // Step 3: One-shot correction: ensure I_final <= floor(D_est / K)
-    let inode_count_final = inode_count_est.min(if avg_data_blocks_per_inode > 0 {
-        data_blocks_est / avg_data_blocks_per_inode
-    } else {
+    let inode_count_final = inode_count_est.min(
         data_blocks_est
-    });
-    let inode_table_blocks_final =
-        (((inode_count_final as u64).saturating_mul(inode_size_bytes) + block_size_bytes - 1)
-            / block_size_bytes) as u32;
+            .checked_div(avg_data_blocks_per_inode)
+            .unwrap_or(data_blocks_est),
+    );
+    let inode_table_blocks_final = (inode_count_final as u64)
+        .saturating_mul(inode_size_bytes)
+        .div_ceil(block_size_bytes) as u32;
+
+    // Inode bitmap: one bit per inode
+    let bits_per_block = block_size_bytes * 8;
+    let inode_bitmap_blocks = (inode_count_final as u64).div_ceil(bits_per_block) as u32;
+    let metadata_blocks = inode_table_blocks_final + inode_bitmap_blocks;
 
     // Estimate number of bitmap blocks required to track data blocks.
     // Each bitmap block holds (block_size_bytes * 8) bits -> that many data blocks.
     let mut bitmap_blocks: u32 = 0;
     for _ in 0..3 {
-        let data_blocks_tmp = blocks_total.saturating_sub(inode_table_blocks_final + bitmap_blocks);
-        let bits_per_bitmap_block = (block_size_bytes * 8) as u64;
-        let needed = ((data_blocks_tmp as u64) + bits_per_bitmap_block - 1) / bits_per_bitmap_block;
+        let data_blocks_tmp = blocks_total.saturating_sub(metadata_blocks + bitmap_blocks);
+        let bits_per_bitmap_block = block_size_bytes * 8;
+        let needed = (data_blocks_tmp as u64).div_ceil(bits_per_bitmap_block);
         let needed_u32 = needed as u32;
         if needed_u32 == bitmap_blocks {
             break;
         }
         bitmap_blocks = needed_u32;
     }
-    let data_blocks_final = blocks_total.saturating_sub(inode_table_blocks_final + bitmap_blocks);
+    let data_blocks_final = blocks_total.saturating_sub(metadata_blocks + bitmap_blocks);
 
     // Step 4: Populate superblock
+    // Order on disk: superblock | journal | data bitmap | inode bitmap | inode table | data blocks
     Superblock {
         fs_size: fs_bytes,
         magic: FS_MAGIC,
         root_inode_id: 0,
-        block_start: 1 + bitmap_blocks + inode_table_blocks_final,
+        block_start: first + bitmap_blocks + metadata_blocks,
         block_count: data_blocks_final,
-        inode_start: 1 + bitmap_blocks,
+        inode_start: first + bitmap_blocks + inode_bitmap_blocks,
         inode_count: inode_count_final,
-        bitmap_start: 1,
+        bitmap_start: first,
         bitmap_count: bitmap_blocks,
+        inode_bitmap_start: first + bitmap_blocks,
+        inode_bitmap_count: inode_bitmap_blocks,
+        inode_version: INODE_VERSION,
+        features,
+        journal_start: 1,
+        journal_count: journal_blocks,
     }
 }
 
-pub fn load_bitmap(f: &mut File, sb: &Superblock) -> io::Result<Vec<u8>> {
+// 1/64 of the image within JOURNAL_MIN_BLOCKS..=JOURNAL_MAX_BLOCKS; none when the journal
+// would take more than a quarter of the blocks
+fn journal_size(blocks_total: u32) -> u32 {
+    if blocks_total < 4 * JOURNAL_MIN_BLOCKS {
+        return 0;
+    }
+    (blocks_total / 64).clamp(JOURNAL_MIN_BLOCKS, JOURNAL_MAX_BLOCKS)
+}
+
+pub fn load_bitmap(dev: &mut dyn BlockDevice, sb: &Superblock) -> io::Result<Vec<u8>> {
     if sb.bitmap_count == 0 {
         return Ok(Vec::new());
     }
     let mut buf = vec![0u8; (sb.bitmap_count as usize) * (BLOCK_SIZE as usize)];
     read_span(
-        f,
+        dev,
         sb.bitmap_start as u64,
         sb.bitmap_count as u64,
         BLOCK_SIZE,
@@ -198,23 +257,54 @@ pub fn load_bitmap(f: &mut File, sb: &Superblock) -> io::Result<Vec<u8>> {
     Ok(buf)
 }
 
-pub fn flush_bitmap(f: &mut File, sb: &Superblock, bitmap: &[u8]) -> io::Result<()> {
-    let expected = (sb.bitmap_count as usize) * (BLOCK_SIZE as usize);
+pub fn load_inode_bitmap(dev: &mut dyn BlockDevice, sb: &Superblock) -> io::Result<Vec<u8>> {
+    if sb.inode_bitmap_count == 0 {
+        return Ok(Vec::new());
+    }
+    let mut buf = vec![0u8; (sb.inode_bitmap_count as usize) * (BLOCK_SIZE as usize)];
+    read_span(
+        dev,
+        sb.inode_bitmap_start as u64,
+        sb.inode_bitmap_count as u64,
+        BLOCK_SIZE,
+        &mut buf,
+    )?;
+    Ok(buf)
+}
+
+pub fn flush_inode_bitmap(
+    dev: &mut dyn BlockDevice,
+    sb: &Superblock,
+    bitmap: &[u8],
+) -> io::Result<()> {
+    let expected = (sb.inode_bitmap_count as usize) * (BLOCK_SIZE as usize);
     if bitmap.len() != expected {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
-            "bitmap length does not match superblock bitmap_count",
+            "inode bitmap length does not match superblock inode_bitmap_count",
         ));
     }
     write_span(
-        f,
-        sb.bitmap_start as u64,
-        sb.bitmap_count as u64,
+        dev,
+        sb.inode_bitmap_start as u64,
+        sb.inode_bitmap_count as u64,
         BLOCK_SIZE,
         bitmap,
     )
 }
 
+// Rebuilds the inode bitmap of an image without the on-disk region by scanning link_count.
+pub fn scan_inode_bitmap(dev: &mut dyn BlockDevice, sb: &Superblock) -> io::Result<Vec<u8>> {
+    let mut bitmap = vec![0u8; (sb.inode_count as usize).div_ceil(8)];
+    for id in 0..sb.inode_count {
+        let ino = read_inode(dev, sb, id)?;
+        if ino.link_count != 0 || id == sb.root_inode_id {
+            bitmap_set(&mut bitmap, id);
+        }
+    }
+    Ok(bitmap)
+}
+
 #[inline]
 pub fn bitmap_is_set(bitmap: &[u8], idx: u32) -> bool {
     let byte = (idx / 8) as usize;
@@ -262,6 +352,38 @@ pub fn alloc_data_block(bitmap: &mut [u8], sb: &Superblock) -> Option<u32> {
     }
 }
 
+// Allocates up to `want` contiguous data blocks: continues at `goal` (absolute block) when it
+// is free, otherwise takes the first free run. Returns (first absolute block, run length).
+pub fn alloc_data_run(
+    bitmap: &mut [u8],
+    sb: &Superblock,
+    goal: Option<u32>,
+    want: u32,
+) -> Option<(u32, u32)> {
+    if want == 0 {
+        return None;
+    }
+    let goal_rel = goal
+        .and_then(|g| g.checked_sub(sb.block_start))
+        .filter(|&rel| rel < sb.block_count && !bitmap_is_set(bitmap, rel));
+    let start = match goal_rel {
+        Some(rel) => rel,
+        None => find_free_data_block(bitmap, sb.block_count)?,
+    };
+    let mut len = 0;
+    while len < want && start + len < sb.block_count && !bitmap_is_set(bitmap, start + len) {
+        bitmap_set(bitmap, start + len);
+        len += 1;
+    }
+    Some((sb.block_start + start, len))
+}
+
+pub fn alloc_inode_id(inode_bitmap: &mut [u8], sb: &Superblock) -> Option<u32> {
+    let id = find_free_data_block(inode_bitmap, sb.inode_count)?;
+    bitmap_set(inode_bitmap, id);
+    Some(id)
+}
+
 pub fn free_data_block(bitmap: &mut [u8], sb: &Superblock, abs_block: u32) -> io::Result<()> {
     if abs_block < sb.block_start {
         return Err(io::Error::new(
@@ -280,57 +402,33 @@ pub fn free_data_block(bitmap: &mut [u8], sb: &Superblock, abs_block: u32) -> io
     Ok(())
 }
 
-pub fn read_inode(f: &mut File, sb: &Superblock, inode_id: u32) -> io::Result<Inode> {
+// Byte offset of an inode in the inode table
+pub fn inode_offset(sb: &Superblock, inode_id: u32) -> io::Result<u64> {
     if inode_id >= sb.inode_count {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             "inode_id out of range",
         ));
     }
+    let inode_table_base = (sb.inode_start as u64) * (BLOCK_SIZE as u64);
+    Ok(inode_table_base + (inode_id as u64) * (sb.inode_size() as u64))
+}
 
-    let block_size_bytes = BLOCK_SIZE as u64;
-    let inode_table_base = (sb.inode_start as u64) * block_size_bytes;
-    let inode_offset = inode_table_base + (inode_id as u64) * (INODE_SIZE as u64);
-
-    let mut buf = vec![0u8; INODE_SIZE];
-    f.seek(SeekFrom::Start(inode_offset))?;
-    f.read_exact(&mut buf)?;
-
-    let file_size = u64::from_le_bytes(buf[0..8].try_into().unwrap());
-    let id = u32::from_le_bytes(buf[8..12].try_into().unwrap());
-
-    let mut single_directs = [0u32; 5];
-    for i in 0..5 {
-        let start = 12 + i * 4;
-        single_directs[i] = u32::from_le_bytes(buf[start..start + 4].try_into().unwrap());
-    }
+pub fn read_inode(dev: &mut dyn BlockDevice, sb: &Superblock, inode_id: u32) -> io::Result<Inode> {
+    let inode_offset = inode_offset(sb, inode_id)?;
+    let mut buf = vec![0u8; sb.inode_size()];
+    dev.read_at(inode_offset, &mut buf)?;
 
-    let single_indirect = u32::from_le_bytes(buf[32..36].try_into().unwrap());
-    let double_indirect = u32::from_le_bytes(buf[36..40].try_into().unwrap());
-    let file_type = buf[40];
-    let link_count = buf[41];
-    let mut _reserved = [0u8; 6];
-    _reserved.copy_from_slice(&buf[42..48]);
-
-    Ok(Inode {
-        file_size,
-        id,
-        single_directs,
-        single_indirect,
-        double_indirect,
-        file_type,
-        link_count,
-        _reserved,
-    })
+    Ok(Inode::from_bytes(&buf))
 }
 
-pub fn write_inode(f: &mut File, sb: &Superblock, inode_id: u32, inode: &Inode) -> io::Result<()> {
-    if inode_id >= sb.inode_count {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "inode_id out of range",
-        ));
-    }
+pub fn write_inode(
+    dev: &mut dyn BlockDevice,
+    sb: &Superblock,
+    inode_id: u32,
+    inode: &Inode,
+) -> io::Result<()> {
+    let inode_offset = inode_offset(sb, inode_id)?;
     // Debug / safety: ensure caller passes matching logical and stored inode id.
     debug_assert_eq!(inode_id, inode.id, "write_inode: inode_id != inode.id");
     if inode_id != inode.id {
@@ -340,26 +438,9 @@ pub fn write_inode(f: &mut File, sb: &Superblock, inode_id: u32, inode: &Inode)
         ));
     }
 
-    let block_size_bytes = BLOCK_SIZE as u64;
-    let inode_table_base = (sb.inode_start as u64) * block_size_bytes;
-    let inode_offset = inode_table_base + (inode_id as u64) * (INODE_SIZE as u64);
+    // Serialize fields to little-endian byte layout (v1 images keep only the first 48 B)
+    let buf = inode.to_bytes(sb.inode_size());
 
-    let mut buf = vec![0u8; INODE_SIZE];
-
-    // Serialize fields to little-endian byte layout
-    buf[0..8].copy_from_slice(&inode.file_size.to_le_bytes());
-    buf[8..12].copy_from_slice(&inode.id.to_le_bytes());
-    for i in 0..5 {
-        let start = 12 + i * 4;
-        buf[start..start + 4].copy_from_slice(&inode.single_directs[i].to_le_bytes());
-    }
-    buf[32..36].copy_from_slice(&inode.single_indirect.to_le_bytes());
-    buf[36..40].copy_from_slice(&inode.double_indirect.to_le_bytes());
-    buf[40] = inode.file_type;
-    buf[41] = inode.link_count;
-    buf[42..48].copy_from_slice(&inode._reserved);
-
-    f.seek(SeekFrom::Start(inode_offset))?;
-    f.write_all(&buf)?;
+    dev.write_at(inode_offset, &buf)?;
     Ok(())
 }
@@ -0,0 +1,295 @@
+// Write-ahead journal for metadata blocks.
+//
+// The journal region holds at most one transaction at a time:
+//   block 0          descriptor: magic, sequence, count, then `count` target block numbers
+//   blocks 1..=count new contents of the target blocks
+//   block count + 1  commit: magic, sequence, count, checksum of descriptor and contents
+// A transaction is durable once its commit block is on disk. The blocks are then written to
+// their home locations and the descriptor magic is cleared. After a crash, `recover` replays a
+// transaction with a valid commit block and discards one without.
+
+use crate::fs::consts::{
+    BLOCK_SIZE, JOURNAL_COMMIT_MAGIC, JOURNAL_DESC_MAGIC, JOURNAL_HEADER_SIZE,
+};
+use crate::fs::device::BlockDevice;
+use crate::fs::layout::Superblock;
+use std::collections::BTreeMap;
+use std::io;
+
+// Metadata changes collected between begin_transaction() and commit_transaction()
+#[derive(Debug, Default)]
+pub struct Transaction {
+    // New contents of metadata blocks by absolute block number
+    pub blocks: BTreeMap<u32, Vec<u8>>,
+    // Data blocks released inside the transaction. They stay allocated until the commit, so a
+    // new owner can't overwrite them before the change that freed them is durable.
+    pub freed: Vec<u32>,
+    // Nesting level of begin_transaction() calls
+    pub depth: u32,
+    // A commit failed and rolled the transaction back; it takes no more changes
+    pub failed: bool,
+}
+
+// What FileSystem::open found in the journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    Clean,
+    // A committed transaction was written to its home locations
+    Replayed { blocks: u32 },
+    // A transaction without a valid commit block was dropped
+    Discarded,
+}
+
+// Blocks one transaction can carry: limited by the descriptor and by the region size
+pub fn capacity(sb: &Superblock) -> usize {
+    let per_descriptor = (BLOCK_SIZE as usize - JOURNAL_HEADER_SIZE) / 4;
+    per_descriptor.min(sb.journal_count.saturating_sub(2) as usize)
+}
+
+// Writes `blocks` through the journal and then to their home locations as one transaction and
+// returns its sequence number. A set larger than capacity() is refused before anything is
+// written: split up, it would no longer be atomic. Images without a journal get the blocks
+// written directly.
+pub fn commit(
+    dev: &mut dyn BlockDevice,
+    sb: &Superblock,
+    seq: u32,
+    blocks: &BTreeMap<u32, Vec<u8>>,
+) -> io::Result<u32> {
+    if blocks.is_empty() {
+        return Ok(seq);
+    }
+    if !sb.has_journal() {
+        for (&block, data) in blocks {
+            crate::fs::io::write_block(dev, BLOCK_SIZE, block as u64, data)?;
+        }
+        return Ok(seq);
+    }
+
+    if blocks.len() > capacity(sb) {
+        return Err(crate::fs::error::ElfsError::TransactionTooLarge.into());
+    }
+
+    let seq = seq.wrapping_add(1);
+    let start = sb.journal_start as u64;
+
+    let mut desc = vec![0u8; BLOCK_SIZE as usize];
+    desc[0..4].copy_from_slice(&JOURNAL_DESC_MAGIC);
+    desc[4..8].copy_from_slice(&seq.to_le_bytes());
+    desc[8..12].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+    for (i, block) in blocks.keys().enumerate() {
+        let off = JOURNAL_HEADER_SIZE + i * 4;
+        desc[off..off + 4].copy_from_slice(&block.to_le_bytes());
+    }
+    let mut sum = checksum(FNV_OFFSET, &desc);
+    crate::fs::io::write_block(dev, BLOCK_SIZE, start, &desc)?;
+    for (i, data) in blocks.values().enumerate() {
+        sum = checksum(sum, data);
+        crate::fs::io::write_block(dev, BLOCK_SIZE, start + 1 + i as u64, data)?;
+    }
+
+    // The checksum covers everything above, so a torn write can't pass for a commit
+    let mut commit = vec![0u8; BLOCK_SIZE as usize];
+    commit[0..4].copy_from_slice(&JOURNAL_COMMIT_MAGIC);
+    commit[4..8].copy_from_slice(&seq.to_le_bytes());
+    commit[8..12].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+    commit[12..16].copy_from_slice(&sum.to_le_bytes());
+    crate::fs::io::write_block(dev, BLOCK_SIZE, start + 1 + blocks.len() as u64, &commit)?;
+    dev.flush()?;
+
+    // Checkpoint
+    for (&block, data) in blocks {
+        crate::fs::io::write_block(dev, BLOCK_SIZE, block as u64, data)?;
+    }
+    dev.flush()?;
+    clear(dev, sb)?;
+    Ok(seq)
+}
+
+// Replays or discards what a crash left in the journal. Also returns the last sequence number
+// so new transactions continue after it.
+pub fn recover(dev: &mut dyn BlockDevice, sb: &Superblock) -> io::Result<(Recovery, u32)> {
+    if !sb.has_journal() {
+        return Ok((Recovery::Clean, 0));
+    }
+    let start = sb.journal_start as u64;
+    let mut desc = vec![0u8; BLOCK_SIZE as usize];
+    crate::fs::io::read_block(dev, BLOCK_SIZE, start, &mut desc)?;
+    let seq = u32::from_le_bytes(desc[4..8].try_into().unwrap());
+    if desc[0..4] != JOURNAL_DESC_MAGIC {
+        return Ok((Recovery::Clean, seq));
+    }
+
+    let count = u32::from_le_bytes(desc[8..12].try_into().unwrap()) as usize;
+    let fs_blocks = sb.fs_size / BLOCK_SIZE as u64;
+    let targets: Vec<u32> = (0..count.min(capacity(sb)))
+        .map(|i| {
+            let off = JOURNAL_HEADER_SIZE + i * 4;
+            u32::from_le_bytes(desc[off..off + 4].try_into().unwrap())
+        })
+        .collect();
+    let in_journal = |b: u32| b >= sb.journal_start && b < sb.journal_start + sb.journal_count;
+    let sane = count > 0
+        && targets.len() == count
+        && targets
+            .iter()
+            .all(|&b| (b as u64) < fs_blocks && !in_journal(b));
+    if !sane {
+        clear(dev, sb)?;
+        return Ok((Recovery::Discarded, seq));
+    }
+
+    let mut images = Vec::with_capacity(count);
+    let mut sum = checksum(FNV_OFFSET, &desc);
+    for i in 0..count {
+        let mut data = vec![0u8; BLOCK_SIZE as usize];
+        crate::fs::io::read_block(dev, BLOCK_SIZE, start + 1 + i as u64, &mut data)?;
+        sum = checksum(sum, &data);
+        images.push(data);
+    }
+    let mut commit = vec![0u8; BLOCK_SIZE as usize];
+    crate::fs::io::read_block(dev, BLOCK_SIZE, start + 1 + count as u64, &mut commit)?;
+    let committed = commit[0..4] == JOURNAL_COMMIT_MAGIC
+        && commit[4..8] == desc[4..8]
+        && commit[8..12] == desc[8..12]
+        && u32::from_le_bytes(commit[12..16].try_into().unwrap()) == sum;
+    if !committed {
+        clear(dev, sb)?;
+        return Ok((Recovery::Discarded, seq));
+    }
+
+    for (block, data) in targets.iter().zip(&images) {
+        crate::fs::io::write_block(dev, BLOCK_SIZE, *block as u64, data)?;
+    }
+    dev.flush()?;
+    clear(dev, sb)?;
+    Ok((
+        Recovery::Replayed {
+            blocks: count as u32,
+        },
+        seq,
+    ))
+}
+
+// Marks the journal empty; the sequence number stays readable
+fn clear(dev: &mut dyn BlockDevice, sb: &Superblock) -> io::Result<()> {
+    dev.write_at(sb.journal_start as u64 * BLOCK_SIZE as u64, &[0u8; 4])
+}
+
+// 32-bit FNV-1a, continued from `state`
+const FNV_OFFSET: u32 = 0x811c_9dc5;
+
+fn checksum(state: u32, data: &[u8]) -> u32 {
+    data.iter()
+        .fold(state, |h, &b| (h ^ b as u32).wrapping_mul(0x0100_0193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::device::{FailingDevice, MemDevice};
+    use crate::fs::error::ElfsError;
+    use crate::fs::io::{compute_layout, read_block, write_block};
+
+    fn image() -> (MemDevice, Superblock) {
+        let sb = compute_layout(4 * 1024 * 1024, BLOCK_SIZE, 16 * 1024);
+        (MemDevice::new(sb.fs_size), sb)
+    }
+
+    // Leaves a transaction in the journal as a crash before the checkpoint would
+    fn crash_after_commit(dev: &mut dyn BlockDevice, sb: &Superblock, target: u32, fill: u8) {
+        let blocks = BTreeMap::from([(target, vec![fill; BLOCK_SIZE as usize])]);
+        commit(dev, sb, 0, &blocks).unwrap();
+        let mut desc = vec![0u8; BLOCK_SIZE as usize];
+        read_block(dev, BLOCK_SIZE, sb.journal_start as u64, &mut desc).unwrap();
+        desc[0..4].copy_from_slice(&JOURNAL_DESC_MAGIC);
+        write_block(dev, BLOCK_SIZE, sb.journal_start as u64, &desc).unwrap();
+        write_block(
+            dev,
+            BLOCK_SIZE,
+            target as u64,
+            &vec![0u8; BLOCK_SIZE as usize],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn committed_transaction_is_replayed() {
+        let (mut dev, sb) = image();
+        let target = sb.block_start;
+        crash_after_commit(&mut dev, &sb, target, 0xAB);
+
+        assert_eq!(
+            recover(&mut dev, &sb).unwrap(),
+            (Recovery::Replayed { blocks: 1 }, 1)
+        );
+        let mut data = vec![0u8; BLOCK_SIZE as usize];
+        read_block(&mut dev, BLOCK_SIZE, target as u64, &mut data).unwrap();
+        assert!(data.iter().all(|&b| b == 0xAB));
+        assert_eq!(recover(&mut dev, &sb).unwrap(), (Recovery::Clean, 1));
+    }
+
+    #[test]
+    fn torn_transaction_is_discarded() {
+        let (mut dev, sb) = image();
+        let target = sb.block_start;
+        crash_after_commit(&mut dev, &sb, target, 0xCD);
+        // Content block changed after the commit block was written
+        let mut data = vec![0u8; BLOCK_SIZE as usize];
+        write_block(&mut dev, BLOCK_SIZE, sb.journal_start as u64 + 1, &data).unwrap();
+
+        assert_eq!(recover(&mut dev, &sb).unwrap(), (Recovery::Discarded, 1));
+        read_block(&mut dev, BLOCK_SIZE, target as u64, &mut data).unwrap();
+        assert!(data.iter().all(|&b| b == 0));
+    }
+
+    // How many of the given blocks hold `fill`
+    fn blocks_with(dev: &mut dyn BlockDevice, blocks: &BTreeMap<u32, Vec<u8>>, fill: u8) -> usize {
+        let mut data = vec![0u8; BLOCK_SIZE as usize];
+        blocks
+            .keys()
+            .filter(|&&b| {
+                read_block(dev, BLOCK_SIZE, b as u64, &mut data).unwrap();
+                data.iter().all(|&x| x == fill)
+            })
+            .count()
+    }
+
+    #[test]
+    fn crash_during_commit_is_all_or_nothing() {
+        let (_, sb) = image();
+        let fill = |n: usize| -> BTreeMap<u32, Vec<u8>> {
+            (0..n as u32)
+                .map(|i| (sb.block_start + i, vec![0xEF; BLOCK_SIZE as usize]))
+                .collect()
+        };
+
+        // More than fits is refused without touching the image
+        let (mut dev, _) = image();
+        let oversized = fill(capacity(&sb) + 1);
+        assert!(matches!(
+            commit(&mut dev, &sb, 0, &oversized).map_err(ElfsError::from),
+            Err(ElfsError::TransactionTooLarge)
+        ));
+        assert_eq!(recover(&mut dev, &sb).unwrap(), (Recovery::Clean, 0));
+        assert_eq!(blocks_with(&mut dev, &oversized, 0), oversized.len());
+
+        // A full transaction cut off after any number of writes comes back whole or not at all:
+        // descriptor, contents and commit block, then the checkpoint and the journal clear
+        let blocks = fill(capacity(&sb));
+        for n in 0..2 * blocks.len() + 3 {
+            let mut dev = FailingDevice::new(sb.fs_size);
+            dev.fail_after(Some(n));
+            assert!(commit(&mut dev, &sb, 0, &blocks).is_err());
+            let mut after_crash = dev.image();
+            let (recovery, _) = recover(&mut after_crash, &sb).unwrap();
+            let written = blocks_with(&mut after_crash, &blocks, 0xEF);
+            if n < blocks.len() + 2 {
+                assert!(!matches!(recovery, Recovery::Replayed { .. }));
+                assert_eq!(written, 0, "crash after {n} writes");
+            } else {
+                assert_eq!(written, blocks.len(), "crash after {n} writes");
+            }
+        }
+    }
+}
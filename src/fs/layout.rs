@@ -1,21 +1,81 @@
-use crate::fs::consts::{DIR_ENTRY_SIZE, DIR_INODE_UNUSED, DIR_NAME_LEN, INODE_SIZE};
+use crate::fs::consts::{
+    DIR_ENTRY_SIZE, DIR_INODE_UNUSED, DIR_NAME_LEN, DIR_NAME_MAX_LEN, DIR_VAR_HEADER_SIZE,
+    FEATURE_DOT_ENTRIES, FEATURE_EXTENTS, FEATURE_JOURNAL, FEATURE_LONG_NAMES, INODE_FLAG_EXTENTS,
+    INODE_SIZE, INODE_SIZE_V1,
+};
+use crate::fs::perm::default_mode;
 use std::fmt;
 
-// Structure that represents File system's Superblock - 40 B
+// Structure that represents File system's Superblock - 64 B
 #[repr(C)]
 pub struct Superblock {
-    pub fs_size: u64,       // 8 B (offset 0..7)
-    pub magic: [u8; 4],     // 4 B (offset 8..11)
-    pub root_inode_id: u32, // 4 B (offset 12..15)
-    pub bitmap_start: u32,  // 4 B
-    pub bitmap_count: u32,  // 4 B
-    pub block_start: u32,   // 4 B
-    pub block_count: u32,   // 4 B
-    pub inode_start: u32,   // 4 B
-    pub inode_count: u32,   // 4 B
+    pub fs_size: u64,            // 8 B (offset 0..7)
+    pub magic: [u8; 4],          // 4 B (offset 8..11)
+    pub root_inode_id: u32,      // 4 B (offset 12..15)
+    pub bitmap_start: u32,       // 4 B
+    pub bitmap_count: u32,       // 4 B
+    pub block_start: u32,        // 4 B
+    pub block_count: u32,        // 4 B
+    pub inode_start: u32,        // 4 B
+    pub inode_count: u32,        // 4 B
+    pub inode_bitmap_start: u32, // 4 B (offset 40..43)
+    pub inode_bitmap_count: u32, // 4 B (offset 44..47) 0 = image predates the inode bitmap
+    pub inode_version: u32,      // 4 B (offset 48..51) 0/1 = legacy 48 B inodes, 2+ = 128 B
+    pub features: u32,           // 4 B (offset 52..55) FEATURE_* flags, 0 on older images
+    pub journal_start: u32,      // 4 B (offset 56..59)
+    pub journal_count: u32,      // 4 B (offset 60..63) 0 = no journal region
 }
 
-// Structure that represents one inode - 48 B
+impl Superblock {
+    // On-disk size of one inode for this image's inode layout version
+    pub fn inode_size(&self) -> usize {
+        if self.inode_version >= 2 {
+            INODE_SIZE
+        } else {
+            INODE_SIZE_V1
+        }
+    }
+
+    // Legacy (v1) inodes have no room for timestamps
+    pub fn has_timestamps(&self) -> bool {
+        self.inode_version >= 2
+    }
+
+    // Mode and ownership are stored (and enforced) since inode version 3
+    pub fn has_permissions(&self) -> bool {
+        self.inode_version >= 3
+    }
+
+    // Directories use variable-length entries instead of fixed 16 B slots
+    pub fn has_long_names(&self) -> bool {
+        self.features & FEATURE_LONG_NAMES != 0
+    }
+
+    // Directories carry "." and ".." entries (parent lookups can read them directly)
+    pub fn has_dot_entries(&self) -> bool {
+        self.features & FEATURE_DOT_ENTRIES != 0
+    }
+
+    // New regular files are mapped by extents instead of block pointers
+    pub fn has_extents(&self) -> bool {
+        self.features & FEATURE_EXTENTS != 0
+    }
+
+    // Metadata updates are committed through the journal region
+    pub fn has_journal(&self) -> bool {
+        self.features & FEATURE_JOURNAL != 0 && self.journal_count > 2
+    }
+}
+
+// Seconds since the UNIX epoch, used for inode timestamps
+pub fn now_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Structure that represents one inode - 128 B (v2+), legacy v1 images store only the first 48 B
 #[repr(C)]
 pub struct Inode {
     pub file_size: u64,           // 8  (offset 0..7)
@@ -25,19 +85,60 @@ pub struct Inode {
     pub double_indirect: u32,     // 4  (offset 36..39) 2nd level of indirection
     pub file_type: u8,            // 1  (offset 40) | 0 - file, 1 - dir, 2 - symlink
     pub link_count: u8,           // 1  (offset 41)
-    pub _reserved: [u8; 6],       // 6  (offset 42..47) remaining padding
+    pub flags: u8,                // 1  (offset 42) INODE_FLAG_* bits
+    pub _reserved: [u8; 5],       // 5  (offset 43..47) remaining padding
+    pub crtime: u64,              // 8  (offset 48..55) v2: creation time
+    pub mtime: u64,               // 8  (offset 56..63) v2: last content modification
+    pub ctime: u64,               // 8  (offset 64..71) v2: last inode change
+    pub atime: u64,               // 8  (offset 72..79) v2: last access
+    pub uid: u32,                 // 4  (offset 80..83) v3: owner
+    pub gid: u32,                 // 4  (offset 84..87) v3: group
+    pub mode: u16,                // 2  (offset 88..89) v3: permission bits (0o777)
+    pub triple_indirect: u32,     // 4  (offset 90..93) 3rd level of indirection (128 B inodes only)
+    pub _reserved_v2: [u8; 34],   // 34 (offset 94..127) room for future fields
 }
 
 impl Inode {
-    pub fn to_bytes(&self) -> [u8; INODE_SIZE] {
-        let mut buf = [0u8; INODE_SIZE];
+    // Fresh in-use inode of the given type, owned by root, default mode, timestamps set to now
+    pub fn new(id: u32, file_type: u8) -> Self {
+        let now = now_timestamp();
+        Self {
+            file_size: 0,
+            id,
+            single_directs: [0; 5],
+            single_indirect: 0,
+            double_indirect: 0,
+            file_type,
+            link_count: 1,
+            flags: 0,
+            _reserved: [0; 5],
+            crtime: now,
+            mtime: now,
+            ctime: now,
+            atime: now,
+            uid: 0,
+            gid: 0,
+            mode: default_mode(file_type),
+            triple_indirect: 0,
+            _reserved_v2: [0; 34],
+        }
+    }
+
+    // Pointer words (direct + single/double indirect) hold an extent tree root instead
+    pub fn is_extent_mapped(&self) -> bool {
+        self.flags & INODE_FLAG_EXTENTS != 0
+    }
+
+    // Serializes into `inode_size` bytes (INODE_SIZE or INODE_SIZE_V1)
+    pub fn to_bytes(&self, inode_size: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; INODE_SIZE];
         // Fixed fields
         buf[0..8].copy_from_slice(&self.file_size.to_le_bytes());
         buf[8..12].copy_from_slice(&self.id.to_le_bytes());
         // Direct blocks
-        for i in 0..5 {
+        for (i, b) in self.single_directs.iter().enumerate() {
             let start = 12 + i * 4;
-            buf[start..start + 4].copy_from_slice(&self.single_directs[i].to_le_bytes());
+            buf[start..start + 4].copy_from_slice(&b.to_le_bytes());
         }
         // Indirect levels
         buf[32..36].copy_from_slice(&self.single_indirect.to_le_bytes());
@@ -45,25 +146,54 @@ impl Inode {
         // Metadata
         buf[40] = self.file_type;
         buf[41] = self.link_count;
-        buf[42..48].copy_from_slice(&self._reserved);
+        buf[42] = self.flags;
+        buf[43..48].copy_from_slice(&self._reserved);
+        // Timestamps (v2)
+        buf[48..56].copy_from_slice(&self.crtime.to_le_bytes());
+        buf[56..64].copy_from_slice(&self.mtime.to_le_bytes());
+        buf[64..72].copy_from_slice(&self.ctime.to_le_bytes());
+        buf[72..80].copy_from_slice(&self.atime.to_le_bytes());
+        // Ownership and mode (v3)
+        buf[80..84].copy_from_slice(&self.uid.to_le_bytes());
+        buf[84..88].copy_from_slice(&self.gid.to_le_bytes());
+        buf[88..90].copy_from_slice(&self.mode.to_le_bytes());
+        buf[90..94].copy_from_slice(&self.triple_indirect.to_le_bytes());
+        buf[94..128].copy_from_slice(&self._reserved_v2);
+        buf.truncate(inode_size);
         buf
     }
 
+    // Deserializes a v2+ (INODE_SIZE) or legacy v1 (INODE_SIZE_V1) inode; missing fields are zero
     pub fn from_bytes(buf: &[u8]) -> Self {
-        debug_assert_eq!(buf.len(), INODE_SIZE);
+        debug_assert!(buf.len() == INODE_SIZE || buf.len() == INODE_SIZE_V1);
+        let mut full = [0u8; INODE_SIZE];
+        full[..buf.len()].copy_from_slice(buf);
+        let buf = &full;
+
         let file_size = u64::from_le_bytes(buf[0..8].try_into().unwrap());
         let id = u32::from_le_bytes(buf[8..12].try_into().unwrap());
         let mut single_directs = [0u32; 5];
-        for i in 0..5 {
+        for (i, b) in single_directs.iter_mut().enumerate() {
             let start = 12 + i * 4;
-            single_directs[i] = u32::from_le_bytes(buf[start..start + 4].try_into().unwrap());
+            *b = u32::from_le_bytes(buf[start..start + 4].try_into().unwrap());
         }
         let single_indirect = u32::from_le_bytes(buf[32..36].try_into().unwrap());
         let double_indirect = u32::from_le_bytes(buf[36..40].try_into().unwrap());
         let file_type = buf[40];
         let link_count = buf[41];
-        let mut _reserved = [0u8; 6];
-        _reserved.copy_from_slice(&buf[42..48]);
+        let flags = buf[42];
+        let mut _reserved = [0u8; 5];
+        _reserved.copy_from_slice(&buf[43..48]);
+        let crtime = u64::from_le_bytes(buf[48..56].try_into().unwrap());
+        let mtime = u64::from_le_bytes(buf[56..64].try_into().unwrap());
+        let ctime = u64::from_le_bytes(buf[64..72].try_into().unwrap());
+        let atime = u64::from_le_bytes(buf[72..80].try_into().unwrap());
+        let uid = u32::from_le_bytes(buf[80..84].try_into().unwrap());
+        let gid = u32::from_le_bytes(buf[84..88].try_into().unwrap());
+        let mode = u16::from_le_bytes(buf[88..90].try_into().unwrap());
+        let triple_indirect = u32::from_le_bytes(buf[90..94].try_into().unwrap());
+        let mut _reserved_v2 = [0u8; 34];
+        _reserved_v2.copy_from_slice(&buf[94..128]);
         Self {
             file_size,
             id,
@@ -72,72 +202,111 @@ impl Inode {
             double_indirect,
             file_type,
             link_count,
+            flags,
             _reserved,
+            crtime,
+            mtime,
+            ctime,
+            atime,
+            uid,
+            gid,
+            mode,
+            triple_indirect,
+            _reserved_v2,
         }
     }
 }
 
-#[repr(C)]
+// One directory entry. On disk it is stored either as a fixed 16 B slot (12 B name + inode id)
+// or, on images with FEATURE_LONG_NAMES, as a variable-length record:
+//   inode_id u32 | rec_len u16 | name_len u8 | pad u8 | name (name_len B) | padding to 4 B
+// rec_len spans the whole record, so a freed record can later be reused by a name that fits.
 pub struct DirectoryEntry {
-    pub name: [u8; DIR_NAME_LEN],
+    pub name: String,
     pub inode_id: u32,
 }
 
 impl DirectoryEntry {
-    pub fn empty() -> Self {
-        Self {
-            name: [0u8; DIR_NAME_LEN],
-            inode_id: DIR_INODE_UNUSED,
-        }
-    }
-
     pub fn is_unused(&self) -> bool {
         self.inode_id == DIR_INODE_UNUSED
     }
 
     pub fn from_name(name: &str, inode_id: u32) -> Result<Self, &'static str> {
-        if name.is_empty() || name.len() > DIR_NAME_LEN {
+        if name.is_empty() || name.len() > DIR_NAME_MAX_LEN {
             return Err("invalid name length");
         }
 
-        let mut buf = [0u8; DIR_NAME_LEN];
-        buf[..name.len()].copy_from_slice(name.as_bytes());
         Ok(Self {
-            name: buf,
+            name: name.to_string(),
             inode_id,
         })
     }
 
     pub fn name_str(&self) -> &str {
-        let end = self
-            .name
-            .iter()
-            .position(|&b| b == 0)
-            .unwrap_or(DIR_NAME_LEN);
-
-        std::str::from_utf8(&self.name[..end]).unwrap_or("<invalid>")
+        &self.name
     }
 
     pub fn mark_unused(&mut self) {
         self.inode_id = DIR_INODE_UNUSED;
     }
 
+    // Fixed 16 B slot (legacy format); names longer than DIR_NAME_LEN are truncated
     pub fn serialize(&self, out: &mut [u8]) {
         debug_assert_eq!(out.len(), DIR_ENTRY_SIZE);
-        out[0..DIR_NAME_LEN].copy_from_slice(&self.name);
+        debug_assert!(self.name.len() <= DIR_NAME_LEN);
+        let n = self.name.len().min(DIR_NAME_LEN);
+        out[0..DIR_NAME_LEN].fill(0);
+        out[..n].copy_from_slice(&self.name.as_bytes()[..n]);
         out[DIR_NAME_LEN..DIR_NAME_LEN + 4].copy_from_slice(&self.inode_id.to_le_bytes());
     }
 
     pub fn deserialize(inp: &[u8]) -> Self {
         debug_assert_eq!(inp.len(), DIR_ENTRY_SIZE);
-        let mut name = [0u8; DIR_NAME_LEN];
-        name.copy_from_slice(&inp[0..DIR_NAME_LEN]);
+        let raw = &inp[0..DIR_NAME_LEN];
+        let end = raw.iter().position(|&b| b == 0).unwrap_or(DIR_NAME_LEN);
+        let name = std::str::from_utf8(&raw[..end])
+            .unwrap_or("<invalid>")
+            .to_string();
         let inode_id = u32::from_le_bytes(inp[DIR_NAME_LEN..DIR_NAME_LEN + 4].try_into().unwrap());
 
         Self { name, inode_id }
     }
-}
 
+    // Size of the smallest variable-length record able to hold `name_len` bytes of name
+    pub fn var_record_len(name_len: usize) -> usize {
+        (DIR_VAR_HEADER_SIZE + name_len).next_multiple_of(4)
+    }
+
+    // Variable-length record occupying `out.len()` bytes (the record's rec_len)
+    pub fn serialize_var(&self, out: &mut [u8]) {
+        debug_assert!(out.len() >= Self::var_record_len(self.name.len()));
+        debug_assert!(out.len() <= u16::MAX as usize);
+        let rec_len = out.len() as u16;
+        out.fill(0);
+        out[0..4].copy_from_slice(&self.inode_id.to_le_bytes());
+        out[4..6].copy_from_slice(&rec_len.to_le_bytes());
+        out[6] = self.name.len() as u8;
+        out[DIR_VAR_HEADER_SIZE..DIR_VAR_HEADER_SIZE + self.name.len()]
+            .copy_from_slice(self.name.as_bytes());
+    }
+
+    // Parses the record at the start of `inp`; returns the entry and its rec_len,
+    // or None if the record header is malformed
+    pub fn deserialize_var(inp: &[u8]) -> Option<(Self, usize)> {
+        if inp.len() < DIR_VAR_HEADER_SIZE {
+            return None;
+        }
+        let inode_id = u32::from_le_bytes(inp[0..4].try_into().unwrap());
+        let rec_len = u16::from_le_bytes(inp[4..6].try_into().unwrap()) as usize;
+        let name_len = inp[6] as usize;
+        if rec_len < Self::var_record_len(name_len) || rec_len > inp.len() {
+            return None;
+        }
+        let raw = &inp[DIR_VAR_HEADER_SIZE..DIR_VAR_HEADER_SIZE + name_len];
+        let name = String::from_utf8_lossy(raw).into_owned();
+        Some((Self { name, inode_id }, rec_len))
+    }
+}
 impl fmt::Debug for DirectoryEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.is_unused() {
@@ -0,0 +1,72 @@
+use crate::fs::layout::Inode;
+
+// Permission bits (per class) as used in Inode::mode
+pub const PERM_READ: u16 = 0o4;
+pub const PERM_WRITE: u16 = 0o2;
+pub const PERM_EXEC: u16 = 0o1;
+
+// Default modes for newly created inodes
+pub const DEFAULT_FILE_MODE: u16 = 0o644;
+pub const DEFAULT_DIR_MODE: u16 = 0o755;
+pub const DEFAULT_SYMLINK_MODE: u16 = 0o777;
+
+// Identity that filesystem operations are performed as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Credentials {
+    pub const ROOT: Credentials = Credentials { uid: 0, gid: 0 };
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Self::ROOT
+    }
+}
+
+pub fn default_mode(file_type: u8) -> u16 {
+    match file_type {
+        1 => DEFAULT_DIR_MODE,
+        2 => DEFAULT_SYMLINK_MODE,
+        _ => DEFAULT_FILE_MODE,
+    }
+}
+
+// Owner/group/other check of `want` (combination of PERM_* bits); root may do anything
+pub fn permits(creds: &Credentials, inode: &Inode, want: u16) -> bool {
+    if creds.is_root() {
+        return true;
+    }
+    let class = if creds.uid == inode.uid {
+        (inode.mode >> 6) & 0o7
+    } else if creds.gid == inode.gid {
+        (inode.mode >> 3) & 0o7
+    } else {
+        inode.mode & 0o7
+    };
+    class & want == want
+}
+
+// ls -l style rendering, e.g. "drwxr-xr-x"
+pub fn format_mode(file_type: u8, mode: u16) -> String {
+    let mut out = String::with_capacity(10);
+    out.push(match file_type {
+        1 => 'd',
+        2 => 'l',
+        _ => '-',
+    });
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & PERM_READ != 0 { 'r' } else { '-' });
+        out.push(if bits & PERM_WRITE != 0 { 'w' } else { '-' });
+        out.push(if bits & PERM_EXEC != 0 { 'x' } else { '-' });
+    }
+    out
+}
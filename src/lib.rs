@@ -0,0 +1,45 @@
+//! ELFS filesystem emulator as a library.
+//!
+//! An image is opened with [`FileSystem::open`] or created with [`FileSystem::format`] on a
+//! [`BlockDevice`]: a host file ([`FileDevice`]), memory ([`MemDevice`]) or your own backend.
+//! Everything goes through paths: whole-file calls, [`ElfsFile`] handles, recursive tree
+//! operations and tar import/export. Failures are reported as [`ElfsError`], which converts
+//! into [`std::io::Error`] where that is more convenient.
+//!
+//! ```
+//! use elfs_emu::{FileSystem, FileKind, MemDevice};
+//!
+//! let size = 4 * 1024 * 1024;
+//! let mut fs = FileSystem::format(MemDevice::new(size), size)?;
+//! fs.create_dir("/notes")?;
+//! fs.write_file("/notes/todo.txt", b"buy milk")?;
+//! fs.symlink("/notes/todo.txt", "/todo")?;
+//!
+//! assert_eq!(fs.read_file("/todo")?, b"buy milk");
+//! assert_eq!(fs.lstat("/todo")?.kind, FileKind::Symlink);
+//! assert_eq!(fs.list_dir("/notes")?[0].name, "todo.txt");
+//! fs.flush()?;
+//! # Ok::<(), elfs_emu::ElfsError>(())
+//! ```
+
+pub mod fs;
+
+// The interactive shell behind the elfs-emu binary; not part of the library API
+#[doc(hidden)]
+pub mod commands;
+#[doc(hidden)]
+pub mod context;
+#[doc(hidden)]
+pub mod utils;
+
+#[cfg(test)]
+mod testing;
+
+pub use fs::device::{BlockDevice, FileDevice, MemDevice};
+pub use fs::error::ElfsError;
+pub use fs::filesystem::{
+    CheckReport, DirEntry, ElfsFile, FileKind, FileSystem, Metadata, OpenOptions, Problem,
+    TreeReport,
+};
+pub use fs::journal::Recovery;
+pub use fs::perm::Credentials;
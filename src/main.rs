@@ -1,14 +1,11 @@
-mod commands;
-mod context;
-mod fs;
-mod utils;
-
-use context::Context;
-use utils::{cli, tui};
+use elfs_emu::context::Context;
+use elfs_emu::utils::{cli, tui};
 
 fn main() -> std::io::Result<()> {
     let mut context = Context::new();
-    cli::handle_prog_args(&mut context);
-    tui::handle_app_loop(&mut context);
+    match cli::handle_prog_args(&mut context) {
+        Some(batch) => std::process::exit(batch.run(&mut context).into()),
+        None => tui::handle_app_loop(&mut context),
+    }
     Ok(())
 }
@@ -0,0 +1,74 @@
+// Fixtures shared by the unit tests.
+
+use crate::commands::{Output, Registry, Status};
+use crate::context::Context;
+use crate::fs::device::MemDevice;
+use crate::fs::filesystem::FileSystem;
+use crate::fs::layout::Inode;
+
+// Size of the images below: enough for a journal and a few MiB of data
+pub(crate) const IMAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+// Freshly formatted in-memory image
+pub(crate) fn image() -> FileSystem {
+    image_of(IMAGE_SIZE)
+}
+
+pub(crate) fn image_of(size: u64) -> FileSystem {
+    FileSystem::format(MemDevice::new(size), size).unwrap()
+}
+
+// Pointer-mapped regular file linked in at `path`, as on images without extents
+pub(crate) fn pointer_file(fs: &mut FileSystem, path: &str) -> Inode {
+    let (parent_id, name) = fs.resolve_parent_and_name(path).unwrap();
+    let id = fs.alloc_inode().unwrap().unwrap();
+    let inode = Inode::new(id, 0);
+    fs.write_inode(id, &inode).unwrap();
+    let mut parent = fs.read_inode(parent_id).unwrap();
+    fs.dir_add_entry(&mut parent, &name, id).unwrap();
+    inode
+}
+
+// Command-level state: a context with an image installed, the registry and what the commands
+// printed to stdout and stderr
+pub(crate) struct Shell {
+    pub ctx: Context,
+    pub registry: Registry,
+    pub out: Vec<u8>,
+    pub err: Vec<u8>,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self::with(image())
+    }
+
+    pub fn with(fs: FileSystem) -> Self {
+        let mut ctx = Context::new();
+        ctx.install_fs(fs);
+        Self {
+            ctx,
+            registry: Registry::new(),
+            out: Vec::new(),
+            err: Vec::new(),
+        }
+    }
+
+    // Runs one command line as the shell would
+    pub fn run(&mut self, line: &str) -> Status {
+        let mut output = Output::new(&mut self.out, &mut self.err);
+        self.registry.execute(line, &mut self.ctx, &mut output)
+    }
+
+    pub fn fs(&mut self) -> &mut FileSystem {
+        self.ctx.fs_mut().unwrap()
+    }
+
+    pub fn out(&self) -> String {
+        String::from_utf8(self.out.clone()).unwrap()
+    }
+
+    pub fn err(&self) -> String {
+        String::from_utf8(self.err.clone()).unwrap()
+    }
+}
@@ -1,4 +1,6 @@
 pub mod cli;
 pub mod exit_codes;
 pub mod file_man;
+pub mod script;
+pub mod time;
 pub mod tui;
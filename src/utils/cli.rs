@@ -1,25 +1,64 @@
 use crate::context::Context;
 use crate::utils::file_man::handle_fs;
+use crate::utils::script::{Batch, Source};
+use std::io::IsTerminal;
+use std::path::PathBuf;
 use std::{env, process};
 
 fn handle_help(exit: bool) {
-    eprintln!("Usage: elfs-emu [--help] <filesystem.elfs>");
+    eprintln!(
+        "Usage: elfs-emu [--help] [--verbose] [-c <commands>] [--script <file>] [--fail-fast] <filesystem.elfs>"
+    );
+    eprintln!("  -c <commands>    run commands separated by ';' (no quoting) and exit");
+    eprintln!("  --script <file>  run commands from a host file, one per line, and exit");
+    eprintln!("  --fail-fast      stop at the first failed command with a nonzero exit code");
+    eprintln!("Commands piped to stdin are run the same way, without the prompt.");
     if exit {
         process::exit(1);
     }
 }
 
-pub fn handle_prog_args(context: &mut Context) {
+// Opens the image and returns the batch to run, or None for the interactive shell
+pub fn handle_prog_args(context: &mut Context) -> Option<Batch> {
     let mut it = env::args().skip(1).peekable();
 
     if it.peek().is_none() {
         handle_help(true);
     }
 
+    let mut batch = Batch {
+        sources: Vec::new(),
+        fail_fast: false,
+    };
     while let Some(arg) = it.next() {
         match arg.as_str() {
             "--help" => handle_help(true),
+            // Error messages get the underlying cause appended
+            "-v" | "--verbose" => crate::commands::set_verbose(true),
+            "-c" | "--script" => {
+                let Some(value) = it.next() else {
+                    eprintln!("{arg} needs a value");
+                    handle_help(true);
+                    return None;
+                };
+                batch.sources.push(if arg == "-c" {
+                    Source::Inline(value)
+                } else {
+                    Source::File(PathBuf::from(value))
+                });
+            }
+            "--fail-fast" => batch.fail_fast = true,
             fs_path => handle_fs(fs_path, context),
         }
     }
+
+    // Without -c/--script a pipe on stdin is a script as well
+    if batch.sources.is_empty() && !std::io::stdin().is_terminal() {
+        batch.sources.push(Source::Stdin);
+    }
+    if batch.sources.is_empty() {
+        None
+    } else {
+        Some(batch)
+    }
 }
@@ -1,4 +1,5 @@
 use crate::context::Context;
+use crate::fs::journal::Recovery;
 use std::path::Path;
 
 pub fn handle_fs(fs_path: &str, context: &mut Context) {
@@ -12,13 +13,13 @@ pub fn handle_fs(fs_path: &str, context: &mut Context) {
         return;
     }
 
-    if let Some(parent) = path.parent() {
-        if !parent.as_os_str().is_empty() && !parent.exists() {
-            if let Err(e) = std::fs::create_dir_all(parent) {
-                eprintln!("Cannot create directory '{}': {}", parent.display(), e);
-                return;
-            }
-        }
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+        && let Err(e) = std::fs::create_dir_all(parent)
+    {
+        eprintln!("Cannot create directory '{}': {}", parent.display(), e);
+        return;
     }
 
     let existed = path.exists();
@@ -30,6 +31,18 @@ pub fn handle_fs(fs_path: &str, context: &mut Context) {
             } else {
                 eprintln!("Creted new file: {}", fs_path);
             }
+            match context.fs_mut().map(|fs| fs.recovery()) {
+                Ok(Recovery::Replayed { blocks }) => {
+                    eprintln!(
+                        "Journal: replayed {} blocks of an interrupted command",
+                        blocks
+                    )
+                }
+                Ok(Recovery::Discarded) => {
+                    eprintln!("Journal: discarded an incomplete transaction")
+                }
+                _ => {}
+            }
         }
         Err(e) => eprintln!("Failed to open/create '{}': {}", fs_path, e),
     }
@@ -0,0 +1,131 @@
+// Non-interactive mode: commands given with -c, read from --script files or piped to stdin.
+// Runs without the greeting and prompt; the process exit code tells whether it went through.
+
+use crate::commands::{Output, Registry};
+use crate::context::Context;
+use crate::utils::exit_codes::ExitCode;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+
+pub enum Source {
+    // -c "cmd1; cmd2": commands separated by ';' or newlines. There is no quoting, every ';'
+    // ends a command; arguments containing one need a --script file.
+    Inline(String),
+    // --script <file>: one command per line, like `load`
+    File(PathBuf),
+    // Commands piped in
+    Stdin,
+}
+
+pub struct Batch {
+    pub sources: Vec<Source>,
+    // Stop at the first command that fails. Without it the rest still runs, but a failure
+    // anywhere makes the exit code ExitCode::Error all the same.
+    pub fail_fast: bool,
+}
+
+impl Batch {
+    pub fn run(&self, context: &mut Context) -> ExitCode {
+        let registry = Registry::new();
+        let (mut stdout, mut stderr) = (io::stdout(), io::stderr());
+        let code = self.run_sources(
+            &registry,
+            context,
+            &mut Output::new(&mut stdout, &mut stderr),
+        );
+        // Same as `exit`: write everything back before the process ends
+        context.close_fs();
+        code
+    }
+
+    fn run_sources(
+        &self,
+        registry: &Registry,
+        context: &mut Context,
+        output: &mut Output,
+    ) -> ExitCode {
+        let mut failed = false;
+        for source in &self.sources {
+            let lines: Box<dyn Iterator<Item = io::Result<String>>> = match source {
+                Source::Inline(commands) => Box::new(
+                    commands
+                        .split([';', '\n'])
+                        .map(|c| Ok(c.to_string()))
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                ),
+                Source::File(path) => match File::open(path) {
+                    Ok(f) => Box::new(BufReader::new(f).lines()),
+                    Err(e) => {
+                        let _ =
+                            writeln!(output.err, "Cannot open script '{}': {}", path.display(), e);
+                        return ExitCode::Error;
+                    }
+                },
+                Source::Stdin => Box::new(io::stdin().lock().lines()),
+            };
+
+            for line in lines {
+                let line = match line {
+                    Ok(l) => l,
+                    Err(e) => {
+                        let _ = writeln!(output.err, "Cannot read commands: {}", e);
+                        return ExitCode::Error;
+                    }
+                };
+                if registry.execute(&line, context, output).is_err() {
+                    if self.fail_fast {
+                        return ExitCode::Error;
+                    }
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            ExitCode::Error
+        } else {
+            ExitCode::Success
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Shell;
+
+    fn run(commands: &str, fail_fast: bool) -> (ExitCode, Context, String) {
+        let mut shell = Shell::new();
+        let batch = Batch {
+            sources: vec![Source::Inline(commands.to_string())],
+            fail_fast,
+        };
+        let code = batch.run_sources(
+            &shell.registry,
+            &mut shell.ctx,
+            &mut Output::new(&mut shell.out, &mut shell.err),
+        );
+        let err = shell.err();
+        (code, shell.ctx, err)
+    }
+
+    #[test]
+    fn failures_set_the_exit_code() {
+        let (code, _, err) = run("mkdir /a; mkdir /a/b\nmkdir /a", false);
+        assert!(matches!(code, ExitCode::Error));
+        assert_eq!(err, "OK\nOK\nEXIST\n");
+
+        // Without --fail-fast the commands after a failure still run
+        let (code, mut context, _) = run("mkdir /a; cd /missing; mkdir /a/b", false);
+        assert!(matches!(code, ExitCode::Error));
+        assert!(context.fs_mut().unwrap().resolve_path("/a/b").is_ok());
+
+        let (code, mut context, _) = run("mkdir /a; cd /missing; mkdir /a/b", true);
+        assert!(matches!(code, ExitCode::Error));
+        assert!(context.fs_mut().unwrap().resolve_path("/a/b").is_err());
+
+        let (code, _, _) = run("mkdir /a;; pwd", true);
+        assert!(matches!(code, ExitCode::Success));
+    }
+}
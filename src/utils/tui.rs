@@ -1,4 +1,4 @@
-use crate::commands::Registry;
+use crate::commands::{Output, Registry};
 use crate::context::Context;
 use colored::Colorize;
 use std::io;
@@ -23,6 +23,7 @@ fn write_prefix(context: &mut Context) {
 pub fn handle_app_loop(context: &mut Context) {
     let stdin = io::stdin();
     let registry = Registry::new();
+    let (mut stdout, mut stderr) = (io::stdout(), io::stderr());
 
     write_greet();
     loop {
@@ -30,19 +31,18 @@ pub fn handle_app_loop(context: &mut Context) {
         write_prefix(context);
         let mut user_input = String::new();
 
-        // user input
-        let _ = stdin.read_line(&mut user_input);
-
-        // preparing user input to be formated into 'command' and 'args'
-        let trimmed = user_input.trim();
-        if trimmed.is_empty() {
-            continue;
+        // user input; end of input (Ctrl-D) or a read error ends the session like `exit`
+        match stdin.read_line(&mut user_input) {
+            Ok(0) | Err(_) => {
+                eprintln!();
+                context.close_fs();
+                return;
+            }
+            Ok(_) => {}
         }
-        let mut it = trimmed.split_whitespace();
-        let command = it.next().unwrap();
-        let args: Vec<&str> = it.collect();
 
-        // command dispatch
-        registry.dispatch(command, &args, context);
+        // command dispatch; a failure has been printed already
+        let mut output = Output::new(&mut stdout, &mut stderr);
+        let _ = registry.execute(&user_input, context, &mut output);
     }
 }